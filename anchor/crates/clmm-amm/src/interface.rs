@@ -0,0 +1,111 @@
+//! The aggregator-side AMM interface: the methods and shapes of `jupiter-amm-interface`'s
+//! `Amm` trait, declared here until that crate can be added to the workspace. An aggregator
+//! integration maps these types onto its own one for one.
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+use anchor_lang::prelude::{AccountMeta, Pubkey};
+use anyhow::Result;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Account {
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub owner: Pubkey,
+    pub executable: bool,
+    pub rent_epoch: u64,
+}
+
+pub type AccountMap = HashMap<Pubkey, Account>;
+
+#[derive(Clone, Debug)]
+pub struct KeyedAccount {
+    pub key: Pubkey,
+    pub account: Account,
+}
+
+/// Cluster state shared by the aggregator with every adapter and kept current by it.
+#[derive(Clone, Debug, Default)]
+pub struct ClockRef {
+    pub epoch: Arc<AtomicU64>,
+    pub unix_timestamp: Arc<AtomicU64>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AmmContext {
+    pub clock_ref: ClockRef,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SwapMode {
+    #[default]
+    ExactIn,
+    ExactOut,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuoteParams {
+    pub amount: u64,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub swap_mode: SwapMode,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quote {
+    pub in_amount: u64,
+    pub out_amount: u64,
+    pub fee_amount: u64,
+    pub fee_mint: Pubkey,
+    // Pool fee as a fraction of the input, e.g. 0.003 for 0.3%.
+    pub fee_pct: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapParams {
+    pub source_mint: Pubkey,
+    pub destination_mint: Pubkey,
+    pub source_token_account: Pubkey,
+    pub destination_token_account: Pubkey,
+    pub token_transfer_authority: Pubkey,
+    pub in_amount: u64,
+    pub out_amount: u64,
+}
+
+/// Venue-specific swap arguments the aggregator encodes into its route instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Swap {
+    Clmm { swap_token_0_for_1: bool },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwapAndAccountMetas {
+    pub swap: Swap,
+    pub account_metas: Vec<AccountMeta>,
+}
+
+pub trait Amm {
+    fn from_keyed_account(keyed_account: &KeyedAccount, amm_context: &AmmContext) -> Result<Self>
+    where
+        Self: Sized;
+    fn label(&self) -> String;
+    fn program_id(&self) -> Pubkey;
+    fn key(&self) -> Pubkey;
+    fn get_reserve_mints(&self) -> Vec<Pubkey>;
+    /// Accounts the aggregator must fetch and pass to [`Amm::update`] before quoting.
+    fn get_accounts_to_update(&self) -> Vec<Pubkey>;
+    fn update(&mut self, account_map: &AccountMap) -> Result<()>;
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote>;
+    fn get_swap_and_account_metas(&self, swap_params: &SwapParams) -> Result<SwapAndAccountMetas>;
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync>;
+
+    fn supports_exact_out(&self) -> bool {
+        false
+    }
+
+    fn is_active(&self) -> bool {
+        true
+    }
+}
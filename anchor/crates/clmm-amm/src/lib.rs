@@ -0,0 +1,196 @@
+//! Aggregator adapter for `clmm` pools. Quotes come from `clmm-quote`, which reproduces the
+//! program's swap math exactly, and swap accounts from the `clmm-sdk` builders.
+
+pub mod interface;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::{anyhow, bail, Context, Result};
+use clmm_quote::{PoolSnapshot, PoolTransferFees, TransferFee};
+use clmm_sdk::instructions::{self, SwapParams as ClmmSwapParams};
+use clmm_sdk::pda::find_global_config_address;
+use clmm_sdk::{state, GlobalConfig, PoolKeys};
+
+use crate::interface::{
+    Account, AccountMap, Amm, AmmContext, KeyedAccount, Quote, QuoteParams, Swap, SwapAndAccountMetas, SwapMode,
+    SwapParams,
+};
+
+#[derive(Clone, Debug)]
+pub struct ClmmAmm {
+    pool: PoolSnapshot,
+    keys: PoolKeys,
+    // Token programs and transfer fees come from the mints, which are only known after the
+    // first update. The raw accounts are kept so each quote uses the current epoch's fee.
+    mints: Option<[Account; 2]>,
+    // The global config's PAUSE_SWAP flag halts every pool at once.
+    swaps_paused: bool,
+    // Swaps of transfer-hook mints need the hook's extra accounts, which the adapter does not
+    // resolve, so such pools are reported inactive rather than routed into failing swaps.
+    has_transfer_hook: bool,
+    epoch: Arc<AtomicU64>,
+}
+
+impl ClmmAmm {
+    fn load_pool(key: Pubkey, data: &[u8]) -> Result<(PoolSnapshot, PoolKeys)> {
+        let pool = state::decode_pool(data).with_context(|| format!("decoding pool {key}"))?;
+        let snapshot = PoolSnapshot::decode(data)?;
+        Ok((snapshot, PoolKeys::from_pool(key, &pool, Pubkey::default(), Pubkey::default())))
+    }
+
+    fn transfer_fees(&self) -> Result<PoolTransferFees> {
+        let [mint_0, mint_1] = self
+            .mints
+            .as_ref()
+            .ok_or_else(|| anyhow!("pool {} has not been updated with its mints", self.keys.pool))?;
+        let epoch = self.epoch.load(Ordering::Relaxed);
+        let transfer_fee = |mint: &Account| -> Result<Option<TransferFee>> {
+            let fee = state::decode_epoch_transfer_fee(&mint.data, epoch)?;
+            Ok(fee.map(|fee| TransferFee {
+                transfer_fee_basis_points: u16::from(fee.transfer_fee_basis_points),
+                maximum_fee: u64::from(fee.maximum_fee),
+            }))
+        };
+        Ok(PoolTransferFees {
+            token_0: transfer_fee(mint_0)?,
+            token_1: transfer_fee(mint_1)?,
+        })
+    }
+
+    fn direction(&self, input_mint: &Pubkey, output_mint: &Pubkey) -> Result<bool> {
+        match (*input_mint, *output_mint) {
+            (input, output) if input == self.keys.token_mint_0 && output == self.keys.token_mint_1 => Ok(true),
+            (input, output) if input == self.keys.token_mint_1 && output == self.keys.token_mint_0 => Ok(false),
+            _ => bail!("pool {} does not trade {input_mint} for {output_mint}", self.keys.pool),
+        }
+    }
+}
+
+impl Amm for ClmmAmm {
+    fn from_keyed_account(keyed_account: &KeyedAccount, amm_context: &AmmContext) -> Result<Self> {
+        let (pool, keys) = Self::load_pool(keyed_account.key, &keyed_account.account.data)?;
+        Ok(Self {
+            pool,
+            keys,
+            mints: None,
+            swaps_paused: false,
+            has_transfer_hook: false,
+            epoch: amm_context.clock_ref.epoch.clone(),
+        })
+    }
+
+    fn label(&self) -> String {
+        "clmm".to_string()
+    }
+
+    fn program_id(&self) -> Pubkey {
+        clmm_sdk::ID
+    }
+
+    fn key(&self) -> Pubkey {
+        self.keys.pool
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        vec![self.keys.token_mint_0, self.keys.token_mint_1]
+    }
+
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        vec![
+            self.keys.pool,
+            self.keys.token_mint_0,
+            self.keys.token_mint_1,
+            find_global_config_address().0,
+        ]
+    }
+
+    fn update(&mut self, account_map: &AccountMap) -> Result<()> {
+        let account = |key: &Pubkey| account_map.get(key).ok_or_else(|| anyhow!("missing account {key}"));
+
+        let (pool, keys) = Self::load_pool(self.keys.pool, &account(&self.keys.pool)?.data)?;
+        let mint_0 = account(&keys.token_mint_0)?.clone();
+        let mint_1 = account(&keys.token_mint_1)?.clone();
+        let global_config_key = find_global_config_address().0;
+        let global_config = state::decode_global_config(&account(&global_config_key)?.data)
+            .with_context(|| format!("decoding global config {global_config_key}"))?;
+        self.keys = PoolKeys {
+            token_program_0: mint_0.owner,
+            token_program_1: mint_1.owner,
+            ..keys
+        };
+        self.pool = pool;
+        self.has_transfer_hook = state::decode_transfer_hook_program_id(&mint_0.data)?.is_some()
+            || state::decode_transfer_hook_program_id(&mint_1.data)?.is_some();
+        self.mints = Some([mint_0, mint_1]);
+        self.swaps_paused = global_config.is_paused(GlobalConfig::PAUSE_SWAP);
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        if quote_params.swap_mode == SwapMode::ExactOut {
+            bail!("clmm pools only quote exact-in swaps");
+        }
+        if !self.pool.is_active {
+            bail!("pool {} is withdraw-only", self.keys.pool);
+        }
+        if self.swaps_paused {
+            bail!("swaps are paused");
+        }
+        if self.has_transfer_hook {
+            bail!("pool {} has a transfer-hook mint", self.keys.pool);
+        }
+        let swap_token_0_for_1 = self.direction(&quote_params.input_mint, &quote_params.output_mint)?;
+        let quote = clmm_quote::quote_swap(&self.pool, &self.transfer_fees()?, quote_params.amount, swap_token_0_for_1)?;
+
+        Ok(Quote {
+            in_amount: quote.amount_in,
+            out_amount: quote.amount_out,
+            fee_amount: quote.fee_amount,
+            fee_mint: quote_params.input_mint,
+            fee_pct: self.pool.fee_rate as f64 / clmm_quote::math::FEE_RATE_DENOMINATOR as f64,
+        })
+    }
+
+    fn get_swap_and_account_metas(&self, swap_params: &SwapParams) -> Result<SwapAndAccountMetas> {
+        if self.mints.is_none() {
+            bail!("pool {} has not been updated with its mints", self.keys.pool);
+        }
+        if self.has_transfer_hook {
+            bail!("pool {} has a transfer-hook mint", self.keys.pool);
+        }
+        let swap_token_0_for_1 = self.direction(&swap_params.source_mint, &swap_params.destination_mint)?;
+        let (user_token_0, user_token_1) = if swap_token_0_for_1 {
+            (swap_params.source_token_account, swap_params.destination_token_account)
+        } else {
+            (swap_params.destination_token_account, swap_params.source_token_account)
+        };
+        let instruction = instructions::swap(
+            swap_params.token_transfer_authority,
+            &self.keys,
+            Some(user_token_0),
+            Some(user_token_1),
+            ClmmSwapParams {
+                amount_in: swap_params.in_amount,
+                swap_token_0_for_1,
+                amount_out_minimum: swap_params.out_amount,
+                deadline: None,
+                expected_sqrt_price: None,
+            },
+        );
+
+        Ok(SwapAndAccountMetas {
+            swap: Swap::Clmm { swap_token_0_for_1 },
+            account_metas: instruction.accounts,
+        })
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn is_active(&self) -> bool {
+        self.pool.is_active && !self.swaps_paused && !self.has_transfer_hook
+    }
+}
@@ -0,0 +1,322 @@
+//! Runs the adapter against getAccountInfo-format snapshots of a pool, its mints and the global
+//! config: native SOL as token 0 and a Token-2022 mint with an epoch-scheduled transfer fee as
+//! token 1.
+
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AccountSerialize;
+use anchor_spl::token_2022::spl_token_2022::extension::transfer_hook::TransferHook;
+use anchor_spl::token_2022::spl_token_2022::extension::{
+    BaseStateWithExtensionsMut, ExtensionType, StateWithExtensionsMut,
+};
+use anchor_spl::token_2022::spl_token_2022::state::Mint;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clmm_amm::interface::{
+    Account, AccountMap, Amm, AmmContext, KeyedAccount, QuoteParams, Swap, SwapMode, SwapParams,
+};
+use clmm_amm::ClmmAmm;
+use serde_json::Value;
+
+// The mint's older fee (50 bps, capped at 10_000) applies before this epoch, 100 bps after.
+const FEE_CHANGE_EPOCH: u64 = 800;
+
+fn load_fixture(name: &str) -> KeyedAccount {
+    let path = format!("{}/tests/fixtures/{name}.json", env!("CARGO_MANIFEST_DIR"));
+    let fixture: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let account = &fixture["account"];
+    KeyedAccount {
+        key: Pubkey::from_str(fixture["pubkey"].as_str().unwrap()).unwrap(),
+        account: Account {
+            lamports: account["lamports"].as_u64().unwrap(),
+            data: BASE64_STANDARD.decode(account["data"][0].as_str().unwrap()).unwrap(),
+            owner: Pubkey::from_str(account["owner"].as_str().unwrap()).unwrap(),
+            executable: account["executable"].as_bool().unwrap(),
+            rent_epoch: account["rentEpoch"].as_u64().unwrap(),
+        },
+    }
+}
+
+// Edits an account through the SDK decoder, so tests change fields rather than byte offsets.
+fn edit_account<T: AccountSerialize>(
+    account: &mut Account,
+    decode: impl Fn(&[u8]) -> anchor_lang::Result<T>,
+    edit: impl FnOnce(&mut T),
+) {
+    let mut state = decode(&account.data).unwrap();
+    edit(&mut state);
+    let mut data = Vec::new();
+    state.try_serialize(&mut data).unwrap();
+    account.data[..data.len()].copy_from_slice(&data);
+}
+
+// A Token-2022 mint with the transfer-hook extension, invoking `hook_program_id` if set.
+fn transfer_hook_mint(hook_program_id: Option<Pubkey>) -> Vec<u8> {
+    let len = ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::TransferHook]).unwrap();
+    let mut data = vec![0; len];
+    let mut mint = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+    mint.init_extension::<TransferHook>(true).unwrap().program_id = hook_program_id.try_into().unwrap();
+    mint.base = Mint {
+        decimals: 6,
+        is_initialized: true,
+        ..Default::default()
+    };
+    mint.pack_base();
+    mint.init_account_type().unwrap();
+    data
+}
+
+struct Fixture {
+    pool: KeyedAccount,
+    mint_0: KeyedAccount,
+    mint_1: KeyedAccount,
+    global_config: KeyedAccount,
+    context: AmmContext,
+}
+
+impl Fixture {
+    fn load(epoch: u64) -> Self {
+        let context = AmmContext::default();
+        context.clock_ref.epoch.store(epoch, Ordering::Relaxed);
+        Self {
+            pool: load_fixture("pool"),
+            mint_0: load_fixture("mint_0"),
+            mint_1: load_fixture("mint_1"),
+            global_config: load_fixture("global_config"),
+            context,
+        }
+    }
+
+    fn account_map(&self) -> AccountMap {
+        [&self.pool, &self.mint_0, &self.mint_1, &self.global_config]
+            .into_iter()
+            .map(|keyed| (keyed.key, keyed.account.clone()))
+            .collect()
+    }
+
+    fn amm(&self) -> ClmmAmm {
+        let mut amm = ClmmAmm::from_keyed_account(&self.pool, &self.context).unwrap();
+        amm.update(&self.account_map()).unwrap();
+        amm
+    }
+
+    fn quote_params(&self, amount: u64, swap_token_0_for_1: bool) -> QuoteParams {
+        let (input_mint, output_mint) = if swap_token_0_for_1 {
+            (self.mint_0.key, self.mint_1.key)
+        } else {
+            (self.mint_1.key, self.mint_0.key)
+        };
+        QuoteParams {
+            amount,
+            input_mint,
+            output_mint,
+            swap_mode: SwapMode::ExactIn,
+        }
+    }
+}
+
+#[test]
+fn fixture_is_a_program_pool() {
+    let fixture = Fixture::load(0);
+    assert_eq!(fixture.pool.account.owner, clmm_sdk::ID);
+    assert_eq!(fixture.pool.account.data.len(), clmm_sdk::Pool::SPACE);
+
+    let amm = ClmmAmm::from_keyed_account(&fixture.pool, &fixture.context).unwrap();
+    assert_eq!(amm.key(), fixture.pool.key);
+    assert_eq!(amm.program_id(), clmm_sdk::ID);
+    assert_eq!(amm.get_reserve_mints(), vec![fixture.mint_0.key, fixture.mint_1.key]);
+    assert_eq!(
+        amm.get_accounts_to_update(),
+        vec![
+            fixture.pool.key,
+            fixture.mint_0.key,
+            fixture.mint_1.key,
+            fixture.global_config.key
+        ]
+    );
+    assert!(amm.is_active());
+    assert!(!amm.supports_exact_out());
+}
+
+#[test]
+fn quotes_require_an_update() {
+    let fixture = Fixture::load(0);
+    let mut amm = ClmmAmm::from_keyed_account(&fixture.pool, &fixture.context).unwrap();
+    assert!(amm.quote(&fixture.quote_params(1_000_000, true)).is_err());
+
+    for key in [fixture.mint_1.key, fixture.global_config.key] {
+        let mut partial = fixture.account_map();
+        partial.remove(&key);
+        assert!(amm.update(&partial).is_err());
+    }
+}
+
+#[test]
+fn quotes_token_0_for_1() {
+    let fixture = Fixture::load(FEE_CHANGE_EPOCH - 1);
+    let quote = fixture.amm().quote(&fixture.quote_params(1_000_000, true)).unwrap();
+
+    // SOL has no transfer fee; the pool grosses up the Token-2022 output so the quoted
+    // amount is what arrives.
+    assert_eq!(quote.in_amount, 1_000_000);
+    assert_eq!(quote.fee_amount, 3_000);
+    assert_eq!(quote.out_amount, 997_000);
+    assert_eq!(quote.fee_mint, fixture.mint_0.key);
+    assert_eq!(quote.fee_pct, 0.003);
+}
+
+#[test]
+fn quotes_token_1_for_0_with_the_epoch_transfer_fee() {
+    let fixture = Fixture::load(FEE_CHANGE_EPOCH - 1);
+    let amm = fixture.amm();
+
+    // 50 bps of 1_000_000 is withheld by the mint before the pool prices the input.
+    let quote = amm.quote(&fixture.quote_params(1_000_000, false)).unwrap();
+    assert_eq!((quote.in_amount, quote.fee_amount, quote.out_amount), (1_000_000, 2_985, 992_015));
+
+    // The older fee is capped at 10_000.
+    let quote = amm.quote(&fixture.quote_params(10_000_000, false)).unwrap();
+    assert_eq!((quote.in_amount, quote.fee_amount, quote.out_amount), (10_000_000, 29_970, 9_960_030));
+
+    // The adapter reads the epoch from the shared clock on every quote.
+    fixture.context.clock_ref.epoch.store(FEE_CHANGE_EPOCH, Ordering::Relaxed);
+    let quote = amm.quote(&fixture.quote_params(1_000_000, false)).unwrap();
+    assert_eq!((quote.in_amount, quote.fee_amount, quote.out_amount), (1_000_000, 2_970, 987_030));
+}
+
+#[test]
+fn rejects_unsupported_quotes() {
+    let fixture = Fixture::load(0);
+    let amm = fixture.amm();
+
+    let exact_out = QuoteParams {
+        swap_mode: SwapMode::ExactOut,
+        ..fixture.quote_params(1_000_000, true)
+    };
+    assert!(amm.quote(&exact_out).is_err());
+
+    let foreign_mint = QuoteParams {
+        output_mint: Pubkey::new_unique(),
+        ..fixture.quote_params(1_000_000, true)
+    };
+    assert!(amm.quote(&foreign_mint).is_err());
+    assert!(amm.quote(&fixture.quote_params(0, true)).is_err());
+}
+
+#[test]
+fn withdraw_only_pools_are_inactive() {
+    let mut fixture = Fixture::load(0);
+    edit_account(&mut fixture.pool.account, clmm_sdk::state::decode_pool, |pool| {
+        assert_eq!(pool.status, clmm_sdk::PoolStatus::Active);
+        pool.status = clmm_sdk::PoolStatus::WithdrawOnly;
+    });
+
+    let amm = fixture.amm();
+    assert!(!amm.is_active());
+    assert!(amm.quote(&fixture.quote_params(1_000_000, true)).is_err());
+}
+
+#[test]
+fn paused_swaps_make_every_pool_inactive() {
+    let mut fixture = Fixture::load(0);
+    let decode_global_config = clmm_sdk::state::decode_global_config;
+    edit_account(&mut fixture.global_config.account, decode_global_config, |config| {
+        assert_eq!(config.pause_flags, 0);
+        config.pause_flags = clmm_sdk::GlobalConfig::PAUSE_SWAP;
+    });
+
+    let amm = fixture.amm();
+    assert!(!amm.is_active());
+    assert!(amm.quote(&fixture.quote_params(1_000_000, true)).is_err());
+
+    // Other pause flags leave swaps alone.
+    edit_account(&mut fixture.global_config.account, decode_global_config, |config| {
+        config.pause_flags = clmm_sdk::GlobalConfig::PAUSE_ALL & !clmm_sdk::GlobalConfig::PAUSE_SWAP;
+    });
+    let amm = fixture.amm();
+    assert!(amm.is_active());
+    assert!(amm.quote(&fixture.quote_params(1_000_000, true)).is_ok());
+}
+
+#[test]
+fn transfer_hook_pools_are_inactive() {
+    let mut fixture = Fixture::load(0);
+    fixture.mint_1.account.data = transfer_hook_mint(Some(Pubkey::new_unique()));
+
+    let amm = fixture.amm();
+    assert!(!amm.is_active());
+    assert!(amm.quote(&fixture.quote_params(1_000_000, true)).is_err());
+    let swap_params = SwapParams {
+        source_mint: fixture.mint_0.key,
+        destination_mint: fixture.mint_1.key,
+        source_token_account: Pubkey::new_unique(),
+        destination_token_account: Pubkey::new_unique(),
+        token_transfer_authority: Pubkey::new_unique(),
+        in_amount: 1_000_000,
+        out_amount: 0,
+    };
+    assert!(amm.get_swap_and_account_metas(&swap_params).is_err());
+
+    // A hook extension without a program invokes nothing on transfer.
+    fixture.mint_1.account.data = transfer_hook_mint(None);
+    assert!(fixture.amm().is_active());
+}
+
+#[test]
+fn builds_swap_account_metas() {
+    let fixture = Fixture::load(0);
+    let amm = fixture.amm();
+    let authority = Pubkey::new_unique();
+    let source = Pubkey::new_unique();
+    let destination = Pubkey::new_unique();
+
+    let swap = amm
+        .get_swap_and_account_metas(&SwapParams {
+            source_mint: fixture.mint_1.key,
+            destination_mint: fixture.mint_0.key,
+            source_token_account: source,
+            destination_token_account: destination,
+            token_transfer_authority: authority,
+            in_amount: 1_000_000,
+            out_amount: 990_000,
+        })
+        .unwrap();
+    assert_eq!(swap.swap, Swap::Clmm { swap_token_0_for_1: false });
+
+    let clmm_sdk::PoolKeys {
+        token_vault_0,
+        token_vault_1,
+        ..
+    } = clmm_sdk::PoolKeys::from_pool(
+        fixture.pool.key,
+        &clmm_sdk::state::decode_pool(&fixture.pool.account.data).unwrap(),
+        Pubkey::default(),
+        Pubkey::default(),
+    );
+    let keys: Vec<_> = swap.account_metas.iter().map(|meta| meta.pubkey).collect();
+    assert_eq!(
+        keys,
+        vec![
+            clmm_sdk::pda::find_global_config_address().0,
+            fixture.pool.key,
+            destination,
+            source,
+            token_vault_0,
+            token_vault_1,
+            authority,
+            // No wSOL account: both token accounts are supplied.
+            clmm_sdk::ID,
+            fixture.mint_0.key,
+            fixture.mint_1.key,
+            anchor_lang::system_program::ID,
+            fixture.mint_0.account.owner,
+            fixture.mint_1.account.owner,
+            anchor_lang::solana_program::sysvar::rent::ID,
+            clmm_sdk::pda::find_event_authority_address().0,
+            clmm_sdk::ID,
+        ]
+    );
+    assert!(swap.account_metas[6].is_signer);
+    assert!(swap.account_metas[1].is_writable);
+}
@@ -0,0 +1,129 @@
+use std::path::PathBuf;
+
+use anchor_lang::prelude::Pubkey;
+use clap::{Parser, Subcommand};
+
+#[derive(Debug, Parser)]
+#[command(name = "clmm", version, about = "Administer clmm pools and manage liquidity")]
+pub struct Cli {
+    /// JSON-RPC endpoint; defaults to a local test validator.
+    #[arg(long, short = 'u', global = true, env = "CLMM_RPC_URL", default_value = "http://127.0.0.1:8899")]
+    pub url: String,
+
+    /// Fee payer and signer for transactions.
+    #[arg(long, short = 'k', global = true, env = "CLMM_KEYPAIR")]
+    pub keypair: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Create a pool for a mint pair. The mints may be given in either order.
+    CreatePool {
+        #[arg(long)]
+        mint_a: Pubkey,
+        #[arg(long)]
+        mint_b: Pubkey,
+        /// One of 1, 10, 60 or 200.
+        #[arg(long)]
+        tick_spacing: i32,
+        /// Initial price as a tick of the pool's token_1/token_0 price.
+        #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+        initial_tick: i32,
+    },
+    /// Open a position, or add to the one the signer already holds for the range.
+    OpenPosition {
+        #[arg(long)]
+        pool: Pubkey,
+        #[arg(long, allow_negative_numbers = true)]
+        tick_lower: i32,
+        #[arg(long, allow_negative_numbers = true)]
+        tick_upper: i32,
+        #[arg(long)]
+        liquidity: u128,
+        #[arg(long, default_value_t = 50)]
+        slippage_bps: u16,
+    },
+    /// Add liquidity to an existing position.
+    IncreaseLiquidity {
+        #[arg(long)]
+        position: Pubkey,
+        #[arg(long)]
+        liquidity: u128,
+        #[arg(long, default_value_t = 50)]
+        slippage_bps: u16,
+    },
+    /// Withdraw liquidity from a position.
+    DecreaseLiquidity {
+        #[arg(long)]
+        position: Pubkey,
+        #[arg(long)]
+        liquidity: u128,
+        #[arg(long, default_value_t = 50)]
+        slippage_bps: u16,
+    },
+    /// Create the signer's position bundle for a pool.
+    InitializePositionBundle {
+        #[arg(long)]
+        pool: Pubkey,
+    },
+    /// Open a position in a slot of the signer's position bundle.
+    OpenBundledPosition {
+        #[arg(long)]
+        pool: Pubkey,
+        #[arg(long)]
+        bundle_index: u16,
+        #[arg(long, allow_negative_numbers = true)]
+        tick_lower: i32,
+        #[arg(long, allow_negative_numbers = true)]
+        tick_upper: i32,
+        #[arg(long)]
+        liquidity: u128,
+        #[arg(long, default_value_t = 50)]
+        slippage_bps: u16,
+    },
+    /// Add liquidity to a bundled position.
+    IncreaseBundledLiquidity {
+        #[arg(long)]
+        pool: Pubkey,
+        #[arg(long)]
+        bundle_index: u16,
+        #[arg(long)]
+        liquidity: u128,
+        #[arg(long, default_value_t = 50)]
+        slippage_bps: u16,
+    },
+    /// Withdraw liquidity from a bundled position.
+    DecreaseBundledLiquidity {
+        #[arg(long)]
+        pool: Pubkey,
+        #[arg(long)]
+        bundle_index: u16,
+        #[arg(long)]
+        liquidity: u128,
+        #[arg(long, default_value_t = 50)]
+        slippage_bps: u16,
+    },
+    /// Swap an exact input amount through a single pool.
+    Swap {
+        #[arg(long)]
+        pool: Pubkey,
+        /// The mint being sold; must be one of the pool's mints.
+        #[arg(long)]
+        input_mint: Pubkey,
+        #[arg(long)]
+        amount_in: u64,
+        #[arg(long, default_value_t = 50)]
+        slippage_bps: u16,
+    },
+    /// Print a pool account.
+    ShowPool { address: Pubkey },
+    /// Print a position account and its current value.
+    ShowPosition { address: Pubkey },
+    /// Print the initialized ticks of a tick array account.
+    ShowTickArray { address: Pubkey },
+    /// Print the pool's liquidity at every initialized tick.
+    Liquidity { pool: Pubkey },
+}
@@ -0,0 +1,498 @@
+use std::path::PathBuf;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use anchor_spl::token::spl_token::native_mint;
+use anyhow::{anyhow, bail, Context, Result};
+use clmm_quote::{PoolSnapshot, PoolTransferFees, PositionSnapshot, TickArraySnapshot, TransferFee};
+use clmm_sdk::instructions::{
+    self, DecreaseBundledLiquidityParams, DecreaseLiquidityParams, IncreaseBundledLiquidityParams,
+    OpenBundledPositionParams, OpenPositionParams, SwapParams,
+};
+use clmm_sdk::pda::find_position_bundle_address;
+use clmm_sdk::{state, PoolKeys, PositionKeys, TickArray, UserTokenAccounts};
+use solana_keypair::{read_keypair_file, Keypair};
+use solana_signer::Signer;
+use solana_transaction::Transaction;
+
+use crate::cli::{Cli, Command};
+use crate::display;
+use crate::rpc::RpcClient;
+
+const BPS_DENOMINATOR: u128 = 10_000;
+
+/// Raises a quoted amount the user pays by `slippage_bps`.
+pub fn with_slippage_up(amount: u64, slippage_bps: u16) -> u64 {
+    let bounded = amount as u128 * (BPS_DENOMINATOR + slippage_bps as u128) / BPS_DENOMINATOR;
+    bounded.min(u64::MAX as u128) as u64
+}
+
+/// Lowers a quoted amount the user receives by `slippage_bps`.
+pub fn with_slippage_down(amount: u64, slippage_bps: u16) -> u64 {
+    let slippage_bps = (slippage_bps as u128).min(BPS_DENOMINATOR);
+    (amount as u128 * (BPS_DENOMINATOR - slippage_bps) / BPS_DENOMINATOR) as u64
+}
+
+/// A loaded pool with everything needed to quote against it and build instructions for it.
+struct LoadedPool {
+    keys: PoolKeys,
+    snapshot: PoolSnapshot,
+    transfer_fees: PoolTransferFees,
+}
+
+struct App {
+    rpc: RpcClient,
+    keypair: Option<PathBuf>,
+}
+
+pub fn run(cli: Cli) -> Result<()> {
+    let app = App {
+        rpc: RpcClient::new(cli.url),
+        keypair: cli.keypair,
+    };
+    match cli.command {
+        Command::CreatePool {
+            mint_a,
+            mint_b,
+            tick_spacing,
+            initial_tick,
+        } => app.create_pool(mint_a, mint_b, tick_spacing, initial_tick),
+        Command::OpenPosition {
+            pool,
+            tick_lower,
+            tick_upper,
+            liquidity,
+            slippage_bps,
+        } => app.open_position(pool, tick_lower, tick_upper, liquidity, slippage_bps),
+        Command::IncreaseLiquidity {
+            position,
+            liquidity,
+            slippage_bps,
+        } => app.increase_liquidity(position, liquidity, slippage_bps),
+        Command::DecreaseLiquidity {
+            position,
+            liquidity,
+            slippage_bps,
+        } => app.decrease_liquidity(position, liquidity, slippage_bps),
+        Command::InitializePositionBundle { pool } => app.initialize_position_bundle(pool),
+        Command::OpenBundledPosition {
+            pool,
+            bundle_index,
+            tick_lower,
+            tick_upper,
+            liquidity,
+            slippage_bps,
+        } => app.open_bundled_position(pool, bundle_index, tick_lower, tick_upper, liquidity, slippage_bps),
+        Command::IncreaseBundledLiquidity {
+            pool,
+            bundle_index,
+            liquidity,
+            slippage_bps,
+        } => app.increase_bundled_liquidity(pool, bundle_index, liquidity, slippage_bps),
+        Command::DecreaseBundledLiquidity {
+            pool,
+            bundle_index,
+            liquidity,
+            slippage_bps,
+        } => app.decrease_bundled_liquidity(pool, bundle_index, liquidity, slippage_bps),
+        Command::Swap {
+            pool,
+            input_mint,
+            amount_in,
+            slippage_bps,
+        } => app.swap(pool, input_mint, amount_in, slippage_bps),
+        Command::ShowPool { address } => app.show_pool(address),
+        Command::ShowPosition { address } => app.show_position(address),
+        Command::ShowTickArray { address } => app.show_tick_array(address),
+        Command::Liquidity { pool } => app.liquidity(pool),
+    }
+}
+
+impl App {
+    fn payer(&self) -> Result<Keypair> {
+        let path = match &self.keypair {
+            Some(path) => path.clone(),
+            None => {
+                let home = std::env::var_os("HOME").ok_or_else(|| anyhow!("--keypair is required when HOME is unset"))?;
+                PathBuf::from(home).join(".config/solana/id.json")
+            }
+        };
+        read_keypair_file(&path).map_err(|error| anyhow!("reading keypair {}: {error}", path.display()))
+    }
+
+    fn send(&self, payer: &Keypair, instruction: Instruction) -> Result<String> {
+        let blockhash = self.rpc.get_latest_blockhash()?;
+        let transaction =
+            Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[payer], blockhash);
+        self.rpc.send_and_confirm(&transaction)
+    }
+
+    fn mint_transfer_fee(&self, data: &[u8], epoch: u64) -> Result<Option<TransferFee>> {
+        let fee = state::decode_epoch_transfer_fee(data, epoch).context("decoding mint")?;
+        Ok(fee.map(|fee| TransferFee {
+            transfer_fee_basis_points: u16::from(fee.transfer_fee_basis_points),
+            maximum_fee: u64::from(fee.maximum_fee),
+        }))
+    }
+
+    fn load_pool(&self, address: Pubkey) -> Result<LoadedPool> {
+        let account = self.rpc.get_existing_account(&address)?;
+        let pool = state::decode_pool(&account.data).with_context(|| format!("decoding pool {address}"))?;
+        let snapshot = PoolSnapshot::decode(&account.data)?;
+
+        let mint_0 = self.rpc.get_existing_account(&pool.token_mint_0)?;
+        let mint_1 = self.rpc.get_existing_account(&pool.token_mint_1)?;
+        let epoch = self.rpc.get_epoch()?;
+        Ok(LoadedPool {
+            keys: PoolKeys::from_pool(address, &pool, mint_0.owner, mint_1.owner),
+            snapshot,
+            transfer_fees: PoolTransferFees {
+                token_0: self.mint_transfer_fee(&mint_0.data, epoch)?,
+                token_1: self.mint_transfer_fee(&mint_1.data, epoch)?,
+            },
+        })
+    }
+
+    fn create_pool(&self, mint_a: Pubkey, mint_b: Pubkey, tick_spacing: i32, initial_tick: i32) -> Result<()> {
+        let payer = self.payer()?;
+        let (mint_0, mint_1, _) = clmm_sdk::pda::sort_token_mints(mint_a, mint_b);
+        let token_program_0 = self.rpc.get_existing_account(&mint_0)?.owner;
+        let token_program_1 = self.rpc.get_existing_account(&mint_1)?.owner;
+        let keys = PoolKeys::derive(mint_0, mint_1, tick_spacing, token_program_0, token_program_1);
+        let initial_sqrt_price = clmm_quote::math::get_sqrt_price_from_tick(initial_tick)?;
+
+        let signature = self.send(&payer, instructions::initialize_pool(payer.pubkey(), &keys, initial_sqrt_price))?;
+        println!("Created pool {}", keys.pool);
+        println!("Signature: {signature}");
+        Ok(())
+    }
+
+    fn open_position(
+        &self,
+        pool: Pubkey,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity: u128,
+        slippage_bps: u16,
+    ) -> Result<()> {
+        let payer = self.payer()?;
+        let pool = self.load_pool(pool)?;
+        let (transfer_0, transfer_1) =
+            clmm_quote::quote_open_position(&pool.snapshot, &pool.transfer_fees, tick_lower, tick_upper, liquidity)?;
+
+        let owner = payer.pubkey();
+        let instruction = instructions::open_position(
+            owner,
+            &pool.keys,
+            wrappable_token_account(&owner, &pool.keys, true),
+            wrappable_token_account(&owner, &pool.keys, false),
+            OpenPositionParams {
+                owner,
+                tick_lower,
+                tick_upper,
+                liquidity_amount: liquidity,
+                amount_0_max: with_slippage_up(transfer_0.gross_amount, slippage_bps),
+                amount_1_max: with_slippage_up(transfer_1.gross_amount, slippage_bps),
+                deadline: None,
+                expected_sqrt_price: None,
+            },
+        );
+        let signature = self.send(&payer, instruction)?;
+        let position = PositionKeys::derive(&pool.keys, &owner, tick_lower, tick_upper);
+        println!("Position {}", position.position);
+        println!("Deposited {} / {}", transfer_0.gross_amount, transfer_1.gross_amount);
+        println!("Signature: {signature}");
+        Ok(())
+    }
+
+    fn load_position(&self, address: Pubkey) -> Result<(LoadedPool, PositionSnapshot, PositionKeys)> {
+        let account = self.rpc.get_existing_account(&address)?;
+        let position = PositionSnapshot::decode(&account.data)?;
+        let pool = self.load_pool(Pubkey::new_from_array(position.pool))?;
+        let owner = Pubkey::new_from_array(position.owner);
+        let keys = PositionKeys::derive(&pool.keys, &owner, position.tick_lower, position.tick_upper);
+        if keys.position != address {
+            bail!("{address} is not a position account");
+        }
+        Ok((pool, position, keys))
+    }
+
+    fn increase_liquidity(&self, position: Pubkey, liquidity: u128, slippage_bps: u16) -> Result<()> {
+        let payer = self.payer()?;
+        let (pool, snapshot, position) = self.load_position(position)?;
+        let (transfer_0, transfer_1) = clmm_quote::quote_increase_liquidity(
+            &pool.snapshot,
+            &pool.transfer_fees,
+            snapshot.tick_lower,
+            snapshot.tick_upper,
+            liquidity,
+        )?;
+
+        let instruction = instructions::increase_liquidity(
+            payer.pubkey(),
+            &pool.keys,
+            &position,
+            user_token_accounts(&payer.pubkey(), &pool.keys),
+            liquidity,
+            with_slippage_up(transfer_0.gross_amount, slippage_bps),
+            with_slippage_up(transfer_1.gross_amount, slippage_bps),
+        );
+        let signature = self.send(&payer, instruction)?;
+        println!("Deposited {} / {}", transfer_0.gross_amount, transfer_1.gross_amount);
+        println!("Signature: {signature}");
+        Ok(())
+    }
+
+    fn decrease_liquidity(&self, position: Pubkey, liquidity: u128, slippage_bps: u16) -> Result<()> {
+        let payer = self.payer()?;
+        let (pool, snapshot, position) = self.load_position(position)?;
+        let (transfer_0, transfer_1) =
+            clmm_quote::quote_decrease_liquidity(&pool.snapshot, &pool.transfer_fees, &snapshot, liquidity)?;
+
+        let instruction = instructions::decrease_liquidity(
+            payer.pubkey(),
+            &pool.keys,
+            &position,
+            user_token_accounts(&payer.pubkey(), &pool.keys),
+            DecreaseLiquidityParams {
+                liquidity_amount: liquidity,
+                amount_0_min: with_slippage_down(transfer_0.net_amount, slippage_bps),
+                amount_1_min: with_slippage_down(transfer_1.net_amount, slippage_bps),
+                deadline: None,
+                expected_sqrt_price: None,
+            },
+        );
+        let signature = self.send(&payer, instruction)?;
+        println!("Withdrew {} / {}", transfer_0.net_amount, transfer_1.net_amount);
+        println!("Signature: {signature}");
+        Ok(())
+    }
+
+    fn initialize_position_bundle(&self, pool: Pubkey) -> Result<()> {
+        let payer = self.payer()?;
+        let signature = self.send(&payer, instructions::initialize_position_bundle(payer.pubkey(), pool))?;
+        println!("Position bundle {}", find_position_bundle_address(&payer.pubkey(), &pool).0);
+        println!("Signature: {signature}");
+        Ok(())
+    }
+
+    fn open_bundled_position(
+        &self,
+        pool: Pubkey,
+        bundle_index: u16,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity: u128,
+        slippage_bps: u16,
+    ) -> Result<()> {
+        let payer = self.payer()?;
+        let pool = self.load_pool(pool)?;
+        let (transfer_0, transfer_1) =
+            clmm_quote::quote_open_position(&pool.snapshot, &pool.transfer_fees, tick_lower, tick_upper, liquidity)?;
+
+        let instruction = instructions::open_bundled_position(
+            payer.pubkey(),
+            &pool.keys,
+            user_token_accounts(&payer.pubkey(), &pool.keys),
+            OpenBundledPositionParams {
+                bundle_index,
+                tick_lower,
+                tick_upper,
+                liquidity_amount: liquidity,
+                amount_0_max: with_slippage_up(transfer_0.gross_amount, slippage_bps),
+                amount_1_max: with_slippage_up(transfer_1.gross_amount, slippage_bps),
+            },
+        );
+        let signature = self.send(&payer, instruction)?;
+        println!("Deposited {} / {}", transfer_0.gross_amount, transfer_1.gross_amount);
+        println!("Signature: {signature}");
+        Ok(())
+    }
+
+    /// Loads the signer's bundled position in `bundle_index` as a standalone position snapshot.
+    fn load_bundled_position(&self, owner: &Pubkey, pool: &PoolKeys, bundle_index: u16) -> Result<PositionSnapshot> {
+        let address = find_position_bundle_address(owner, &pool.pool).0;
+        let account = self.rpc.get_existing_account(&address)?;
+        let bundle = state::decode_position_bundle(&account.data)
+            .with_context(|| format!("decoding position bundle {address}"))?;
+        let position = bundle
+            .get_position(bundle_index)
+            .with_context(|| format!("reading bundle slot {bundle_index}"))?;
+        Ok(PositionSnapshot {
+            liquidity: position.get_liquidity(),
+            tick_lower: position.tick_lower,
+            tick_upper: position.tick_upper,
+            owner: owner.to_bytes(),
+            pool: pool.pool.to_bytes(),
+            fee_growth_inside_0_last_x64: position.get_fee_growth_inside_0_last_x64(),
+            fee_growth_inside_1_last_x64: position.get_fee_growth_inside_1_last_x64(),
+            tokens_owed_0: position.tokens_owed_0,
+            tokens_owed_1: position.tokens_owed_1,
+        })
+    }
+
+    fn increase_bundled_liquidity(
+        &self,
+        pool: Pubkey,
+        bundle_index: u16,
+        liquidity: u128,
+        slippage_bps: u16,
+    ) -> Result<()> {
+        let payer = self.payer()?;
+        let pool = self.load_pool(pool)?;
+        let position = self.load_bundled_position(&payer.pubkey(), &pool.keys, bundle_index)?;
+        let (transfer_0, transfer_1) = clmm_quote::quote_increase_liquidity(
+            &pool.snapshot,
+            &pool.transfer_fees,
+            position.tick_lower,
+            position.tick_upper,
+            liquidity,
+        )?;
+
+        let instruction = instructions::increase_bundled_liquidity(
+            payer.pubkey(),
+            &pool.keys,
+            user_token_accounts(&payer.pubkey(), &pool.keys),
+            IncreaseBundledLiquidityParams {
+                bundle_index,
+                tick_lower: position.tick_lower,
+                tick_upper: position.tick_upper,
+                liquidity_amount: liquidity,
+                amount_0_max: with_slippage_up(transfer_0.gross_amount, slippage_bps),
+                amount_1_max: with_slippage_up(transfer_1.gross_amount, slippage_bps),
+            },
+        );
+        let signature = self.send(&payer, instruction)?;
+        println!("Deposited {} / {}", transfer_0.gross_amount, transfer_1.gross_amount);
+        println!("Signature: {signature}");
+        Ok(())
+    }
+
+    fn decrease_bundled_liquidity(
+        &self,
+        pool: Pubkey,
+        bundle_index: u16,
+        liquidity: u128,
+        slippage_bps: u16,
+    ) -> Result<()> {
+        let payer = self.payer()?;
+        let pool = self.load_pool(pool)?;
+        let position = self.load_bundled_position(&payer.pubkey(), &pool.keys, bundle_index)?;
+        let (transfer_0, transfer_1) =
+            clmm_quote::quote_decrease_liquidity(&pool.snapshot, &pool.transfer_fees, &position, liquidity)?;
+
+        let instruction = instructions::decrease_bundled_liquidity(
+            payer.pubkey(),
+            &pool.keys,
+            user_token_accounts(&payer.pubkey(), &pool.keys),
+            DecreaseBundledLiquidityParams {
+                bundle_index,
+                tick_lower: position.tick_lower,
+                tick_upper: position.tick_upper,
+                liquidity_amount: liquidity,
+                amount_0_min: with_slippage_down(transfer_0.net_amount, slippage_bps),
+                amount_1_min: with_slippage_down(transfer_1.net_amount, slippage_bps),
+            },
+        );
+        let signature = self.send(&payer, instruction)?;
+        println!("Withdrew {} / {}", transfer_0.net_amount, transfer_1.net_amount);
+        println!("Signature: {signature}");
+        Ok(())
+    }
+
+    fn swap(&self, pool: Pubkey, input_mint: Pubkey, amount_in: u64, slippage_bps: u16) -> Result<()> {
+        let payer = self.payer()?;
+        let pool = self.load_pool(pool)?;
+        let swap_token_0_for_1 = if input_mint == pool.keys.token_mint_0 {
+            true
+        } else if input_mint == pool.keys.token_mint_1 {
+            false
+        } else {
+            bail!("{input_mint} is not one of the pool's mints");
+        };
+        let quote = clmm_quote::quote_swap(&pool.snapshot, &pool.transfer_fees, amount_in, swap_token_0_for_1)?;
+
+        let owner = payer.pubkey();
+        let instruction = instructions::swap(
+            owner,
+            &pool.keys,
+            wrappable_token_account(&owner, &pool.keys, true),
+            wrappable_token_account(&owner, &pool.keys, false),
+            SwapParams {
+                amount_in,
+                swap_token_0_for_1,
+                amount_out_minimum: with_slippage_down(quote.amount_out, slippage_bps),
+                deadline: None,
+                expected_sqrt_price: None,
+            },
+        );
+        let signature = self.send(&payer, instruction)?;
+        println!("Swapped {} for {} (fee {})", quote.amount_in, quote.amount_out, quote.fee_amount);
+        println!("Signature: {signature}");
+        Ok(())
+    }
+
+    fn show_pool(&self, address: Pubkey) -> Result<()> {
+        let account = self.rpc.get_existing_account(&address)?;
+        let pool = state::decode_pool(&account.data).with_context(|| format!("decoding pool {address}"))?;
+        print!("{}", display::format_pool(&address, &pool));
+        Ok(())
+    }
+
+    fn show_position(&self, address: Pubkey) -> Result<()> {
+        let account = self.rpc.get_existing_account(&address)?;
+        let position = state::decode_position(&account.data).with_context(|| format!("decoding position {address}"))?;
+        let snapshot = PositionSnapshot::decode(&account.data)?;
+        let pool = self.rpc.get_existing_account(&position.pool)?;
+        let value = clmm_quote::get_position_value(&PoolSnapshot::decode(&pool.data)?, &snapshot)?;
+        print!("{}", display::format_position(&address, &position, Some(&value)));
+        Ok(())
+    }
+
+    fn show_tick_array(&self, address: Pubkey) -> Result<()> {
+        let account = self.rpc.get_existing_account(&address)?;
+        let tick_array = TickArraySnapshot::decode(&account.data)?;
+        let pool = self.rpc.get_existing_account(&Pubkey::new_from_array(tick_array.pool))?;
+        let tick_spacing = PoolSnapshot::decode(&pool.data)?.tick_spacing;
+        print!("{}", display::format_tick_array(&address, &tick_array, tick_spacing));
+        Ok(())
+    }
+
+    fn liquidity(&self, address: Pubkey) -> Result<()> {
+        let pool = PoolSnapshot::decode(&self.rpc.get_existing_account(&address)?.data)?;
+        let tick_arrays = self
+            .rpc
+            .get_program_accounts(&clmm_sdk::ID, 8 + std::mem::size_of::<TickArray>(), 8, address.as_ref())?
+            .into_iter()
+            .map(|(_, account)| TickArraySnapshot::decode(&account.data))
+            .collect::<clmm_quote::Result<Vec<_>>>()?;
+
+        let levels = display::liquidity_distribution(&tick_arrays, pool.tick_spacing);
+        println!("Pool {address}: current tick {}, liquidity {}", pool.current_tick, pool.global_liquidity);
+        print!("{}", display::format_liquidity_distribution(&levels, pool.current_tick));
+        Ok(())
+    }
+}
+
+fn user_token_account(owner: &Pubkey, keys: &PoolKeys, is_token_0: bool) -> Pubkey {
+    let (mint, token_program) = if is_token_0 {
+        (keys.token_mint_0, keys.token_program_0)
+    } else {
+        (keys.token_mint_1, keys.token_program_1)
+    };
+    get_associated_token_address_with_program_id(owner, &mint, &token_program)
+}
+
+fn user_token_accounts(owner: &Pubkey, keys: &PoolKeys) -> UserTokenAccounts {
+    UserTokenAccounts {
+        token_0: user_token_account(owner, keys, true),
+        token_1: user_token_account(owner, keys, false),
+    }
+}
+
+// Native SOL sides are left out so the program wraps and unwraps from the payer's lamports.
+fn wrappable_token_account(owner: &Pubkey, keys: &PoolKeys, is_token_0: bool) -> Option<Pubkey> {
+    let mint = if is_token_0 { keys.token_mint_0 } else { keys.token_mint_1 };
+    (mint != native_mint::ID).then(|| user_token_account(owner, keys, is_token_0))
+}
@@ -0,0 +1,139 @@
+//! Human-readable renderings of program accounts. Everything here is pure so it can be
+//! tested without an RPC endpoint.
+
+use std::fmt::Write;
+
+use anchor_lang::prelude::Pubkey;
+use clmm_quote::{PositionValue, TickArraySnapshot};
+use clmm_sdk::{Pool, Position};
+
+/// Net and gross liquidity at an initialized tick, and the pool liquidity in effect from
+/// that tick up to the next initialized one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiquidityLevel {
+    pub tick: i32,
+    pub liquidity_net: i128,
+    pub liquidity_gross: u128,
+    pub active_liquidity: i128,
+}
+
+/// Walks every initialized tick from the lowest up, accumulating net liquidity.
+pub fn liquidity_distribution(tick_arrays: &[TickArraySnapshot], tick_spacing: i32) -> Vec<LiquidityLevel> {
+    let mut ticks: Vec<_> = tick_arrays
+        .iter()
+        .flat_map(|tick_array| {
+            tick_array
+                .ticks
+                .iter()
+                .enumerate()
+                .filter(|(_, tick)| tick.is_initialized())
+                .map(move |(offset, tick)| (tick_array.starting_tick + offset as i32 * tick_spacing, tick))
+        })
+        .collect();
+    ticks.sort_by_key(|(tick, _)| *tick);
+
+    let mut active_liquidity = 0i128;
+    ticks
+        .into_iter()
+        .map(|(tick, info)| {
+            active_liquidity = active_liquidity.saturating_add(info.liquidity_net());
+            LiquidityLevel {
+                tick,
+                liquidity_net: info.liquidity_net(),
+                liquidity_gross: info.liquidity_gross(),
+                active_liquidity,
+            }
+        })
+        .collect()
+}
+
+pub fn format_liquidity_distribution(levels: &[LiquidityLevel], current_tick: i32) -> String {
+    if levels.is_empty() {
+        return "No initialized ticks\n".to_string();
+    }
+    let mut out = format!(
+        "{:>12} {:>24} {:>24} {:>24}\n",
+        "tick", "liquidity_net", "liquidity_gross", "active_liquidity"
+    );
+    for (index, level) in levels.iter().enumerate() {
+        let next_tick = levels.get(index + 1).map_or(i32::MAX, |next| next.tick);
+        let marker = if (level.tick..next_tick).contains(&current_tick) {
+            "  <- current"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            out,
+            "{:>12} {:>24} {:>24} {:>24}{marker}",
+            level.tick, level.liquidity_net, level.liquidity_gross, level.active_liquidity
+        );
+    }
+    out
+}
+
+pub fn format_pool(address: &Pubkey, pool: &Pool) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "Pool {address}");
+    let _ = writeln!(out, "  status:               {:?}", pool.status);
+    let _ = writeln!(out, "  token_mint_0:         {}", pool.token_mint_0);
+    let _ = writeln!(out, "  token_mint_1:         {}", pool.token_mint_1);
+    let _ = writeln!(out, "  token_vault_0:        {}", pool.token_vault_0);
+    let _ = writeln!(out, "  token_vault_1:        {}", pool.token_vault_1);
+    let _ = writeln!(out, "  tick_spacing:         {}", pool.tick_spacing);
+    let _ = writeln!(out, "  current_tick:         {}", pool.current_tick);
+    let _ = writeln!(out, "  sqrt_price_x96:       {}", pool.sqrt_price_x96);
+    let _ = writeln!(out, "  global_liquidity:     {}", pool.global_liquidity);
+    let _ = writeln!(out, "  fee_rate:             {} ({:.4}%)", pool.fee_rate, pool.fee_rate as f64 / 1e4);
+    let _ = writeln!(
+        out,
+        "  protocol_fee_rate:    {} ({:.2}% of fees)",
+        pool.protocol_fee_rate,
+        pool.protocol_fee_rate as f64 / 1e2
+    );
+    let _ = writeln!(out, "  protocol_fees_owed:   {} / {}", pool.protocol_fees_owed_0, pool.protocol_fees_owed_1);
+    let _ = writeln!(out, "  fee_growth_global_0:  {}", pool.fee_growth_global_0_x64);
+    let _ = writeln!(out, "  fee_growth_global_1:  {}", pool.fee_growth_global_1_x64);
+    if let Some(pending_change) = &pool.pending_change {
+        let _ = writeln!(
+            out,
+            "  pending_change:       {:?} after {}",
+            pending_change.change, pending_change.execute_after
+        );
+    }
+    out
+}
+
+pub fn format_position(address: &Pubkey, position: &Position, value: Option<&PositionValue>) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "Position {address}");
+    let _ = writeln!(out, "  owner:          {}", position.owner);
+    let _ = writeln!(out, "  pool:           {}", position.pool);
+    let _ = writeln!(out, "  range:          [{}, {})", position.tick_lower, position.tick_upper);
+    let _ = writeln!(out, "  liquidity:      {}", position.liquidity);
+    let _ = writeln!(out, "  tokens_owed:    {} / {}", position.tokens_owed_0, position.tokens_owed_1);
+    if let Some(value) = value {
+        let _ = writeln!(out, "  in_range:       {}", value.in_range);
+        let _ = writeln!(out, "  amounts:        {} / {}", value.amount_0, value.amount_1);
+        let _ = writeln!(out, "  fees_owed:      {} / {}", value.fees_owed_0, value.fees_owed_1);
+    }
+    out
+}
+
+pub fn format_tick_array(address: &Pubkey, tick_array: &TickArraySnapshot, tick_spacing: i32) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "Tick array {address}");
+    let _ = writeln!(out, "  pool:           {}", Pubkey::new_from_array(tick_array.pool));
+    let _ = writeln!(out, "  starting_tick:  {}", tick_array.starting_tick);
+    let levels = liquidity_distribution(std::slice::from_ref(tick_array), tick_spacing);
+    if levels.is_empty() {
+        let _ = writeln!(out, "  no initialized ticks");
+    }
+    for level in levels {
+        let _ = writeln!(
+            out,
+            "  tick {:>10}: liquidity_net {}, liquidity_gross {}",
+            level.tick, level.liquidity_net, level.liquidity_gross
+        );
+    }
+    out
+}
@@ -0,0 +1,7 @@
+//! The `clmm` command-line tool: pool administration, liquidity management and swaps
+//! against any RPC endpoint, plus human-readable views of program accounts.
+
+pub mod cli;
+pub mod commands;
+pub mod display;
+pub mod rpc;
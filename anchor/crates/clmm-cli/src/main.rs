@@ -0,0 +1,6 @@
+use clap::Parser;
+use clmm_cli::cli::Cli;
+
+fn main() -> anyhow::Result<()> {
+    clmm_cli::commands::run(Cli::parse())
+}
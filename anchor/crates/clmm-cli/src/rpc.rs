@@ -0,0 +1,142 @@
+//! Just enough JSON-RPC for the CLI: account reads, blockhashes and sending transactions.
+
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::hash::Hash;
+use anyhow::{anyhow, bail, Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde_json::{json, Value};
+use solana_transaction::Transaction;
+
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+const CONFIRM_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct Account {
+    pub owner: Pubkey,
+    pub data: Vec<u8>,
+}
+
+pub struct RpcClient {
+    url: String,
+}
+
+impl RpcClient {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+
+    fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let mut response: Value = ureq::post(&self.url)
+            .send_json(&body)
+            .with_context(|| format!("{method} request to {}", self.url))?
+            .body_mut()
+            .read_json()?;
+        if let Some(error) = response.get("error") {
+            bail!("{method} failed: {error}");
+        }
+        Ok(response["result"].take())
+    }
+
+    pub fn get_account(&self, address: &Pubkey) -> Result<Option<Account>> {
+        let result = self.request(
+            "getAccountInfo",
+            json!([address.to_string(), { "encoding": "base64", "commitment": "confirmed" }]),
+        )?;
+        match &result["value"] {
+            Value::Null => Ok(None),
+            value => parse_account(value).map(Some),
+        }
+    }
+
+    pub fn get_existing_account(&self, address: &Pubkey) -> Result<Account> {
+        self.get_account(address)?
+            .ok_or_else(|| anyhow!("account {address} does not exist"))
+    }
+
+    /// Accounts of `program` with exactly `data_size` bytes and `bytes` at `offset`.
+    pub fn get_program_accounts(
+        &self,
+        program: &Pubkey,
+        data_size: usize,
+        offset: usize,
+        bytes: &[u8],
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        let filters = json!([
+            { "dataSize": data_size },
+            { "memcmp": { "offset": offset, "bytes": BASE64_STANDARD.encode(bytes), "encoding": "base64" } },
+        ]);
+        let result = self.request(
+            "getProgramAccounts",
+            json!([program.to_string(), { "encoding": "base64", "commitment": "confirmed", "filters": filters }]),
+        )?;
+        let entries = result.as_array().ok_or_else(|| anyhow!("unexpected getProgramAccounts result"))?;
+        entries
+            .iter()
+            .map(|entry| {
+                let address = parse_pubkey(&entry["pubkey"])?;
+                Ok((address, parse_account(&entry["account"])?))
+            })
+            .collect()
+    }
+
+    pub fn get_epoch(&self) -> Result<u64> {
+        let result = self.request("getEpochInfo", json!([{ "commitment": "confirmed" }]))?;
+        result["epoch"].as_u64().ok_or_else(|| anyhow!("unexpected getEpochInfo result"))
+    }
+
+    pub fn get_latest_blockhash(&self) -> Result<Hash> {
+        let result = self.request("getLatestBlockhash", json!([{ "commitment": "confirmed" }]))?;
+        let blockhash = result["value"]["blockhash"]
+            .as_str()
+            .ok_or_else(|| anyhow!("unexpected getLatestBlockhash result"))?;
+        Ok(Hash::from_str(blockhash)?)
+    }
+
+    /// Sends a signed transaction and waits until it is confirmed, returning its signature.
+    pub fn send_and_confirm(&self, transaction: &Transaction) -> Result<String> {
+        let encoded = BASE64_STANDARD.encode(bincode::serialize(transaction)?);
+        let signature = self.request(
+            "sendTransaction",
+            json!([encoded, { "encoding": "base64", "preflightCommitment": "confirmed" }]),
+        )?;
+        let signature = signature
+            .as_str()
+            .ok_or_else(|| anyhow!("unexpected sendTransaction result"))?
+            .to_string();
+
+        let started = Instant::now();
+        while started.elapsed() < CONFIRM_TIMEOUT {
+            let result = self.request("getSignatureStatuses", json!([[signature]]))?;
+            let status = &result["value"][0];
+            if !status.is_null() {
+                if !status["err"].is_null() {
+                    bail!("transaction {signature} failed: {}", status["err"]);
+                }
+                if matches!(status["confirmationStatus"].as_str(), Some("confirmed" | "finalized")) {
+                    return Ok(signature);
+                }
+            }
+            sleep(CONFIRM_POLL_INTERVAL);
+        }
+        bail!("transaction {signature} was not confirmed within {CONFIRM_TIMEOUT:?}")
+    }
+}
+
+fn parse_pubkey(value: &Value) -> Result<Pubkey> {
+    let address = value.as_str().ok_or_else(|| anyhow!("expected an address, got {value}"))?;
+    Ok(Pubkey::from_str(address)?)
+}
+
+fn parse_account(value: &Value) -> Result<Account> {
+    let data = value["data"][0]
+        .as_str()
+        .ok_or_else(|| anyhow!("expected base64 account data, got {}", value["data"]))?;
+    Ok(Account {
+        owner: parse_pubkey(&value["owner"])?,
+        data: BASE64_STANDARD.decode(data)?,
+    })
+}
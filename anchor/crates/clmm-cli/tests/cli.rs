@@ -0,0 +1,121 @@
+use anchor_lang::prelude::Pubkey;
+use clap::{CommandFactory, Parser};
+use clmm_cli::cli::{Cli, Command};
+use clmm_cli::commands::{with_slippage_down, with_slippage_up};
+use clmm_cli::display::{format_liquidity_distribution, liquidity_distribution, LiquidityLevel};
+use clmm_quote::{TickArraySnapshot, TickSnapshot};
+
+#[test]
+fn cli_definition_is_valid() {
+    Cli::command().debug_assert();
+}
+
+#[test]
+fn parses_negative_ticks() {
+    let pool = Pubkey::new_unique();
+    let cli = Cli::try_parse_from([
+        "clmm",
+        "--url",
+        "http://localhost:8899",
+        "open-position",
+        "--pool",
+        &pool.to_string(),
+        "--tick-lower",
+        "-1800",
+        "--tick-upper",
+        "60",
+        "--liquidity",
+        "1000000",
+    ])
+    .unwrap();
+
+    assert_eq!(cli.url, "http://localhost:8899");
+    match cli.command {
+        Command::OpenPosition {
+            pool: parsed,
+            tick_lower,
+            tick_upper,
+            liquidity,
+            slippage_bps,
+        } => {
+            assert_eq!(parsed, pool);
+            assert_eq!((tick_lower, tick_upper), (-1800, 60));
+            assert_eq!(liquidity, 1_000_000);
+            assert_eq!(slippage_bps, 50);
+        }
+        command => panic!("unexpected command {command:?}"),
+    }
+}
+
+#[test]
+fn slippage_bounds() {
+    assert_eq!(with_slippage_up(10_000, 50), 10_050);
+    assert_eq!(with_slippage_up(u64::MAX, 50), u64::MAX);
+    assert_eq!(with_slippage_down(10_000, 50), 9_950);
+    assert_eq!(with_slippage_down(10_000, 20_000), 0);
+}
+
+fn tick(liquidity_net: i128, liquidity_gross: u128) -> TickSnapshot {
+    TickSnapshot {
+        liquidity_gross_lower: liquidity_gross as u64,
+        liquidity_gross_upper: (liquidity_gross >> 64) as u64,
+        liquidity_net_lower: liquidity_net as u128 as u64,
+        liquidity_net_upper: (liquidity_net as u128 >> 64) as u64,
+        initialized: 1,
+    }
+}
+
+#[test]
+fn liquidity_distribution_accumulates_across_arrays() {
+    // Two positions, [-60, 1800) with 500 and [0, 1800) with 300, at tick spacing 60.
+    let mut lower = TickArraySnapshot {
+        pool: [0; 32],
+        starting_tick: -1800,
+        ticks: [TickSnapshot::default(); 30],
+    };
+    lower.ticks[29] = tick(500, 500);
+    let mut middle = TickArraySnapshot {
+        starting_tick: 0,
+        ..lower.clone()
+    };
+    middle.ticks = [TickSnapshot::default(); 30];
+    middle.ticks[0] = tick(300, 300);
+    let mut upper = TickArraySnapshot {
+        starting_tick: 1800,
+        ..middle.clone()
+    };
+    upper.ticks = [TickSnapshot::default(); 30];
+    upper.ticks[0] = tick(-800, 800);
+
+    // Account order from getProgramAccounts is arbitrary.
+    let levels = liquidity_distribution(&[upper, lower, middle], 60);
+    assert_eq!(
+        levels,
+        vec![
+            LiquidityLevel {
+                tick: -60,
+                liquidity_net: 500,
+                liquidity_gross: 500,
+                active_liquidity: 500,
+            },
+            LiquidityLevel {
+                tick: 0,
+                liquidity_net: 300,
+                liquidity_gross: 300,
+                active_liquidity: 800,
+            },
+            LiquidityLevel {
+                tick: 1800,
+                liquidity_net: -800,
+                liquidity_gross: 800,
+                active_liquidity: 0,
+            },
+        ]
+    );
+
+    let table = format_liquidity_distribution(&levels, 1000);
+    let current: Vec<_> = table.lines().filter(|line| line.ends_with("<- current")).collect();
+    assert_eq!(current.len(), 1);
+    assert!(current[0].trim_start().starts_with("0 "));
+    assert_eq!(format_liquidity_distribution(&[], 0), "No initialized ticks\n");
+}
@@ -0,0 +1,29 @@
+use std::fmt;
+
+/// Mirrors the `ClmmError` variants the quoted paths can fail with, so a quote fails
+/// exactly when the program would. Snapshot decoding adds its own variants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuoteError {
+    ArithmeticOverflow,
+    InsufficientInputAmount,
+    InsufficientLiquidity,
+    InsufficientPoolLiquidity,
+    InvalidTickRange,
+    InvalidTickArray,
+    InvalidTickArrayIndex,
+    NoLiquidityToRemove,
+    TickNotFound,
+    AccountDiscriminatorMismatch,
+    AccountDidNotDeserialize,
+    PoolWithdrawOnly,
+}
+
+impl fmt::Display for QuoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for QuoteError {}
+
+pub type Result<T> = std::result::Result<T, QuoteError>;
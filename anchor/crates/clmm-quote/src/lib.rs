@@ -0,0 +1,239 @@
+//! Off-chain quotes for the `clmm` program. Loads `Pool`, `Position` and `TickArray`
+//! snapshots from cached account data and reproduces the program's swap and liquidity
+//! math exactly, without depending on the Solana runtime.
+
+pub mod error;
+pub mod math;
+pub mod snapshot;
+pub mod transfer_fee;
+
+pub use error::{QuoteError, Result};
+pub use snapshot::{PoolSnapshot, PositionSnapshot, TickArraySnapshot, TickSnapshot};
+pub use transfer_fee::{TransferFee, TransferFeeQuote};
+
+use math::{
+    get_amounts_for_liquidity, get_fees_owed, get_price_impact, get_sqrt_price_from_tick, get_tick_at_sqrt_price,
+    quote_transfer_for_net, quote_transfer_from_gross, swap_segment,
+};
+
+/// The epoch transfer fee of each of the pool's mints, None for mints without the extension.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolTransferFees {
+    pub token_0: Option<TransferFee>,
+    pub token_1: Option<TransferFee>,
+}
+
+impl PoolTransferFees {
+    fn get(&self, is_token_0: bool) -> Option<&TransferFee> {
+        if is_token_0 {
+            self.token_0.as_ref()
+        } else {
+            self.token_1.as_ref()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapQuote {
+    // Sent by the user, including the input mint's transfer fee.
+    pub amount_in: u64,
+    // Received by the user, after the output mint's transfer fee.
+    pub amount_out: u64,
+    pub fee_amount: u64,
+    pub sqrt_price_x96: u128,
+    pub tick: i32,
+    // Left at 0 by `quote_swap`, as by the program's `compute_swap`; see `count_initialized_ticks_crossed`.
+    pub ticks_crossed: u32,
+    pub price_impact: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PositionValue {
+    pub amount_0: u64,
+    pub amount_1: u64,
+    pub fees_owed_0: u64,
+    pub fees_owed_1: u64,
+    pub in_range: bool,
+}
+
+/// Same result as the program's `quote_swap`, and as `swap` when it succeeds.
+pub fn quote_swap(
+    pool: &PoolSnapshot,
+    transfer_fees: &PoolTransferFees,
+    amount_in: u64,
+    swap_token_0_for_1: bool,
+) -> Result<SwapQuote> {
+    if !pool.is_active {
+        return Err(QuoteError::PoolWithdrawOnly);
+    }
+    if pool.global_liquidity == 0 {
+        return Err(QuoteError::InsufficientPoolLiquidity);
+    }
+    if amount_in == 0 {
+        return Err(QuoteError::InsufficientInputAmount);
+    }
+
+    let fee_in = transfer_fees.get(swap_token_0_for_1);
+    let net_amount_in = quote_transfer_from_gross(fee_in, amount_in)?.net_amount;
+    if net_amount_in == 0 {
+        return Err(QuoteError::InsufficientInputAmount);
+    }
+
+    let (amount_in_used, amount_out, fee_amount, sqrt_price_x96) =
+        swap_segment(pool.sqrt_price_x96, pool.global_liquidity, net_amount_in, pool.fee_rate, swap_token_0_for_1)?;
+    let tick = get_tick_at_sqrt_price(sqrt_price_x96)?;
+
+    // A fully used input is charged as sent, as in the program's `compute_swap`.
+    let gross_amount_in = if amount_in_used == net_amount_in {
+        amount_in
+    } else {
+        quote_transfer_for_net(fee_in, amount_in_used)?.gross_amount
+    };
+
+    Ok(SwapQuote {
+        amount_in: gross_amount_in,
+        amount_out,
+        fee_amount,
+        sqrt_price_x96,
+        tick,
+        ticks_crossed: 0,
+        price_impact: get_price_impact(pool.sqrt_price_x96, sqrt_price_x96)?,
+    })
+}
+
+/// Start indexes of the tick arrays holding the ticks a move of the current tick from
+/// `from_tick` to `to_tick` crosses, in the order the program's `quote_swap` expects them.
+pub fn get_crossed_tick_array_start_indexes(tick_spacing: i32, from_tick: i32, to_tick: i32) -> Vec<i32> {
+    if from_tick == to_tick {
+        return Vec::new();
+    }
+    let ticks_per_array = snapshot::TICKS_PER_ARRAY as i64 * tick_spacing as i64;
+    let lowest = from_tick.min(to_tick) as i64 + 1;
+    let highest = from_tick.max(to_tick) as i64;
+    (lowest.div_euclid(ticks_per_array)..=highest.div_euclid(ticks_per_array))
+        .map(|array_index| (array_index * ticks_per_array) as i32)
+        .collect()
+}
+
+/// Same result as the program's `count_initialized_ticks_crossed`, which `quote_swap` uses to
+/// fill in `SwapQuote::ticks_crossed`. Tick arrays that don't exist can simply be left out.
+pub fn count_initialized_ticks_crossed(
+    tick_arrays: &[TickArraySnapshot],
+    tick_spacing: i32,
+    from_tick: i32,
+    to_tick: i32,
+) -> u32 {
+    let crossed = (from_tick.min(to_tick) as i64 + 1)..=(from_tick.max(to_tick) as i64);
+    tick_arrays
+        .iter()
+        .flat_map(|tick_array| {
+            tick_array.ticks.iter().enumerate().map(move |(offset, tick)| {
+                (tick_array.starting_tick as i64 + offset as i64 * tick_spacing as i64, tick)
+            })
+        })
+        .filter(|(tick_index, tick)| crossed.contains(tick_index) && tick.is_initialized())
+        .count() as u32
+}
+
+/// Advances a snapshot past a quoted swap, the way `swap` updates the pool account.
+pub fn apply_swap(pool: &mut PoolSnapshot, quote: &SwapQuote, swap_token_0_for_1: bool) -> Result<()> {
+    pool.accrue_swap_fee(quote.fee_amount, swap_token_0_for_1)?;
+    pool.sqrt_price_x96 = quote.sqrt_price_x96;
+    pool.current_tick = quote.tick;
+    Ok(())
+}
+
+/// What `open_position` pulls from the user for each token, grossed up for transfer fees.
+pub fn quote_open_position(
+    pool: &PoolSnapshot,
+    transfer_fees: &PoolTransferFees,
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity_amount: u128,
+) -> Result<(TransferFeeQuote, TransferFeeQuote)> {
+    if tick_lower >= tick_upper || tick_lower % pool.tick_spacing != 0 || tick_upper % pool.tick_spacing != 0 {
+        return Err(QuoteError::InvalidTickRange);
+    }
+    quote_increase_liquidity(pool, transfer_fees, tick_lower, tick_upper, liquidity_amount)
+}
+
+/// What `increase_liquidity` pulls from the user for each token, grossed up for transfer fees.
+pub fn quote_increase_liquidity(
+    pool: &PoolSnapshot,
+    transfer_fees: &PoolTransferFees,
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity_amount: u128,
+) -> Result<(TransferFeeQuote, TransferFeeQuote)> {
+    if liquidity_amount == 0 {
+        return Err(QuoteError::InsufficientInputAmount);
+    }
+    let (amount_0, amount_1) = get_amounts_for_liquidity(
+        pool.sqrt_price_x96,
+        get_sqrt_price_from_tick(tick_lower)?,
+        get_sqrt_price_from_tick(tick_upper)?,
+        liquidity_amount,
+    )?;
+    Ok((
+        quote_transfer_for_net(transfer_fees.token_0.as_ref(), amount_0)?,
+        quote_transfer_for_net(transfer_fees.token_1.as_ref(), amount_1)?,
+    ))
+}
+
+/// What `decrease_liquidity` sends for each token; the user bears the transfer fee, so the
+/// slippage minimums apply to `net_amount`.
+pub fn quote_decrease_liquidity(
+    pool: &PoolSnapshot,
+    transfer_fees: &PoolTransferFees,
+    position: &PositionSnapshot,
+    liquidity_amount: u128,
+) -> Result<(TransferFeeQuote, TransferFeeQuote)> {
+    if liquidity_amount == 0 {
+        return Err(QuoteError::InsufficientInputAmount);
+    }
+    if position.liquidity < liquidity_amount {
+        return Err(QuoteError::NoLiquidityToRemove);
+    }
+    let (amount_0, amount_1) = get_amounts_for_liquidity(
+        pool.sqrt_price_x96,
+        get_sqrt_price_from_tick(position.tick_lower)?,
+        get_sqrt_price_from_tick(position.tick_upper)?,
+        liquidity_amount,
+    )?;
+    Ok((
+        quote_transfer_from_gross(transfer_fees.token_0.as_ref(), amount_0)?,
+        quote_transfer_from_gross(transfer_fees.token_1.as_ref(), amount_1)?,
+    ))
+}
+
+/// Same result as the program's `get_position_value`.
+pub fn get_position_value(pool: &PoolSnapshot, position: &PositionSnapshot) -> Result<PositionValue> {
+    let lower_sqrt_price_x96 = get_sqrt_price_from_tick(position.tick_lower)?;
+    let upper_sqrt_price_x96 = get_sqrt_price_from_tick(position.tick_upper)?;
+
+    let (amount_0, amount_1) =
+        get_amounts_for_liquidity(pool.sqrt_price_x96, lower_sqrt_price_x96, upper_sqrt_price_x96, position.liquidity)?;
+
+    let fees_owed_0 = get_fees_owed(
+        position.liquidity,
+        pool.fee_growth_global_0_x64,
+        position.fee_growth_inside_0_last_x64,
+    )?
+    .checked_add(position.tokens_owed_0)
+    .ok_or(QuoteError::ArithmeticOverflow)?;
+    let fees_owed_1 = get_fees_owed(
+        position.liquidity,
+        pool.fee_growth_global_1_x64,
+        position.fee_growth_inside_1_last_x64,
+    )?
+    .checked_add(position.tokens_owed_1)
+    .ok_or(QuoteError::ArithmeticOverflow)?;
+
+    Ok(PositionValue {
+        amount_0,
+        amount_1,
+        fees_owed_0,
+        fees_owed_1,
+        in_range: pool.sqrt_price_x96 >= lower_sqrt_price_x96 && pool.sqrt_price_x96 < upper_sqrt_price_x96,
+    })
+}
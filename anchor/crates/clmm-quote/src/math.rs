@@ -0,0 +1,144 @@
+//! The program's price, liquidity and fee math, line for line. Function names match the
+//! program so the two can be read side by side.
+
+use crate::error::{QuoteError, Result};
+use crate::transfer_fee::{TransferFee, TransferFeeQuote};
+
+pub const FEE_RATE_DENOMINATOR: u32 = 1_000_000;
+pub const PROTOCOL_FEE_RATE_DENOMINATOR: u16 = 10_000;
+
+// Simplified tick math
+pub fn get_sqrt_price_from_tick(tick: i32) -> Result<u128> {
+    let base_sqrt_price = 1u128 << 96;
+    let adjustment_factor = 1_000_000_000 / 1000;
+    base_sqrt_price
+        .checked_add_signed((tick as i128) * (adjustment_factor as i128))
+        .ok_or(QuoteError::ArithmeticOverflow)
+}
+
+pub fn get_tick_at_sqrt_price(sqrt_price_x96: u128) -> Result<i32> {
+    let base_sqrt_price = 1u128 << 96;
+    let adjustment_factor = 1_000_000_000 / 1000;
+
+    let diff = sqrt_price_x96 as i128 - base_sqrt_price as i128;
+    let tick = diff
+        .checked_div(adjustment_factor as i128)
+        .ok_or(QuoteError::ArithmeticOverflow)? as i32;
+    Ok(tick)
+}
+
+pub fn get_amounts_for_liquidity(
+    current_sqrt_price_x96: u128,
+    lower_sqrt_price_x96: u128,
+    upper_sqrt_price_x96: u128,
+    liquidity: u128,
+) -> Result<(u64, u64)> {
+    let amount = (liquidity / 1000) as u64;
+    if current_sqrt_price_x96 >= lower_sqrt_price_x96 && current_sqrt_price_x96 < upper_sqrt_price_x96 {
+        Ok((amount, amount))
+    } else if current_sqrt_price_x96 < lower_sqrt_price_x96 {
+        Ok((amount, 0))
+    } else {
+        Ok((0, amount))
+    }
+}
+
+pub fn get_fees_owed(liquidity: u128, fee_growth_x64: u128, fee_growth_last_x64: u128) -> Result<u64> {
+    let fee_growth_delta_x64 = fee_growth_x64.wrapping_sub(fee_growth_last_x64);
+    let fees = liquidity
+        .checked_mul(fee_growth_delta_x64)
+        .ok_or(QuoteError::ArithmeticOverflow)?
+        >> 64;
+    u64::try_from(fees).map_err(|_| QuoteError::ArithmeticOverflow)
+}
+
+pub fn get_fee_growth_delta_x64(fee_amount: u64, liquidity: u128) -> Result<u128> {
+    ((fee_amount as u128) << 64)
+        .checked_div(liquidity)
+        .ok_or(QuoteError::InsufficientLiquidity)
+}
+
+pub fn get_fee_amount(amount: u64, fee_rate: u32) -> Result<u64> {
+    let fee_amount = (amount as u128)
+        .checked_mul(fee_rate as u128)
+        .ok_or(QuoteError::ArithmeticOverflow)?
+        / FEE_RATE_DENOMINATOR as u128;
+    Ok(fee_amount as u64)
+}
+
+pub fn get_protocol_fee(fee_amount: u64, protocol_fee_rate: u16) -> Result<u64> {
+    let protocol_fee = (fee_amount as u128)
+        .checked_mul(protocol_fee_rate as u128)
+        .ok_or(QuoteError::ArithmeticOverflow)?
+        / PROTOCOL_FEE_RATE_DENOMINATOR as u128;
+    Ok(protocol_fee as u64)
+}
+
+/// Returns (amount_in_used, amount_out, fee_amount, new_sqrt_price_x96).
+pub fn swap_segment(
+    current_sqrt_price_x96: u128,
+    global_liquidity: u128,
+    amount_remaining_in: u64,
+    fee_rate: u32,
+    swap_token_0_for_1: bool,
+) -> Result<(u64, u64, u64, u128)> {
+    if global_liquidity == 0 {
+        return Err(QuoteError::InsufficientLiquidity);
+    }
+
+    let amount_in_used = amount_remaining_in;
+    let fee_amount = get_fee_amount(amount_in_used, fee_rate)?;
+    let amount_out = amount_in_used
+        .checked_sub(fee_amount)
+        .ok_or(QuoteError::ArithmeticOverflow)?;
+
+    let new_sqrt_price = if swap_token_0_for_1 {
+        current_sqrt_price_x96.checked_sub(1_000_000_000)
+    } else {
+        current_sqrt_price_x96.checked_add(1_000_000_000)
+    }
+    .ok_or(QuoteError::ArithmeticOverflow)?;
+    Ok((amount_in_used, amount_out, fee_amount, new_sqrt_price))
+}
+
+// |p1 - p0| / p0 with p = sqrt_price^2, scaled by FEE_RATE_DENOMINATOR.
+pub fn get_price_impact(sqrt_price_before_x96: u128, sqrt_price_after_x96: u128) -> Result<u32> {
+    let sqrt_price_delta = sqrt_price_before_x96.abs_diff(sqrt_price_after_x96);
+    let sqrt_price_sum_scaled = sqrt_price_before_x96
+        .checked_add(sqrt_price_after_x96)
+        .and_then(|sum| sum.checked_mul(FEE_RATE_DENOMINATOR as u128))
+        .and_then(|sum| sum.checked_div(sqrt_price_before_x96))
+        .ok_or(QuoteError::ArithmeticOverflow)?;
+    let price_impact = sqrt_price_delta
+        .checked_mul(sqrt_price_sum_scaled)
+        .and_then(|product| product.checked_div(sqrt_price_before_x96))
+        .ok_or(QuoteError::ArithmeticOverflow)?;
+    Ok(price_impact.min(u32::MAX as u128) as u32)
+}
+
+/// What arrives when `gross_amount` is sent. `transfer_fee` is None for mints without the
+/// transfer fee extension.
+pub fn quote_transfer_from_gross(transfer_fee: Option<&TransferFee>, gross_amount: u64) -> Result<TransferFeeQuote> {
+    let fee = match transfer_fee {
+        Some(fee) => fee.calculate_fee(gross_amount).ok_or(QuoteError::ArithmeticOverflow)?,
+        None => 0,
+    };
+    Ok(TransferFeeQuote {
+        gross_amount,
+        net_amount: gross_amount - fee,
+        transfer_fee: fee,
+    })
+}
+
+/// What must be sent for `net_amount` to arrive.
+pub fn quote_transfer_for_net(transfer_fee: Option<&TransferFee>, net_amount: u64) -> Result<TransferFeeQuote> {
+    let gross_amount = match transfer_fee {
+        Some(fee) => fee.calculate_pre_fee_amount(net_amount).ok_or(QuoteError::ArithmeticOverflow)?,
+        None => net_amount,
+    };
+    Ok(TransferFeeQuote {
+        gross_amount,
+        net_amount,
+        transfer_fee: gross_amount - net_amount,
+    })
+}
@@ -0,0 +1,352 @@
+//! Account snapshots decoded from raw account data, without the Anchor runtime. Only the
+//! fields the quote paths read or write are kept.
+
+use crate::error::{QuoteError, Result};
+use crate::math::{get_fee_growth_delta_x64, get_protocol_fee};
+
+pub const POOL_DISCRIMINATOR: [u8; 8] = [241, 154, 109, 4, 17, 177, 109, 188];
+pub const POSITION_DISCRIMINATOR: [u8; 8] = [170, 188, 143, 228, 122, 64, 247, 208];
+pub const TICK_ARRAY_DISCRIMINATOR: [u8; 8] = [69, 97, 189, 190, 110, 7, 66, 187];
+
+pub const TICKS_PER_ARRAY: usize = 30;
+
+pub type Address = [u8; 32];
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], discriminator: &[u8; 8]) -> Result<Self> {
+        match data.strip_prefix(discriminator) {
+            Some(data) => Ok(Self { data }),
+            None => Err(QuoteError::AccountDiscriminatorMismatch),
+        }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let (bytes, rest) = self
+            .data
+            .split_first_chunk::<N>()
+            .ok_or(QuoteError::AccountDidNotDeserialize)?;
+        self.data = rest;
+        Ok(*bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<()> {
+        self.data = self.data.get(len..).ok_or(QuoteError::AccountDidNotDeserialize)?;
+        Ok(())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn u128(&mut self) -> Result<u128> {
+        Ok(u128::from_le_bytes(self.take()?))
+    }
+
+    fn option_tag(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(QuoteError::AccountDidNotDeserialize),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolSnapshot {
+    pub token_mint_0: Address,
+    pub token_mint_1: Address,
+    pub token_vault_0: Address,
+    pub token_vault_1: Address,
+    pub global_liquidity: u128,
+    pub sqrt_price_x96: u128,
+    pub current_tick: i32,
+    pub tick_spacing: i32,
+    pub fee_growth_global_0_x64: u128,
+    pub fee_growth_global_1_x64: u128,
+    pub fee_rate: u32,
+    pub protocol_fee_rate: u16,
+    pub protocol_fees_owed_0: u64,
+    pub protocol_fees_owed_1: u64,
+    // Withdraw-only pools reject swaps and deposits.
+    pub is_active: bool,
+}
+
+impl PoolSnapshot {
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data, &POOL_DISCRIMINATOR)?;
+        let token_mint_0 = reader.take()?;
+        let token_mint_1 = reader.take()?;
+        let token_vault_0 = reader.take()?;
+        let token_vault_1 = reader.take()?;
+        let global_liquidity = reader.u128()?;
+        let sqrt_price_x96 = reader.u128()?;
+        let current_tick = reader.i32()?;
+        let tick_spacing = reader.i32()?;
+        let _bump = reader.u8()?;
+        let fee_growth_global_0_x64 = reader.u128()?;
+        let fee_growth_global_1_x64 = reader.u128()?;
+        let fee_rate = reader.u32()?;
+        let protocol_fee_rate = reader.u16()?;
+        let protocol_fees_owed_0 = reader.u64()?;
+        let protocol_fees_owed_1 = reader.u64()?;
+        if reader.option_tag()? {
+            // PendingChange: a ParameterChange variant and its value, then execute_after.
+            let value_len = match reader.u8()? {
+                0 => 4,
+                1 => 2,
+                2 => 8,
+                _ => return Err(QuoteError::AccountDidNotDeserialize),
+            };
+            reader.skip(value_len + 8)?;
+        }
+        let is_active = match reader.u8()? {
+            0 => true,
+            1 => false,
+            _ => return Err(QuoteError::AccountDidNotDeserialize),
+        };
+
+        Ok(Self {
+            token_mint_0,
+            token_mint_1,
+            token_vault_0,
+            token_vault_1,
+            global_liquidity,
+            sqrt_price_x96,
+            current_tick,
+            tick_spacing,
+            fee_growth_global_0_x64,
+            fee_growth_global_1_x64,
+            fee_rate,
+            protocol_fee_rate,
+            protocol_fees_owed_0,
+            protocol_fees_owed_1,
+            is_active,
+        })
+    }
+
+    // Spreads an amount over every unit of liquidity in the pool.
+    pub fn credit_fee_growth(&mut self, amount: u64, is_token_0: bool) -> Result<()> {
+        let fee_growth_delta_x64 = get_fee_growth_delta_x64(amount, self.global_liquidity)?;
+        if is_token_0 {
+            self.fee_growth_global_0_x64 = self.fee_growth_global_0_x64.wrapping_add(fee_growth_delta_x64);
+        } else {
+            self.fee_growth_global_1_x64 = self.fee_growth_global_1_x64.wrapping_add(fee_growth_delta_x64);
+        }
+        Ok(())
+    }
+
+    pub fn accrue_swap_fee(&mut self, fee_amount: u64, is_token_0: bool) -> Result<()> {
+        let protocol_fee = get_protocol_fee(fee_amount, self.protocol_fee_rate)?;
+        let protocol_fees_owed = if is_token_0 {
+            &mut self.protocol_fees_owed_0
+        } else {
+            &mut self.protocol_fees_owed_1
+        };
+        *protocol_fees_owed = protocol_fees_owed
+            .checked_add(protocol_fee)
+            .ok_or(QuoteError::ArithmeticOverflow)?;
+
+        self.credit_fee_growth(fee_amount - protocol_fee, is_token_0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PositionSnapshot {
+    pub liquidity: u128,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub owner: Address,
+    pub pool: Address,
+    pub fee_growth_inside_0_last_x64: u128,
+    pub fee_growth_inside_1_last_x64: u128,
+    pub tokens_owed_0: u64,
+    pub tokens_owed_1: u64,
+}
+
+impl PositionSnapshot {
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data, &POSITION_DISCRIMINATOR)?;
+        let liquidity = reader.u128()?;
+        let tick_lower = reader.i32()?;
+        let tick_upper = reader.i32()?;
+        let owner = reader.take()?;
+        let pool = reader.take()?;
+        let _bump = reader.u8()?;
+        Ok(Self {
+            liquidity,
+            tick_lower,
+            tick_upper,
+            owner,
+            pool,
+            fee_growth_inside_0_last_x64: reader.u128()?,
+            fee_growth_inside_1_last_x64: reader.u128()?,
+            tokens_owed_0: reader.u64()?,
+            tokens_owed_1: reader.u64()?,
+        })
+    }
+}
+
+/// Liquidity is stored as split u64 halves, as in the program's zero-copy `TickInfo`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TickSnapshot {
+    pub liquidity_gross_lower: u64,
+    pub liquidity_gross_upper: u64,
+    pub liquidity_net_lower: u64,
+    pub liquidity_net_upper: u64,
+    pub initialized: u64,
+}
+
+impl TickSnapshot {
+    pub fn is_initialized(&self) -> bool {
+        self.initialized != 0
+    }
+
+    pub fn liquidity_gross(&self) -> u128 {
+        ((self.liquidity_gross_upper as u128) << 64) | (self.liquidity_gross_lower as u128)
+    }
+
+    pub fn liquidity_net(&self) -> i128 {
+        (((self.liquidity_net_upper as u128) << 64) | (self.liquidity_net_lower as u128)) as i128
+    }
+
+    fn set_liquidity_gross(&mut self, value: u128) {
+        self.liquidity_gross_lower = value as u64;
+        self.liquidity_gross_upper = (value >> 64) as u64;
+    }
+
+    fn set_liquidity_net(&mut self, value: i128) {
+        let as_u128 = value as u128;
+        self.liquidity_net_lower = as_u128 as u64;
+        self.liquidity_net_upper = (as_u128 >> 64) as u64;
+    }
+
+    pub fn update_liquidity(&mut self, liquidity_delta: i128, is_lower: bool) -> Result<()> {
+        self.initialized = 1;
+
+        let new_gross = self
+            .liquidity_gross()
+            .checked_add(liquidity_delta.unsigned_abs())
+            .ok_or(QuoteError::ArithmeticOverflow)?;
+        self.set_liquidity_gross(new_gross);
+
+        let new_net = if is_lower {
+            self.liquidity_net().checked_add(liquidity_delta)
+        } else {
+            self.liquidity_net().checked_sub(liquidity_delta)
+        }
+        .ok_or(QuoteError::ArithmeticOverflow)?;
+        self.set_liquidity_net(new_net);
+        Ok(())
+    }
+
+    pub fn update_liquidity_decrease(&mut self, liquidity_delta: i128, is_lower: bool) -> Result<()> {
+        if !self.is_initialized() {
+            return Err(QuoteError::TickNotFound);
+        }
+
+        let new_gross = self
+            .liquidity_gross()
+            .checked_sub(liquidity_delta.unsigned_abs())
+            .ok_or(QuoteError::ArithmeticOverflow)?;
+        self.set_liquidity_gross(new_gross);
+
+        let new_net = if is_lower {
+            self.liquidity_net().checked_sub(liquidity_delta)
+        } else {
+            self.liquidity_net().checked_add(liquidity_delta)
+        }
+        .ok_or(QuoteError::ArithmeticOverflow)?;
+        self.set_liquidity_net(new_net);
+
+        if new_gross == 0 {
+            self.initialized = 0;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TickArraySnapshot {
+    pub pool: Address,
+    pub starting_tick: i32,
+    pub ticks: [TickSnapshot; TICKS_PER_ARRAY],
+}
+
+impl TickArraySnapshot {
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data, &TICK_ARRAY_DISCRIMINATOR)?;
+        let pool = reader.take()?;
+        let starting_tick = reader.i32()?;
+        // bump and alignment padding
+        reader.skip(4)?;
+        let mut ticks = [TickSnapshot::default(); TICKS_PER_ARRAY];
+        for tick in &mut ticks {
+            *tick = TickSnapshot {
+                liquidity_gross_lower: reader.u64()?,
+                liquidity_gross_upper: reader.u64()?,
+                liquidity_net_lower: reader.u64()?,
+                liquidity_net_upper: reader.u64()?,
+                initialized: reader.u64()?,
+            };
+        }
+        Ok(Self {
+            pool,
+            starting_tick,
+            ticks,
+        })
+    }
+
+    pub fn contains_tick(&self, tick: i32, tick_spacing: i32) -> bool {
+        let start = self.starting_tick as i64;
+        let end = start + TICKS_PER_ARRAY as i64 * tick_spacing as i64;
+        (start..end).contains(&(tick as i64))
+    }
+
+    fn tick_offset(&self, tick: i32, tick_spacing: i32) -> Result<usize> {
+        let offset = tick
+            .checked_div(tick_spacing)
+            .ok_or(QuoteError::ArithmeticOverflow)?
+            .checked_sub(
+                self.starting_tick
+                    .checked_div(tick_spacing)
+                    .ok_or(QuoteError::ArithmeticOverflow)?,
+            )
+            .ok_or(QuoteError::ArithmeticOverflow)?
+            .checked_rem(TICKS_PER_ARRAY as i32)
+            .ok_or(QuoteError::ArithmeticOverflow)? as usize;
+        if offset < TICKS_PER_ARRAY {
+            Ok(offset)
+        } else {
+            Err(QuoteError::InvalidTickArrayIndex)
+        }
+    }
+
+    pub fn get_tick_info(&self, tick: i32, tick_spacing: i32) -> Result<&TickSnapshot> {
+        Ok(&self.ticks[self.tick_offset(tick, tick_spacing)?])
+    }
+
+    pub fn get_tick_info_mutable(&mut self, tick: i32, tick_spacing: i32) -> Result<&mut TickSnapshot> {
+        let offset = self.tick_offset(tick, tick_spacing)?;
+        Ok(&mut self.ticks[offset])
+    }
+}
@@ -0,0 +1,54 @@
+//! Token-2022 transfer fee arithmetic, matching `spl_token_2022`'s `TransferFee`.
+
+const ONE_IN_BASIS_POINTS: u128 = 10_000;
+
+/// The transfer fee in effect for the current epoch. Callers pick the older or newer fee
+/// from the mint's `TransferFeeConfig` the same way the program does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TransferFee {
+    pub transfer_fee_basis_points: u16,
+    pub maximum_fee: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransferFeeQuote {
+    pub gross_amount: u64,
+    pub net_amount: u64,
+    pub transfer_fee: u64,
+}
+
+fn ceil_div(numerator: u128, denominator: u128) -> Option<u128> {
+    numerator.checked_add(denominator)?.checked_sub(1)?.checked_div(denominator)
+}
+
+impl TransferFee {
+    pub fn calculate_fee(&self, pre_fee_amount: u64) -> Option<u64> {
+        let basis_points = self.transfer_fee_basis_points as u128;
+        if basis_points == 0 || pre_fee_amount == 0 {
+            return Some(0);
+        }
+        let numerator = (pre_fee_amount as u128).checked_mul(basis_points)?;
+        let raw_fee = u64::try_from(ceil_div(numerator, ONE_IN_BASIS_POINTS)?).ok()?;
+        Some(raw_fee.min(self.maximum_fee))
+    }
+
+    // Picks the smaller amount when several pre-fee amounts round to the same net amount.
+    pub fn calculate_pre_fee_amount(&self, post_fee_amount: u64) -> Option<u64> {
+        let basis_points = self.transfer_fee_basis_points as u128;
+        match (basis_points, post_fee_amount) {
+            (0, _) => Some(post_fee_amount),
+            (_, 0) => Some(0),
+            (ONE_IN_BASIS_POINTS, _) => self.maximum_fee.checked_add(post_fee_amount),
+            _ => {
+                let numerator = (post_fee_amount as u128).checked_mul(ONE_IN_BASIS_POINTS)?;
+                let denominator = ONE_IN_BASIS_POINTS.checked_sub(basis_points)?;
+                let raw_pre_fee_amount = ceil_div(numerator, denominator)?;
+                if raw_pre_fee_amount.checked_sub(post_fee_amount as u128)? >= self.maximum_fee as u128 {
+                    post_fee_amount.checked_add(self.maximum_fee)
+                } else {
+                    u64::try_from(raw_pre_fee_amount).ok()
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,650 @@
+//! Runs the same inputs through the program's functions and the quote engine and requires
+//! identical results, including which error is returned.
+
+use std::fmt::Debug;
+
+use anchor_lang::error::Error;
+use anchor_lang::prelude::{AccountInfo, InterfaceAccount, Pubkey};
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::{AccountSerialize, Discriminator};
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022::extension::transfer_fee::TransferFee as SplTransferFee;
+use anchor_spl::token_interface::Mint;
+use clmm::{OutstandingFlashLoan, ParameterChange, PendingChange, Pool, PoolStatus, Position, TickArray, TickInfo};
+use clmm_quote::snapshot::{POOL_DISCRIMINATOR, POSITION_DISCRIMINATOR, TICK_ARRAY_DISCRIMINATOR};
+use clmm_quote::{
+    math, PoolSnapshot, PoolTransferFees, PositionSnapshot, QuoteError, SwapQuote, TickArraySnapshot, TickSnapshot,
+    TransferFee,
+};
+
+const ITERATIONS: usize = 2_000;
+
+// splitmix64, so failures reproduce without a rand dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    fn bool(&mut self) -> bool {
+        self.next_u64() & 1 == 1
+    }
+
+    // Biased towards the edges and towards values near the base sqrt price.
+    fn u128(&mut self) -> u128 {
+        match self.below(6) {
+            0 => [0, 1, u128::MAX, u128::MAX - 1, 1 << 64, 1 << 96][self.below(6) as usize],
+            1 => self.below(1 << 20) as u128,
+            2 => (1u128 << 96).wrapping_add_signed(self.i64() as i128 % 10_000_000_000_000),
+            3 => self.next_u64() as u128,
+            _ => ((self.next_u64() as u128) << 64) | self.next_u64() as u128,
+        }
+    }
+
+    fn u64(&mut self) -> u64 {
+        match self.below(4) {
+            0 => [0, 1, u64::MAX, u64::MAX - 1][self.below(4) as usize],
+            1 => self.below(1 << 20),
+            _ => self.next_u64(),
+        }
+    }
+
+    fn i64(&mut self) -> i64 {
+        self.next_u64() as i64
+    }
+
+    fn i32(&mut self) -> i32 {
+        match self.below(4) {
+            0 => [0, i32::MIN, i32::MAX, -1][self.below(4) as usize],
+            1 => self.below(200_000) as i32 - 100_000,
+            _ => self.next_u64() as i32,
+        }
+    }
+}
+
+fn error_name(error: Error) -> String {
+    match error {
+        Error::AnchorError(error) => error.error_name,
+        Error::ProgramError(error) => format!("{:?}", error.program_error),
+    }
+}
+
+fn assert_parity<T: PartialEq + Debug>(
+    program: anchor_lang::Result<T>,
+    engine: clmm_quote::Result<T>,
+    inputs: impl Debug,
+) {
+    match (program, engine) {
+        (Ok(program), Ok(engine)) => assert_eq!(program, engine, "inputs: {inputs:?}"),
+        (Err(program), Err(engine)) => assert_eq!(error_name(program), format!("{engine:?}"), "inputs: {inputs:?}"),
+        (program, engine) => panic!("inputs: {inputs:?}\nprogram: {program:?}\nengine: {engine:?}"),
+    }
+}
+
+fn random_pool(rng: &mut Rng) -> Pool {
+    let pending_change = match rng.below(4) {
+        0 => Some(ParameterChange::FeeRate(rng.next_u64() as u32)),
+        1 => Some(ParameterChange::ProtocolFeeRate(rng.next_u64() as u16)),
+        2 => Some(ParameterChange::TimelockDelay(rng.i64())),
+        _ => None,
+    }
+    .map(|change| PendingChange {
+        change,
+        execute_after: rng.i64(),
+    });
+    Pool {
+        token_mint_0: Pubkey::new_unique(),
+        token_mint_1: Pubkey::new_unique(),
+        token_vault_0: Pubkey::new_unique(),
+        token_vault_1: Pubkey::new_unique(),
+        global_liquidity: rng.u128(),
+        sqrt_price_x96: rng.u128(),
+        current_tick: rng.i32(),
+        tick_spacing: [1, 10, 60, 200][rng.below(4) as usize],
+        bump: rng.next_u64() as u8,
+        fee_growth_global_0_x64: rng.u128(),
+        fee_growth_global_1_x64: rng.u128(),
+        fee_rate: rng.below(clmm::MAX_FEE_RATE as u64 + 1) as u32,
+        protocol_fee_rate: rng.below(clmm::MAX_PROTOCOL_FEE_RATE as u64 + 1) as u16,
+        protocol_fees_owed_0: rng.u64(),
+        protocol_fees_owed_1: rng.u64(),
+        pending_change,
+        status: if rng.below(4) == 0 {
+            PoolStatus::WithdrawOnly
+        } else {
+            PoolStatus::Active
+        },
+        flash_loan: rng.bool().then(|| OutstandingFlashLoan {
+            amount_0: rng.next_u64(),
+            amount_1: rng.next_u64(),
+            fee_0: rng.next_u64(),
+            fee_1: rng.next_u64(),
+        }),
+    }
+}
+
+fn snapshot_of(pool: &Pool) -> PoolSnapshot {
+    let mut data = Vec::new();
+    pool.try_serialize(&mut data).unwrap();
+    PoolSnapshot::decode(&data).unwrap()
+}
+
+// A plain SPL Token mint, so compute_swap runs without transfer fees or sysvars.
+fn with_mint<R>(f: impl FnOnce(&InterfaceAccount<Mint>) -> R) -> R {
+    let key = Pubkey::new_unique();
+    let owner = spl_token::ID;
+    let mut lamports = 0;
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint {
+        decimals: 6,
+        is_initialized: true,
+        ..Default::default()
+    }
+    .pack_into_slice(&mut data);
+    let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+    let mint = InterfaceAccount::<Mint>::try_from(&info).unwrap();
+    f(&mint)
+}
+
+fn to_engine_quote(quote: clmm::SwapQuote) -> SwapQuote {
+    SwapQuote {
+        amount_in: quote.amount_in,
+        amount_out: quote.amount_out,
+        fee_amount: quote.fee_amount,
+        sqrt_price_x96: quote.sqrt_price_x96,
+        tick: quote.tick,
+        ticks_crossed: quote.ticks_crossed,
+        price_impact: quote.price_impact,
+    }
+}
+
+#[test]
+fn discriminators_match_program() {
+    assert_eq!(POOL_DISCRIMINATOR, Pool::DISCRIMINATOR);
+    assert_eq!(POSITION_DISCRIMINATOR, Position::DISCRIMINATOR);
+    assert_eq!(TICK_ARRAY_DISCRIMINATOR, TickArray::DISCRIMINATOR);
+}
+
+#[test]
+fn tick_and_price_math() {
+    let mut rng = Rng(1);
+    for _ in 0..ITERATIONS {
+        let tick = rng.i32();
+        assert_parity(clmm::get_sqrt_price_from_tick(tick), math::get_sqrt_price_from_tick(tick), tick);
+
+        let sqrt_price = rng.u128();
+        assert_parity(clmm::get_tick_at_sqrt_price(sqrt_price), math::get_tick_at_sqrt_price(sqrt_price), sqrt_price);
+
+        let (before, after) = (rng.u128(), rng.u128());
+        assert_parity(
+            clmm::get_price_impact(before, after),
+            math::get_price_impact(before, after),
+            (before, after),
+        );
+    }
+}
+
+#[test]
+fn liquidity_and_fee_math() {
+    let mut rng = Rng(2);
+    for _ in 0..ITERATIONS {
+        let inputs = (rng.u128(), rng.u128(), rng.u128(), rng.u128());
+        assert_parity(
+            clmm::get_amounts_for_liquidity(inputs.0, inputs.1, inputs.2, inputs.3),
+            math::get_amounts_for_liquidity(inputs.0, inputs.1, inputs.2, inputs.3),
+            inputs,
+        );
+
+        let inputs = (rng.u128(), rng.u128(), rng.u128());
+        assert_parity(
+            clmm::get_fees_owed(inputs.0, inputs.1, inputs.2),
+            math::get_fees_owed(inputs.0, inputs.1, inputs.2),
+            inputs,
+        );
+
+        let inputs = (rng.u64(), rng.u128());
+        assert_parity(
+            clmm::get_fee_growth_delta_x64(inputs.0, inputs.1),
+            math::get_fee_growth_delta_x64(inputs.0, inputs.1),
+            inputs,
+        );
+
+        let inputs = (rng.u64(), rng.next_u64() as u32);
+        assert_parity(
+            clmm::get_fee_amount(inputs.0, inputs.1),
+            math::get_fee_amount(inputs.0, inputs.1),
+            inputs,
+        );
+
+        let inputs = (rng.u128(), rng.u128(), rng.u64(), rng.below(1_000_001) as u32, rng.bool());
+        assert_parity(
+            clmm::swap_segment(inputs.0, inputs.1, inputs.2, inputs.3, inputs.4),
+            math::swap_segment(inputs.0, inputs.1, inputs.2, inputs.3, inputs.4),
+            inputs,
+        );
+    }
+}
+
+#[test]
+fn transfer_fee_math() {
+    let mut rng = Rng(3);
+    for _ in 0..ITERATIONS {
+        let basis_points = match rng.below(4) {
+            0 => [0, 1, 9_999, 10_000][rng.below(4) as usize],
+            _ => rng.below(10_001) as u16,
+        };
+        let maximum_fee = rng.u64();
+        let spl = SplTransferFee {
+            epoch: 0.into(),
+            maximum_fee: maximum_fee.into(),
+            transfer_fee_basis_points: basis_points.into(),
+        };
+        let engine = TransferFee {
+            transfer_fee_basis_points: basis_points,
+            maximum_fee,
+        };
+        let amount = rng.u64();
+        let inputs = (basis_points, maximum_fee, amount);
+        assert_eq!(spl.calculate_fee(amount), engine.calculate_fee(amount), "inputs: {inputs:?}");
+        assert_eq!(
+            spl.calculate_pre_fee_amount(amount),
+            engine.calculate_pre_fee_amount(amount),
+            "inputs: {inputs:?}"
+        );
+    }
+}
+
+#[test]
+fn decodes_snapshots() {
+    let mut rng = Rng(4);
+    for _ in 0..ITERATIONS / 10 {
+        let pool = random_pool(&mut rng);
+        let snapshot = snapshot_of(&pool);
+        assert_eq!(snapshot.token_mint_0, pool.token_mint_0.to_bytes());
+        assert_eq!(snapshot.token_vault_1, pool.token_vault_1.to_bytes());
+        assert_eq!(snapshot.global_liquidity, pool.global_liquidity);
+        assert_eq!(snapshot.sqrt_price_x96, pool.sqrt_price_x96);
+        assert_eq!(snapshot.current_tick, pool.current_tick);
+        assert_eq!(snapshot.tick_spacing, pool.tick_spacing);
+        assert_eq!(snapshot.fee_growth_global_0_x64, pool.fee_growth_global_0_x64);
+        assert_eq!(snapshot.fee_growth_global_1_x64, pool.fee_growth_global_1_x64);
+        assert_eq!(snapshot.fee_rate, pool.fee_rate);
+        assert_eq!(snapshot.protocol_fee_rate, pool.protocol_fee_rate);
+        assert_eq!(snapshot.protocol_fees_owed_0, pool.protocol_fees_owed_0);
+        assert_eq!(snapshot.protocol_fees_owed_1, pool.protocol_fees_owed_1);
+        assert_eq!(snapshot.is_active, pool.status == PoolStatus::Active);
+
+        let position = Position {
+            liquidity: rng.u128(),
+            tick_lower: rng.i32(),
+            tick_upper: rng.i32(),
+            owner: Pubkey::new_unique(),
+            pool: Pubkey::new_unique(),
+            bump: rng.next_u64() as u8,
+            fee_growth_inside_0_last_x64: rng.u128(),
+            fee_growth_inside_1_last_x64: rng.u128(),
+            tokens_owed_0: rng.u64(),
+            tokens_owed_1: rng.u64(),
+        };
+        let mut data = Vec::new();
+        position.try_serialize(&mut data).unwrap();
+        let snapshot = PositionSnapshot::decode(&data).unwrap();
+        assert_eq!(
+            snapshot,
+            PositionSnapshot {
+                liquidity: position.liquidity,
+                tick_lower: position.tick_lower,
+                tick_upper: position.tick_upper,
+                owner: position.owner.to_bytes(),
+                pool: position.pool.to_bytes(),
+                fee_growth_inside_0_last_x64: position.fee_growth_inside_0_last_x64,
+                fee_growth_inside_1_last_x64: position.fee_growth_inside_1_last_x64,
+                tokens_owed_0: position.tokens_owed_0,
+                tokens_owed_1: position.tokens_owed_1,
+            }
+        );
+    }
+
+    let mut tick_array = TickArray {
+        pool: Pubkey::new_unique(),
+        starting_tick: -1800,
+        bump: 255,
+        ..TickArray::default()
+    };
+    for tick in &mut tick_array.ticks {
+        *tick = TickInfo {
+            liquidity_gross_lower: rng.next_u64(),
+            liquidity_gross_upper: rng.next_u64(),
+            liquidity_net_lower: rng.next_u64(),
+            liquidity_net_upper: rng.next_u64(),
+            initialized: rng.below(2),
+        };
+    }
+    let mut data = TickArray::DISCRIMINATOR.to_vec();
+    data.extend_from_slice(bytemuck::bytes_of(&tick_array));
+    let snapshot = TickArraySnapshot::decode(&data).unwrap();
+    assert_eq!(snapshot.pool, tick_array.pool.to_bytes());
+    assert_eq!(snapshot.starting_tick, tick_array.starting_tick);
+    for (engine, program) in snapshot.ticks.iter().zip(tick_array.ticks.iter()) {
+        assert_eq!(tick_fields(engine), program_tick_fields(program));
+    }
+
+    assert_eq!(PoolSnapshot::decode(&data), Err(QuoteError::AccountDiscriminatorMismatch));
+    assert_eq!(
+        TickArraySnapshot::decode(&data[..data.len() - 1]),
+        Err(QuoteError::AccountDidNotDeserialize)
+    );
+}
+
+#[test]
+fn swap_quotes_and_pool_updates() {
+    let mut rng = Rng(5);
+    with_mint(|mint| {
+        for _ in 0..ITERATIONS {
+            let mut pool = random_pool(&mut rng);
+            let mut snapshot = snapshot_of(&pool);
+            let amount_in = rng.u64();
+            let swap_token_0_for_1 = rng.bool();
+            let inputs = (snapshot.clone(), amount_in, swap_token_0_for_1);
+
+            let program = clmm::compute_swap(&pool, mint, amount_in, swap_token_0_for_1);
+            let engine = clmm_quote::quote_swap(&snapshot, &PoolTransferFees::default(), amount_in, swap_token_0_for_1);
+            // Withdraw-only pools are rejected before any math runs.
+            let withdraw_only = pool.status == PoolStatus::WithdrawOnly;
+            assert_eq!(withdraw_only, engine == Err(QuoteError::PoolWithdrawOnly), "inputs: {inputs:?}");
+            let program = program.map(to_engine_quote);
+            let quote = match (&program, &engine) {
+                (Ok(quote), Ok(_)) => *quote,
+                _ => {
+                    assert_parity(program, engine, &inputs);
+                    continue;
+                }
+            };
+            assert_parity(program, engine, &inputs);
+
+            let program = pool.accrue_swap_fee(quote.fee_amount, swap_token_0_for_1).map(|()| {
+                pool.sqrt_price_x96 = quote.sqrt_price_x96;
+                pool.current_tick = quote.tick;
+            });
+            let engine = clmm_quote::apply_swap(&mut snapshot, &quote, swap_token_0_for_1);
+            assert_parity(program, engine, quote);
+            assert_eq!(snapshot, snapshot_of(&pool), "quote: {quote:?}");
+        }
+    });
+}
+
+#[test]
+fn swap_quotes_with_transfer_fee_gross_up() {
+    let fee = TransferFee {
+        transfer_fee_basis_points: 100,
+        maximum_fee: 1_000_000,
+    };
+    let transfer_fees = PoolTransferFees {
+        token_0: Some(fee),
+        token_1: None,
+    };
+    let mut snapshot = snapshot_of(&random_pool(&mut Rng(6)));
+    snapshot.is_active = true;
+    snapshot.global_liquidity = 1_000_000;
+    snapshot.sqrt_price_x96 = 1 << 96;
+    snapshot.fee_rate = 1_000;
+
+    let quote = clmm_quote::quote_swap(&snapshot, &transfer_fees, 10_000, true).unwrap();
+    // 1% of 10_000 is withheld by the mint; the pool fee applies to the 9_900 that arrives.
+    assert_eq!(quote.amount_in, 10_000);
+    assert_eq!(quote.fee_amount, 9);
+    assert_eq!(quote.amount_out, 9_891);
+
+    // The fee rounds up, so nothing would arrive.
+    assert_eq!(
+        clmm_quote::quote_swap(&snapshot, &transfer_fees, 1, true),
+        Err(QuoteError::InsufficientInputAmount)
+    );
+    // The output side has no transfer fee.
+    assert_eq!(clmm_quote::quote_swap(&snapshot, &transfer_fees, 10_000, false).unwrap().amount_out, 9_990);
+}
+
+#[test]
+fn swap_quotes_charge_the_full_input_at_the_transfer_fee_cap() {
+    let fee = TransferFee {
+        transfer_fee_basis_points: 100,
+        maximum_fee: 2,
+    };
+    let transfer_fees = PoolTransferFees {
+        token_0: Some(fee),
+        token_1: None,
+    };
+    let mut snapshot = snapshot_of(&random_pool(&mut Rng(7)));
+    snapshot.is_active = true;
+    snapshot.global_liquidity = 1_000_000;
+    snapshot.sqrt_price_x96 = 1 << 96;
+    snapshot.fee_rate = 1_000;
+
+    // The capped fee of 2 leaves 99, and 100 would also deliver 99 (its fee is 1), but the
+    // user sends 101 and must be quoted 101.
+    assert_eq!(fee.calculate_pre_fee_amount(99), Some(100));
+    let quote = clmm_quote::quote_swap(&snapshot, &transfer_fees, 101, true).unwrap();
+    assert_eq!(quote.amount_in, 101);
+    assert_eq!(quote.amount_out, 99);
+}
+
+fn tick_fields(tick: &TickSnapshot) -> [u64; 5] {
+    [
+        tick.liquidity_gross_lower,
+        tick.liquidity_gross_upper,
+        tick.liquidity_net_lower,
+        tick.liquidity_net_upper,
+        tick.initialized,
+    ]
+}
+
+fn program_tick_fields(tick: &TickInfo) -> [u64; 5] {
+    [
+        tick.liquidity_gross_lower,
+        tick.liquidity_gross_upper,
+        tick.liquidity_net_lower,
+        tick.liquidity_net_upper,
+        tick.initialized,
+    ]
+}
+
+#[test]
+fn tick_liquidity_updates() {
+    let mut rng = Rng(7);
+    for _ in 0..ITERATIONS / 10 {
+        let mut program = TickInfo::default();
+        let mut engine = TickSnapshot::default();
+        for _ in 0..20 {
+            let delta = match rng.below(3) {
+                0 => rng.below(1_000_000) as i128,
+                1 => rng.u128() as i128,
+                _ => -(rng.below(1_000_000) as i128),
+            };
+            let is_lower = rng.bool();
+            let before = tick_fields(&engine);
+            if rng.bool() {
+                assert_parity(
+                    program.update_liquidity(delta, is_lower),
+                    engine.update_liquidity(delta, is_lower),
+                    (before, delta, is_lower),
+                );
+            } else {
+                assert_parity(
+                    program.update_liquidity_decrease(delta, is_lower),
+                    engine.update_liquidity_decrease(delta, is_lower),
+                    (before, delta, is_lower),
+                );
+            }
+            assert_eq!(tick_fields(&engine), program_tick_fields(&program), "inputs: {before:?} {delta} {is_lower}");
+        }
+    }
+
+    for _ in 0..ITERATIONS {
+        let tick_spacing = [1, 10, 60, 200][rng.below(4) as usize];
+        let starting_tick = (rng.below(200) as i32 - 100) * tick_spacing * clmm::TICKS_PER_ARRAY as i32;
+        let mut program = TickArray {
+            starting_tick,
+            ..TickArray::default()
+        };
+        let mut data = TickArray::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(bytemuck::bytes_of(&program));
+        let mut engine = TickArraySnapshot::decode(&data).unwrap();
+
+        let tick = starting_tick + (rng.below(80) as i32 - 20) * tick_spacing + rng.below(2) as i32;
+        let inputs = (starting_tick, tick, tick_spacing);
+        assert_eq!(
+            program.contains_tick(tick, tick_spacing),
+            engine.contains_tick(tick, tick_spacing),
+            "inputs: {inputs:?}"
+        );
+        let program = program.get_tick_info_mutable(tick, tick_spacing).map(|info| {
+            info.initialized = 1;
+        });
+        let engine = engine.get_tick_info_mutable(tick, tick_spacing).map(|info| {
+            info.initialized = 1;
+        });
+        assert_parity(program, engine, inputs);
+    }
+}
+
+#[test]
+fn initialized_ticks_crossed() {
+    let mut rng = Rng(9);
+    for _ in 0..ITERATIONS / 10 {
+        let tick_spacing = [1, 10, 60, 200][rng.below(4) as usize];
+        let ticks_per_array = tick_spacing * clmm::TICKS_PER_ARRAY as i32;
+        let first_start = (rng.below(20) as i32 - 10) * ticks_per_array;
+        let mut program = Vec::new();
+        let mut engine = Vec::new();
+        let mut initialized = Vec::new();
+        for array_index in 0..4 {
+            let mut tick_array = TickArray {
+                starting_tick: first_start + array_index * ticks_per_array,
+                ..TickArray::default()
+            };
+            for (offset, tick) in tick_array.ticks.iter_mut().enumerate() {
+                if rng.below(4) == 0 {
+                    tick.initialized = 1;
+                    initialized.push(tick_array.starting_tick + offset as i32 * tick_spacing);
+                }
+            }
+            let mut data = TickArray::DISCRIMINATOR.to_vec();
+            data.extend_from_slice(bytemuck::bytes_of(&tick_array));
+            engine.push(TickArraySnapshot::decode(&data).unwrap());
+            program.push(tick_array);
+        }
+
+        let span = 4 * ticks_per_array as u64;
+        let from_tick = first_start + rng.below(span) as i32;
+        let to_tick = first_start + rng.below(span) as i32;
+        let inputs = (tick_spacing, from_tick, to_tick);
+        let start_indexes = clmm::get_crossed_tick_array_start_indexes(tick_spacing, from_tick, to_tick);
+        assert_eq!(
+            start_indexes,
+            clmm_quote::get_crossed_tick_array_start_indexes(tick_spacing, from_tick, to_tick),
+            "inputs: {inputs:?}"
+        );
+        for tick in &initialized {
+            let crossed = from_tick.min(to_tick) < *tick && *tick <= from_tick.max(to_tick);
+            let start_index = tick.div_euclid(ticks_per_array) * ticks_per_array;
+            assert!(!crossed || start_indexes.contains(&start_index), "inputs: {inputs:?} tick: {tick}");
+        }
+
+        let expected = initialized
+            .iter()
+            .filter(|tick| from_tick.min(to_tick) < **tick && **tick <= from_tick.max(to_tick))
+            .count() as u32;
+        assert_eq!(
+            clmm::count_initialized_ticks_crossed(&program, tick_spacing, from_tick, to_tick),
+            expected,
+            "inputs: {inputs:?}"
+        );
+        assert_eq!(
+            clmm_quote::count_initialized_ticks_crossed(&engine, tick_spacing, from_tick, to_tick),
+            expected,
+            "inputs: {inputs:?}"
+        );
+    }
+}
+
+#[test]
+fn position_value_and_liquidity_quotes() {
+    let mut rng = Rng(8);
+    for _ in 0..ITERATIONS {
+        let pool = random_pool(&mut rng);
+        let snapshot = snapshot_of(&pool);
+        let tick_lower = rng.below(2_000) as i32 - 1_000;
+        let tick_upper = tick_lower + rng.below(2_000) as i32;
+        let mut position = Position {
+            liquidity: rng.u128() >> 64,
+            tick_lower,
+            tick_upper,
+            owner: Pubkey::new_unique(),
+            pool: Pubkey::new_unique(),
+            bump: 0,
+            fee_growth_inside_0_last_x64: rng.u128(),
+            fee_growth_inside_1_last_x64: rng.u128(),
+            tokens_owed_0: rng.u64() >> 1,
+            tokens_owed_1: rng.u64() >> 1,
+        };
+        let mut data = Vec::new();
+        position.try_serialize(&mut data).unwrap();
+        let position_snapshot = PositionSnapshot::decode(&data).unwrap();
+
+        // get_position_value is the amounts for the position's liquidity plus the fees
+        // update_fees would settle.
+        let program = clmm::get_amounts_for_liquidity(
+            pool.sqrt_price_x96,
+            clmm::get_sqrt_price_from_tick(tick_lower).unwrap(),
+            clmm::get_sqrt_price_from_tick(tick_upper).unwrap(),
+            position.liquidity,
+        )
+        .and_then(|(amount_0, amount_1)| {
+            position.update_fees(pool.fee_growth_global_0_x64, pool.fee_growth_global_1_x64)?;
+            Ok((amount_0, amount_1, position.tokens_owed_0, position.tokens_owed_1))
+        });
+        let engine = clmm_quote::get_position_value(&snapshot, &position_snapshot)
+            .map(|value| (value.amount_0, value.amount_1, value.fees_owed_0, value.fees_owed_1));
+        assert_parity(program, engine, (&snapshot, &position_snapshot));
+
+        let liquidity_amount = rng.u128() >> 64;
+        let program = || {
+            clmm::get_amounts_for_liquidity(
+                pool.sqrt_price_x96,
+                clmm::get_sqrt_price_from_tick(tick_lower).unwrap(),
+                clmm::get_sqrt_price_from_tick(tick_upper).unwrap(),
+                liquidity_amount,
+            )
+        };
+        if liquidity_amount > 0 {
+            let engine = clmm_quote::quote_increase_liquidity(
+                &snapshot,
+                &PoolTransferFees::default(),
+                tick_lower,
+                tick_upper,
+                liquidity_amount,
+            )
+            .map(|(transfer_0, transfer_1)| (transfer_0.gross_amount, transfer_1.gross_amount));
+            assert_parity(program(), engine, (&snapshot, liquidity_amount));
+        }
+        if liquidity_amount > 0 && liquidity_amount <= position_snapshot.liquidity {
+            let engine = clmm_quote::quote_decrease_liquidity(
+                &snapshot,
+                &PoolTransferFees::default(),
+                &position_snapshot,
+                liquidity_amount,
+            )
+            .map(|(transfer_0, transfer_1)| (transfer_0.net_amount, transfer_1.net_amount));
+            assert_parity(program(), engine, (&snapshot, liquidity_amount));
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Decoders for the program's events. Events are emitted through a self-CPI signed by the
+//! event authority PDA, so they survive log truncation: each one is an inner instruction to
+//! the program whose data is `EVENT_IX_TAG_LE`, the event discriminator and the borsh body.
+
+use anchor_lang::error::ErrorCode;
+use anchor_lang::event::EVENT_IX_TAG_LE;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AnchorDeserialize, Discriminator, Result};
+
+pub use clmm::{
+    AuthorityTransferStarted, AuthorityTransferred, FeesCollected, FlashLoanRepaid, FlashLoanTaken,
+    GlobalConfigInitialized, LiquidityDecreased, LiquidityIncreased, PauseFlagsChanged, PendingChangeCancelled,
+    PendingChangeExecuted, PendingChangeQueued, PoolInitialized, PoolStatusChanged, PositionBundleInitialized,
+    PositionOpened, ProtocolFeesCollected, RewardsFunded, RoleChanged, Swapped,
+};
+
+use crate::pda::find_event_authority_address;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClmmEvent {
+    PendingChangeQueued(PendingChangeQueued),
+    PendingChangeExecuted(PendingChangeExecuted),
+    PendingChangeCancelled(PendingChangeCancelled),
+    PoolInitialized(PoolInitialized),
+    PositionOpened(PositionOpened),
+    LiquidityIncreased(LiquidityIncreased),
+    LiquidityDecreased(LiquidityDecreased),
+    Swapped(Swapped),
+    FeesCollected(FeesCollected),
+    ProtocolFeesCollected(ProtocolFeesCollected),
+    RewardsFunded(RewardsFunded),
+    FlashLoanTaken(FlashLoanTaken),
+    FlashLoanRepaid(FlashLoanRepaid),
+    PoolStatusChanged(PoolStatusChanged),
+    PauseFlagsChanged(PauseFlagsChanged),
+    RoleChanged(RoleChanged),
+    AuthorityTransferStarted(AuthorityTransferStarted),
+    AuthorityTransferred(AuthorityTransferred),
+    PositionBundleInitialized(PositionBundleInitialized),
+    GlobalConfigInitialized(GlobalConfigInitialized),
+}
+
+/// Decodes an inner instruction from a transaction's metadata. Returns None for anything
+/// that isn't an event CPI from the program, so every inner instruction can be passed in.
+pub fn decode_event_instruction(program_id: &Pubkey, accounts: &[Pubkey], data: &[u8]) -> Result<Option<ClmmEvent>> {
+    if *program_id != crate::ID || accounts.first() != Some(&find_event_authority_address().0) {
+        return Ok(None);
+    }
+    decode_event(data)
+}
+
+/// Decodes event CPI instruction data. Returns None when the data doesn't carry the event tag.
+pub fn decode_event(data: &[u8]) -> Result<Option<ClmmEvent>> {
+    let Some(event) = data.strip_prefix(EVENT_IX_TAG_LE) else {
+        return Ok(None);
+    };
+
+    let event = if let Some(body) = event.strip_prefix(PendingChangeQueued::DISCRIMINATOR) {
+        ClmmEvent::PendingChangeQueued(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(PendingChangeExecuted::DISCRIMINATOR) {
+        ClmmEvent::PendingChangeExecuted(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(PendingChangeCancelled::DISCRIMINATOR) {
+        ClmmEvent::PendingChangeCancelled(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(PoolInitialized::DISCRIMINATOR) {
+        ClmmEvent::PoolInitialized(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(PositionOpened::DISCRIMINATOR) {
+        ClmmEvent::PositionOpened(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(LiquidityIncreased::DISCRIMINATOR) {
+        ClmmEvent::LiquidityIncreased(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(LiquidityDecreased::DISCRIMINATOR) {
+        ClmmEvent::LiquidityDecreased(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(Swapped::DISCRIMINATOR) {
+        ClmmEvent::Swapped(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(FeesCollected::DISCRIMINATOR) {
+        ClmmEvent::FeesCollected(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(ProtocolFeesCollected::DISCRIMINATOR) {
+        ClmmEvent::ProtocolFeesCollected(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(RewardsFunded::DISCRIMINATOR) {
+        ClmmEvent::RewardsFunded(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(FlashLoanTaken::DISCRIMINATOR) {
+        ClmmEvent::FlashLoanTaken(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(FlashLoanRepaid::DISCRIMINATOR) {
+        ClmmEvent::FlashLoanRepaid(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(PoolStatusChanged::DISCRIMINATOR) {
+        ClmmEvent::PoolStatusChanged(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(PauseFlagsChanged::DISCRIMINATOR) {
+        ClmmEvent::PauseFlagsChanged(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(RoleChanged::DISCRIMINATOR) {
+        ClmmEvent::RoleChanged(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(AuthorityTransferStarted::DISCRIMINATOR) {
+        ClmmEvent::AuthorityTransferStarted(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(AuthorityTransferred::DISCRIMINATOR) {
+        ClmmEvent::AuthorityTransferred(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(PositionBundleInitialized::DISCRIMINATOR) {
+        ClmmEvent::PositionBundleInitialized(deserialize(body)?)
+    } else if let Some(body) = event.strip_prefix(GlobalConfigInitialized::DISCRIMINATOR) {
+        ClmmEvent::GlobalConfigInitialized(deserialize(body)?)
+    } else {
+        return Err(ErrorCode::InstructionDidNotDeserialize.into());
+    };
+
+    Ok(Some(event))
+}
+
+fn deserialize<T: AnchorDeserialize>(body: &[u8]) -> Result<T> {
+    T::try_from_slice(body).map_err(|_| ErrorCode::InstructionDidNotDeserialize.into())
+}
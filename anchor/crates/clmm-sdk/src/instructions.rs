@@ -0,0 +1,949 @@
+//! Instruction builders. Each one takes the keys that can't be derived and fills in the
+//! rest (global config, PDAs, vaults, sysvars) using the program's own account structs.
+
+use anchor_lang::prelude::{AccountMeta, Pubkey};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::sysvar::{instructions as instructions_sysvar, rent};
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use anchor_spl::token::spl_token::native_mint;
+use clmm::{accounts, instruction};
+
+use crate::pda::{
+    find_event_authority_address, find_global_config_address, find_pool_address, find_position_address,
+    find_position_bundle_address, find_program_data_address, find_tick_array_address, find_tick_array_address_for_tick,
+    find_token_vault_address, find_wsol_address, get_tick_array_start_index,
+};
+use crate::{ExpectedSqrtPrice, Pool, PoolStatus, Role};
+
+/// Every address that identifies a pool and its token sides.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolKeys {
+    pub pool: Pubkey,
+    pub tick_spacing: i32,
+    pub token_mint_0: Pubkey,
+    pub token_mint_1: Pubkey,
+    pub token_vault_0: Pubkey,
+    pub token_vault_1: Pubkey,
+    pub token_program_0: Pubkey,
+    pub token_program_1: Pubkey,
+}
+
+impl PoolKeys {
+    /// Derives the pool and vault addresses for an ordered mint pair.
+    pub fn derive(
+        token_mint_0: Pubkey,
+        token_mint_1: Pubkey,
+        tick_spacing: i32,
+        token_program_0: Pubkey,
+        token_program_1: Pubkey,
+    ) -> Self {
+        let (pool, _) = find_pool_address(&token_mint_0, &token_mint_1, tick_spacing);
+        Self {
+            pool,
+            tick_spacing,
+            token_mint_0,
+            token_mint_1,
+            token_vault_0: find_token_vault_address(&pool, &token_mint_0).0,
+            token_vault_1: find_token_vault_address(&pool, &token_mint_1).0,
+            token_program_0,
+            token_program_1,
+        }
+    }
+
+    /// Token programs aren't stored in `Pool`; they are the owners of the two mint accounts.
+    pub fn from_pool(pool: Pubkey, state: &Pool, token_program_0: Pubkey, token_program_1: Pubkey) -> Self {
+        Self {
+            pool,
+            tick_spacing: state.tick_spacing,
+            token_mint_0: state.token_mint_0,
+            token_mint_1: state.token_mint_1,
+            token_vault_0: state.token_vault_0,
+            token_vault_1: state.token_vault_1,
+            token_program_0,
+            token_program_1,
+        }
+    }
+}
+
+/// A position and the tick arrays holding its bounds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PositionKeys {
+    pub position: Pubkey,
+    pub lower_tick_array: Pubkey,
+    pub upper_tick_array: Pubkey,
+}
+
+impl PositionKeys {
+    pub fn derive(pool: &PoolKeys, owner: &Pubkey, tick_lower: i32, tick_upper: i32) -> Self {
+        Self {
+            position: find_position_address(owner, &pool.pool, tick_lower, tick_upper).0,
+            lower_tick_array: find_tick_array_address_for_tick(&pool.pool, tick_lower, pool.tick_spacing).0,
+            upper_tick_array: find_tick_array_address_for_tick(&pool.pool, tick_upper, pool.tick_spacing).0,
+        }
+    }
+}
+
+/// The caller's token accounts for token 0 and token 1 of a pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UserTokenAccounts {
+    pub token_0: Pubkey,
+    pub token_1: Pubkey,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpenPositionParams {
+    pub owner: Pubkey,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity_amount: u128,
+    pub amount_0_max: u64,
+    pub amount_1_max: u64,
+    pub deadline: Option<i64>,
+    pub expected_sqrt_price: Option<ExpectedSqrtPrice>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecreaseLiquidityParams {
+    pub liquidity_amount: u128,
+    pub amount_0_min: u64,
+    pub amount_1_min: u64,
+    pub deadline: Option<i64>,
+    pub expected_sqrt_price: Option<ExpectedSqrtPrice>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpenBundledPositionParams {
+    pub bundle_index: u16,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity_amount: u128,
+    pub amount_0_max: u64,
+    pub amount_1_max: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IncreaseBundledLiquidityParams {
+    pub bundle_index: u16,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity_amount: u128,
+    pub amount_0_max: u64,
+    pub amount_1_max: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecreaseBundledLiquidityParams {
+    pub bundle_index: u16,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity_amount: u128,
+    pub amount_0_min: u64,
+    pub amount_1_min: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapParams {
+    pub amount_in: u64,
+    pub swap_token_0_for_1: bool,
+    pub amount_out_minimum: u64,
+    pub deadline: Option<i64>,
+    pub expected_sqrt_price: Option<ExpectedSqrtPrice>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlashSwapParams {
+    pub amount_in: u64,
+    pub swap_token_0_for_1: bool,
+    pub amount_out_minimum: u64,
+    pub callback_program: Pubkey,
+    // Forwarded to the callback as remaining accounts, in order.
+    pub callback_accounts: Vec<AccountMeta>,
+    pub callback_data: Vec<u8>,
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: crate::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+fn global_config() -> Pubkey {
+    find_global_config_address().0
+}
+
+fn event_authority() -> Pubkey {
+    find_event_authority_address().0
+}
+
+// Native SOL sides may omit their user token account; the program then wraps and unwraps
+// through the payer's temporary wSOL account.
+fn wsol_account_for(payer: &Pubkey, pool: &PoolKeys, user_token_0: Option<Pubkey>, user_token_1: Option<Pubkey>) -> Option<Pubkey> {
+    let wraps_0 = user_token_0.is_none() && pool.token_mint_0 == native_mint::ID;
+    let wraps_1 = user_token_1.is_none() && pool.token_mint_1 == native_mint::ID;
+    (wraps_0 || wraps_1).then(|| find_wsol_address(payer).0)
+}
+
+pub fn initialize_global_config(payer: Pubkey, timelock_delay: i64) -> Instruction {
+    build(
+        accounts::InitializeGlobalConfig {
+            payer,
+            global_config: global_config(),
+            program_data: find_program_data_address().0,
+            system_program: system_program::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::InitializeGlobalConfig { timelock_delay },
+    )
+}
+
+pub fn transfer_authority(authority: Pubkey, new_authority: Pubkey) -> Instruction {
+    build(
+        accounts::TransferAuthority {
+            authority,
+            global_config: global_config(),
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::TransferAuthority { new_authority },
+    )
+}
+
+pub fn accept_authority(pending_authority: Pubkey) -> Instruction {
+    build(
+        accounts::AcceptAuthority {
+            pending_authority,
+            global_config: global_config(),
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::AcceptAuthority {},
+    )
+}
+
+pub fn grant_role(authority: Pubkey, role: Role, member: Pubkey) -> Instruction {
+    build(
+        accounts::ManageRole {
+            authority,
+            global_config: global_config(),
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::GrantRole { role, member },
+    )
+}
+
+pub fn revoke_role(authority: Pubkey, role: Role) -> Instruction {
+    build(
+        accounts::ManageRole {
+            authority,
+            global_config: global_config(),
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::RevokeRole { role },
+    )
+}
+
+pub fn set_pool_status(pauser: Pubkey, pool: Pubkey, status: PoolStatus) -> Instruction {
+    build(
+        accounts::SetPoolStatus {
+            pauser,
+            global_config: global_config(),
+            pool,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::SetPoolStatus { status },
+    )
+}
+
+pub fn set_pause_flags(pauser: Pubkey, pause_flags: u8) -> Instruction {
+    build(
+        accounts::SetPauseFlags {
+            pauser,
+            global_config: global_config(),
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::SetPauseFlags { pause_flags },
+    )
+}
+
+pub fn queue_fee_rate_change(fee_manager: Pubkey, pool: Pubkey, fee_rate: u32) -> Instruction {
+    build(
+        accounts::QueuePoolChange {
+            fee_manager,
+            global_config: global_config(),
+            pool,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::QueueFeeRateChange { fee_rate },
+    )
+}
+
+pub fn queue_protocol_fee_rate_change(fee_manager: Pubkey, pool: Pubkey, protocol_fee_rate: u16) -> Instruction {
+    build(
+        accounts::QueuePoolChange {
+            fee_manager,
+            global_config: global_config(),
+            pool,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::QueueProtocolFeeRateChange { protocol_fee_rate },
+    )
+}
+
+pub fn queue_timelock_delay_change(authority: Pubkey, timelock_delay: i64) -> Instruction {
+    build(
+        accounts::QueueConfigChange {
+            authority,
+            global_config: global_config(),
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::QueueTimelockDelayChange { timelock_delay },
+    )
+}
+
+/// `pool` is None for changes queued on the global config.
+pub fn execute_pending_change(pool: Option<Pubkey>) -> Instruction {
+    build(
+        accounts::ExecutePendingChange {
+            global_config: global_config(),
+            pool,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::ExecutePendingChange {},
+    )
+}
+
+pub fn cancel_pending_change(admin: Pubkey, pool: Option<Pubkey>) -> Instruction {
+    build(
+        accounts::CancelPendingChange {
+            admin,
+            global_config: global_config(),
+            pool,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::CancelPendingChange {},
+    )
+}
+
+pub fn collect_protocol_fees(fee_manager: Pubkey, pool: &PoolKeys, recipient: UserTokenAccounts) -> Instruction {
+    build(
+        accounts::CollectProtocolFees {
+            fee_manager,
+            global_config: global_config(),
+            pool: pool.pool,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            recipient_token_0: recipient.token_0,
+            recipient_token_1: recipient.token_1,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::CollectProtocolFees {},
+    )
+}
+
+pub fn fund_rewards(
+    reward_funder: Pubkey,
+    pool: &PoolKeys,
+    funder: UserTokenAccounts,
+    amount_0: u64,
+    amount_1: u64,
+) -> Instruction {
+    build(
+        accounts::FundRewards {
+            reward_funder,
+            global_config: global_config(),
+            pool: pool.pool,
+            funder_token_0: funder.token_0,
+            funder_token_1: funder.token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::FundRewards { amount_0, amount_1 },
+    )
+}
+
+pub fn initialize_pool(payer: Pubkey, pool: &PoolKeys, initial_sqrt_price: u128) -> Instruction {
+    build(
+        accounts::InitializePool {
+            global_config: global_config(),
+            payer,
+            pool: pool.pool,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            token_vault_0: pool.token_vault_0,
+            token_vault_1: pool.token_vault_1,
+            system_program: system_program::ID,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            rent: rent::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::InitializePool {
+            tick_spacing: pool.tick_spacing,
+            initial_sqrt_price,
+        },
+    )
+}
+
+/// A None user token account on a native SOL side is wrapped from the payer's lamports.
+pub fn open_position(
+    payer: Pubkey,
+    pool: &PoolKeys,
+    user_token_0: Option<Pubkey>,
+    user_token_1: Option<Pubkey>,
+    params: OpenPositionParams,
+) -> Instruction {
+    let position = PositionKeys::derive(pool, &params.owner, params.tick_lower, params.tick_upper);
+    build(
+        accounts::OpenPosition {
+            global_config: global_config(),
+            pool: pool.pool,
+            lower_tick_array: position.lower_tick_array,
+            upper_tick_array: position.upper_tick_array,
+            position: position.position,
+            user_token_0,
+            user_token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            payer,
+            wsol_account: wsol_account_for(&payer, pool, user_token_0, user_token_1),
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            system_program: system_program::ID,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            rent: rent::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::OpenPosition {
+            owner: params.owner,
+            lower_tick: params.tick_lower,
+            upper_tick: params.tick_upper,
+            liquidity_amount: params.liquidity_amount,
+            _tick_array_lower_start_index: get_tick_array_start_index(params.tick_lower, pool.tick_spacing),
+            _tick_array_upper_start_index: get_tick_array_start_index(params.tick_upper, pool.tick_spacing),
+            amount_0_max: params.amount_0_max,
+            amount_1_max: params.amount_1_max,
+            deadline: params.deadline,
+            expected_sqrt_price: params.expected_sqrt_price,
+        },
+    )
+}
+
+pub fn increase_liquidity(
+    payer: Pubkey,
+    pool: &PoolKeys,
+    position: &PositionKeys,
+    user: UserTokenAccounts,
+    liquidity_amount: u128,
+    amount_0_max: u64,
+    amount_1_max: u64,
+) -> Instruction {
+    build(
+        accounts::IncreaseLiquidity {
+            global_config: global_config(),
+            pool: pool.pool,
+            lower_tick_array: position.lower_tick_array,
+            upper_tick_array: position.upper_tick_array,
+            position: position.position,
+            user_token_0: user.token_0,
+            user_token_1: user.token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            payer,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            system_program: system_program::ID,
+            rent: rent::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::IncreaseLiquidity {
+            liquidity_amount,
+            amount_0_max,
+            amount_1_max,
+        },
+    )
+}
+
+pub fn decrease_liquidity(
+    payer: Pubkey,
+    pool: &PoolKeys,
+    position: &PositionKeys,
+    user: UserTokenAccounts,
+    params: DecreaseLiquidityParams,
+) -> Instruction {
+    build(
+        accounts::DecreaseLiquidity {
+            global_config: global_config(),
+            payer,
+            pool: pool.pool,
+            lower_tick_array: position.lower_tick_array,
+            upper_tick_array: position.upper_tick_array,
+            position: position.position,
+            user_token_0: user.token_0,
+            user_token_1: user.token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            system_program: system_program::ID,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            rent: rent::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::DecreaseLiquidity {
+            liquidity_amount: params.liquidity_amount,
+            amount_0_min: params.amount_0_min,
+            amount_1_min: params.amount_1_min,
+            deadline: params.deadline,
+            expected_sqrt_price: params.expected_sqrt_price,
+        },
+    )
+}
+
+pub fn emergency_withdraw(
+    payer: Pubkey,
+    pool: &PoolKeys,
+    position: &PositionKeys,
+    user: UserTokenAccounts,
+) -> Instruction {
+    build(
+        accounts::EmergencyWithdraw {
+            global_config: global_config(),
+            payer,
+            pool: pool.pool,
+            lower_tick_array: Some(position.lower_tick_array),
+            upper_tick_array: Some(position.upper_tick_array),
+            position: position.position,
+            user_token_0: user.token_0,
+            user_token_1: user.token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::EmergencyWithdraw {},
+    )
+}
+
+/// A None user token account on a native SOL side is wrapped or unwrapped through the payer's
+/// temporary wSOL account.
+pub fn swap(
+    payer: Pubkey,
+    pool: &PoolKeys,
+    user_token_0: Option<Pubkey>,
+    user_token_1: Option<Pubkey>,
+    params: SwapParams,
+) -> Instruction {
+    build(
+        accounts::Swap {
+            global_config: global_config(),
+            pool: pool.pool,
+            user_token_0,
+            user_token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            payer,
+            wsol_account: wsol_account_for(&payer, pool, user_token_0, user_token_1),
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            system_program: system_program::ID,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            rent: rent::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::Swap {
+            amount_in: params.amount_in,
+            swap_token_0_for_1: params.swap_token_0_for_1,
+            amount_out_minimum: params.amount_out_minimum,
+            deadline: params.deadline,
+            expected_sqrt_price: params.expected_sqrt_price,
+        },
+    )
+}
+
+/// Hops are taken in order; each pool's direction follows from the mint coming out of the
+/// previous hop, starting with `user_token_in`'s mint.
+pub fn swap_route(
+    payer: Pubkey,
+    user_token_in: Pubkey,
+    user_token_out: Pubkey,
+    hops: &[PoolKeys],
+    amount_in: u64,
+    amount_out_minimum: u64,
+) -> Instruction {
+    let mut ix = build(
+        accounts::SwapRoute {
+            global_config: global_config(),
+            user_token_in,
+            user_token_out,
+            payer,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::SwapRoute {
+            amount_in,
+            amount_out_minimum,
+            hop_count: hops.len() as u8,
+        },
+    );
+    for hop in hops {
+        ix.accounts.extend([
+            AccountMeta::new(hop.pool, false),
+            AccountMeta::new(hop.token_vault_0, false),
+            AccountMeta::new(hop.token_vault_1, false),
+            AccountMeta::new_readonly(hop.token_mint_0, false),
+            AccountMeta::new_readonly(hop.token_mint_1, false),
+            AccountMeta::new_readonly(hop.token_program_0, false),
+            AccountMeta::new_readonly(hop.token_program_1, false),
+        ]);
+    }
+    ix
+}
+
+/// Must be followed by [`flash_repay`] for the same pool in the same transaction.
+pub fn flash_loan(payer: Pubkey, pool: &PoolKeys, user: UserTokenAccounts, amount_0: u64, amount_1: u64) -> Instruction {
+    build(
+        accounts::FlashLoan {
+            global_config: global_config(),
+            pool: pool.pool,
+            user_token_0: user.token_0,
+            user_token_1: user.token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            payer,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            instructions: instructions_sysvar::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::FlashLoan { amount_0, amount_1 },
+    )
+}
+
+pub fn flash_repay(payer: Pubkey, pool: &PoolKeys, user: UserTokenAccounts) -> Instruction {
+    build(
+        accounts::FlashRepay {
+            pool: pool.pool,
+            user_token_0: user.token_0,
+            user_token_1: user.token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            payer,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::FlashRepay {},
+    )
+}
+
+pub fn flash_swap(payer: Pubkey, pool: &PoolKeys, user: UserTokenAccounts, params: FlashSwapParams) -> Instruction {
+    let mut ix = build(
+        accounts::FlashSwap {
+            global_config: global_config(),
+            pool: pool.pool,
+            user_token_0: user.token_0,
+            user_token_1: user.token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            payer,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            callback_program: params.callback_program,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::FlashSwap {
+            amount_in: params.amount_in,
+            swap_token_0_for_1: params.swap_token_0_for_1,
+            amount_out_minimum: params.amount_out_minimum,
+            callback_data: params.callback_data,
+        },
+    );
+    ix.accounts.extend(params.callback_accounts);
+    ix
+}
+
+pub fn collect_fees(payer: Pubkey, pool: &PoolKeys, position: Pubkey, user: UserTokenAccounts) -> Instruction {
+    build(
+        accounts::CollectFees {
+            global_config: global_config(),
+            pool: pool.pool,
+            position,
+            user_token_0: user.token_0,
+            user_token_1: user.token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            payer,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::CollectFees {},
+    )
+}
+
+pub fn initialize_position_bundle(payer: Pubkey, pool: Pubkey) -> Instruction {
+    build(
+        accounts::InitializePositionBundle {
+            global_config: global_config(),
+            payer,
+            pool,
+            position_bundle: find_position_bundle_address(&payer, &pool).0,
+            system_program: system_program::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::InitializePositionBundle {},
+    )
+}
+
+pub fn open_bundled_position(
+    payer: Pubkey,
+    pool: &PoolKeys,
+    user: UserTokenAccounts,
+    params: OpenBundledPositionParams,
+) -> Instruction {
+    let OpenBundledPositionParams {
+        bundle_index,
+        tick_lower,
+        tick_upper,
+        ..
+    } = params;
+    let tick_array_lower_start_index = get_tick_array_start_index(tick_lower, pool.tick_spacing);
+    let tick_array_upper_start_index = get_tick_array_start_index(tick_upper, pool.tick_spacing);
+    build(
+        accounts::OpenBundledPosition {
+            global_config: global_config(),
+            pool: pool.pool,
+            lower_tick_array: find_tick_array_address_for_tick(&pool.pool, tick_lower, pool.tick_spacing).0,
+            upper_tick_array: find_tick_array_address_for_tick(&pool.pool, tick_upper, pool.tick_spacing).0,
+            position_bundle: find_position_bundle_address(&payer, &pool.pool).0,
+            user_token_0: user.token_0,
+            user_token_1: user.token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            payer,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            system_program: system_program::ID,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            rent: rent::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::OpenBundledPosition {
+            bundle_index,
+            lower_tick: tick_lower,
+            upper_tick: tick_upper,
+            liquidity_amount: params.liquidity_amount,
+            tick_array_lower_start_index,
+            tick_array_upper_start_index,
+            amount_0_max: params.amount_0_max,
+            amount_1_max: params.amount_1_max,
+        },
+    )
+}
+
+pub fn increase_bundled_liquidity(
+    payer: Pubkey,
+    pool: &PoolKeys,
+    user: UserTokenAccounts,
+    params: IncreaseBundledLiquidityParams,
+) -> Instruction {
+    build(
+        accounts::IncreaseBundledLiquidity {
+            global_config: global_config(),
+            pool: pool.pool,
+            lower_tick_array: find_tick_array_address_for_tick(&pool.pool, params.tick_lower, pool.tick_spacing).0,
+            upper_tick_array: find_tick_array_address_for_tick(&pool.pool, params.tick_upper, pool.tick_spacing).0,
+            position_bundle: find_position_bundle_address(&payer, &pool.pool).0,
+            user_token_0: user.token_0,
+            user_token_1: user.token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            payer,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::IncreaseBundledLiquidity {
+            bundle_index: params.bundle_index,
+            liquidity_amount: params.liquidity_amount,
+            amount_0_max: params.amount_0_max,
+            amount_1_max: params.amount_1_max,
+        },
+    )
+}
+
+pub fn decrease_bundled_liquidity(
+    payer: Pubkey,
+    pool: &PoolKeys,
+    user: UserTokenAccounts,
+    params: DecreaseBundledLiquidityParams,
+) -> Instruction {
+    build(
+        accounts::DecreaseBundledLiquidity {
+            global_config: global_config(),
+            payer,
+            pool: pool.pool,
+            lower_tick_array: find_tick_array_address_for_tick(&pool.pool, params.tick_lower, pool.tick_spacing).0,
+            upper_tick_array: find_tick_array_address_for_tick(&pool.pool, params.tick_upper, pool.tick_spacing).0,
+            position_bundle: find_position_bundle_address(&payer, &pool.pool).0,
+            user_token_0: user.token_0,
+            user_token_1: user.token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::DecreaseBundledLiquidity {
+            bundle_index: params.bundle_index,
+            liquidity_amount: params.liquidity_amount,
+            amount_0_min: params.amount_0_min,
+            amount_1_min: params.amount_1_min,
+        },
+    )
+}
+
+/// The ticks must be the bundled position's own; they locate the tick arrays it is cleared from.
+pub fn emergency_withdraw_bundled(
+    payer: Pubkey,
+    pool: &PoolKeys,
+    user: UserTokenAccounts,
+    bundle_index: u16,
+    tick_lower: i32,
+    tick_upper: i32,
+) -> Instruction {
+    build(
+        accounts::EmergencyWithdrawBundled {
+            global_config: global_config(),
+            payer,
+            pool: pool.pool,
+            lower_tick_array: Some(find_tick_array_address_for_tick(&pool.pool, tick_lower, pool.tick_spacing).0),
+            upper_tick_array: Some(find_tick_array_address_for_tick(&pool.pool, tick_upper, pool.tick_spacing).0),
+            position_bundle: find_position_bundle_address(&payer, &pool.pool).0,
+            user_token_0: user.token_0,
+            user_token_1: user.token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::EmergencyWithdrawBundled { bundle_index },
+    )
+}
+
+pub fn collect_bundled_fees(payer: Pubkey, pool: &PoolKeys, user: UserTokenAccounts, bundle_index: u16) -> Instruction {
+    build(
+        accounts::CollectBundledFees {
+            global_config: global_config(),
+            payer,
+            pool: pool.pool,
+            position_bundle: find_position_bundle_address(&payer, &pool.pool).0,
+            user_token_0: user.token_0,
+            user_token_1: user.token_1,
+            pool_token_0: pool.token_vault_0,
+            pool_token_1: pool.token_vault_1,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            token_program_0: pool.token_program_0,
+            token_program_1: pool.token_program_1,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::CollectBundledFees { bundle_index },
+    )
+}
+
+/// Read-only; run through simulateTransaction and read the return data.
+pub fn get_position_value(pool: Pubkey, position: Pubkey) -> Instruction {
+    build(accounts::GetPositionValue { pool, position }, instruction::GetPositionValue {})
+}
+
+/// Read-only; run through simulateTransaction and read the return data. The tick arrays
+/// between the pool's current tick and the swap's end tick (e.g. from an off-chain quote)
+/// are passed so the program can count the initialized ticks crossed.
+pub fn quote_swap(
+    pool: &PoolKeys,
+    amount_in: u64,
+    swap_token_0_for_1: bool,
+    current_tick: i32,
+    end_tick: i32,
+) -> Instruction {
+    let mut ix = build(
+        accounts::QuoteSwap {
+            pool: pool.pool,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+        },
+        instruction::QuoteSwap {
+            amount_in,
+            swap_token_0_for_1,
+        },
+    );
+    for start_index in clmm::get_crossed_tick_array_start_indexes(pool.tick_spacing, current_tick, end_tick) {
+        let tick_array = find_tick_array_address(&pool.pool, start_index).0;
+        ix.accounts.push(AccountMeta::new_readonly(tick_array, false));
+    }
+    ix
+}
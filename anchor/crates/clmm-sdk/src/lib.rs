@@ -0,0 +1,13 @@
+//! Client-side helpers for the `clmm` program: PDA derivation, account and event decoding
+//! and instruction builders that fill in every derivable account.
+
+pub mod events;
+pub mod instructions;
+pub mod pda;
+pub mod state;
+
+pub use clmm::{
+    self, ExpectedSqrtPrice, GlobalConfig, Pool, PoolStatus, Position, PositionBundle, Role, TickArray, ID,
+    TICKS_PER_ARRAY,
+};
+pub use instructions::{PoolKeys, PositionKeys, UserTokenAccounts};
@@ -0,0 +1,83 @@
+//! Program-derived addresses, using the same seeds as the program's account constraints.
+
+use anchor_lang::prelude::{ProgramData, Pubkey};
+use anchor_lang::Owner;
+
+use crate::TICKS_PER_ARRAY;
+
+pub fn find_global_config_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"global_config"], &crate::ID)
+}
+
+/// The program's upgrade data account, required by `initialize_global_config`.
+pub fn find_program_data_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[crate::ID.as_ref()], &ProgramData::owner())
+}
+
+/// Expects the mints already ordered; see [`sort_token_mints`].
+pub fn find_pool_address(token_mint_0: &Pubkey, token_mint_1: &Pubkey, tick_spacing: i32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"pool",
+            token_mint_0.as_ref(),
+            token_mint_1.as_ref(),
+            &tick_spacing.to_le_bytes(),
+        ],
+        &crate::ID,
+    )
+}
+
+/// Orders a mint pair the way `initialize_pool` requires. The flag is true when the
+/// pair was swapped.
+pub fn sort_token_mints(mint_a: Pubkey, mint_b: Pubkey) -> (Pubkey, Pubkey, bool) {
+    clmm::sort_token_mints(mint_a, mint_b)
+}
+
+pub fn find_token_vault_address(pool: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"token_vault", pool.as_ref(), mint.as_ref()], &crate::ID)
+}
+
+pub fn find_position_address(owner: &Pubkey, pool: &Pubkey, tick_lower: i32, tick_upper: i32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"position",
+            owner.as_ref(),
+            pool.as_ref(),
+            &tick_lower.to_le_bytes(),
+            &tick_upper.to_le_bytes(),
+        ],
+        &crate::ID,
+    )
+}
+
+pub fn find_position_bundle_address(owner: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"position_bundle", owner.as_ref(), pool.as_ref()], &crate::ID)
+}
+
+pub fn find_tick_array_address(pool: &Pubkey, start_tick_index: i32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"tick_array", pool.as_ref(), &start_tick_index.to_le_bytes()],
+        &crate::ID,
+    )
+}
+
+/// Signs the self-CPI that carries the program's events; see [`crate::events`].
+pub fn find_event_authority_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"__event_authority"], &crate::ID)
+}
+
+/// Temporary wSOL account used when a native SOL side's user token account is omitted.
+pub fn find_wsol_address(payer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"wsol", payer.as_ref()], &crate::ID)
+}
+
+/// First tick of the array holding `tick`. Rounds toward negative infinity so negative
+/// ticks land in the array whose range actually contains them.
+pub fn get_tick_array_start_index(tick: i32, tick_spacing: i32) -> i32 {
+    let ticks_per_array = TICKS_PER_ARRAY as i32;
+    tick.div_euclid(tick_spacing).div_euclid(ticks_per_array) * ticks_per_array * tick_spacing
+}
+
+pub fn find_tick_array_address_for_tick(pool: &Pubkey, tick: i32, tick_spacing: i32) -> (Pubkey, u8) {
+    find_tick_array_address(pool, get_tick_array_start_index(tick, tick_spacing))
+}
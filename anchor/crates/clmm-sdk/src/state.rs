@@ -0,0 +1,63 @@
+//! Decoders for raw account data as returned by RPC.
+
+use anchor_lang::error::ErrorCode;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AccountDeserialize, Discriminator, Result};
+use anchor_spl::token_2022::spl_token_2022::extension::transfer_fee::{TransferFee, TransferFeeConfig};
+use anchor_spl::token_2022::spl_token_2022::extension::transfer_hook::TransferHook;
+use anchor_spl::token_2022::spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensions};
+use anchor_spl::token_2022::spl_token_2022::state::Mint;
+use bytemuck::Pod;
+
+use crate::{GlobalConfig, Pool, Position, PositionBundle, TickArray};
+
+pub fn decode_global_config(data: &[u8]) -> Result<GlobalConfig> {
+    GlobalConfig::try_deserialize(&mut &data[..])
+}
+
+pub fn decode_pool(data: &[u8]) -> Result<Pool> {
+    Pool::try_deserialize(&mut &data[..])
+}
+
+pub fn decode_position(data: &[u8]) -> Result<Position> {
+    Position::try_deserialize(&mut &data[..])
+}
+
+pub fn decode_tick_array(data: &[u8]) -> Result<TickArray> {
+    decode_zero_copy(data)
+}
+
+pub fn decode_position_bundle(data: &[u8]) -> Result<PositionBundle> {
+    decode_zero_copy(data)
+}
+
+/// The transfer fee a mint charges in `epoch`, or None for mints without the extension,
+/// which includes every SPL Token mint.
+pub fn decode_epoch_transfer_fee(mint_data: &[u8], epoch: u64) -> Result<Option<TransferFee>> {
+    let mint = StateWithExtensions::<Mint>::unpack(mint_data)?;
+    Ok(mint
+        .get_extension::<TransferFeeConfig>()
+        .ok()
+        .map(|config| *config.get_epoch_fee(epoch)))
+}
+
+/// The program a mint's transfer hook invokes, or None for mints without one. Transfers of
+/// such mints need the hook's extra accounts appended.
+pub fn decode_transfer_hook_program_id(mint_data: &[u8]) -> Result<Option<Pubkey>> {
+    let mint = StateWithExtensions::<Mint>::unpack(mint_data)?;
+    Ok(mint
+        .get_extension::<TransferHook>()
+        .ok()
+        .and_then(|hook| Option::<Pubkey>::from(hook.program_id)))
+}
+
+// Zero-copy accounts are the discriminator followed by the struct's raw bytes.
+fn decode_zero_copy<T: Pod + Discriminator>(data: &[u8]) -> Result<T> {
+    if !data.starts_with(T::DISCRIMINATOR) {
+        return Err(ErrorCode::AccountDiscriminatorMismatch.into());
+    }
+    let body = data
+        .get(T::DISCRIMINATOR.len()..T::DISCRIMINATOR.len() + std::mem::size_of::<T>())
+        .ok_or(ErrorCode::AccountDidNotDeserialize)?;
+    bytemuck::try_pod_read_unaligned(body).map_err(|_| ErrorCode::AccountDidNotDeserialize.into())
+}
@@ -0,0 +1,199 @@
+use anchor_lang::event::EVENT_IX_TAG_LE;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AccountSerialize, Discriminator, Event};
+use anchor_spl::token::spl_token::native_mint;
+use anchor_spl::token::ID as TOKEN_PROGRAM_ID;
+use clmm_sdk::events::{
+    decode_event, decode_event_instruction, ClmmEvent, FeesCollected, GlobalConfigInitialized, Swapped,
+};
+use clmm_sdk::instructions::{self, OpenPositionParams, SwapParams};
+use clmm_sdk::pda::{find_tick_array_address, find_tick_array_address_for_tick, get_tick_array_start_index};
+use clmm_sdk::state::{decode_pool, decode_tick_array};
+use clmm_sdk::{Pool, PoolKeys, PoolStatus, TickArray};
+
+fn pool_keys() -> PoolKeys {
+    let (mint_0, mint_1, _) = clmm_sdk::pda::sort_token_mints(Pubkey::new_unique(), Pubkey::new_unique());
+    PoolKeys::derive(mint_0, mint_1, 60, TOKEN_PROGRAM_ID, TOKEN_PROGRAM_ID)
+}
+
+#[test]
+fn pool_address_matches_program() {
+    let keys = pool_keys();
+    let (expected, _) = clmm_sdk::clmm::find_pool_address(keys.token_mint_1, keys.token_mint_0, 60);
+    assert_eq!(keys.pool, expected);
+}
+
+#[test]
+fn tick_array_start_index_rounds_down() {
+    assert_eq!(get_tick_array_start_index(0, 60), 0);
+    assert_eq!(get_tick_array_start_index(1799, 60), 0);
+    assert_eq!(get_tick_array_start_index(1800, 60), 1800);
+    assert_eq!(get_tick_array_start_index(-60, 60), -1800);
+    assert_eq!(get_tick_array_start_index(-1800, 60), -1800);
+    assert_eq!(get_tick_array_start_index(-1801, 60), -3600);
+
+    let pool = Pubkey::new_unique();
+    assert_eq!(
+        find_tick_array_address_for_tick(&pool, -60, 60),
+        find_tick_array_address(&pool, -1800)
+    );
+}
+
+#[test]
+fn open_position_fills_derived_accounts() {
+    let keys = pool_keys();
+    let payer = Pubkey::new_unique();
+    let params = OpenPositionParams {
+        owner: payer,
+        tick_lower: -120,
+        tick_upper: 1800,
+        liquidity_amount: 1_000_000,
+        amount_0_max: u64::MAX,
+        amount_1_max: u64::MAX,
+        deadline: None,
+        expected_sqrt_price: None,
+    };
+    let ix = instructions::open_position(payer, &keys, Some(Pubkey::new_unique()), Some(Pubkey::new_unique()), params);
+
+    assert_eq!(ix.program_id, clmm_sdk::ID);
+    assert!(ix.data.starts_with(clmm_sdk::clmm::instruction::OpenPosition::DISCRIMINATOR));
+    assert_eq!(ix.accounts.len(), 19);
+    assert_eq!(ix.accounts[2].pubkey, find_tick_array_address(&keys.pool, -1800).0);
+    assert_eq!(ix.accounts[3].pubkey, find_tick_array_address(&keys.pool, 1800).0);
+    // Optional accounts that are absent are passed as the program id.
+    assert_eq!(ix.accounts[10].pubkey, clmm_sdk::ID);
+}
+
+#[test]
+fn swap_wraps_native_sol_when_user_account_is_omitted() {
+    let other_mint = Pubkey::new_unique();
+    let (mint_0, mint_1, _) = clmm_sdk::pda::sort_token_mints(native_mint::ID, other_mint);
+    let keys = PoolKeys::derive(mint_0, mint_1, 60, TOKEN_PROGRAM_ID, TOKEN_PROGRAM_ID);
+    let payer = Pubkey::new_unique();
+    let user_other = Some(Pubkey::new_unique());
+    let (user_token_0, user_token_1) = if mint_0 == native_mint::ID {
+        (None, user_other)
+    } else {
+        (user_other, None)
+    };
+    let params = SwapParams {
+        amount_in: 1_000,
+        swap_token_0_for_1: true,
+        amount_out_minimum: 0,
+        deadline: None,
+        expected_sqrt_price: None,
+    };
+    let ix = instructions::swap(payer, &keys, user_token_0, user_token_1, params);
+
+    assert_eq!(ix.accounts[7].pubkey, clmm_sdk::pda::find_wsol_address(&payer).0);
+}
+
+#[test]
+fn swap_route_appends_hop_accounts() {
+    let hops = [pool_keys(), pool_keys()];
+    let ix = instructions::swap_route(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), &hops, 1_000, 0);
+
+    // The event authority and program come before the hops, after the named accounts.
+    assert_eq!(ix.accounts.len(), 6 + hops.len() * clmm_sdk::clmm::ROUTE_HOP_ACCOUNTS);
+    assert_eq!(ix.accounts[4].pubkey, clmm_sdk::pda::find_event_authority_address().0);
+    assert_eq!(ix.accounts[6].pubkey, hops[0].pool);
+    assert!(ix.accounts[6].is_writable);
+    assert_eq!(ix.accounts[13].pubkey, hops[1].pool);
+}
+
+#[test]
+fn decodes_pool_and_tick_array() {
+    let keys = pool_keys();
+    let pool = Pool {
+        token_mint_0: keys.token_mint_0,
+        token_mint_1: keys.token_mint_1,
+        token_vault_0: keys.token_vault_0,
+        token_vault_1: keys.token_vault_1,
+        global_liquidity: 5_000,
+        sqrt_price_x96: 1 << 96,
+        current_tick: -42,
+        tick_spacing: 60,
+        bump: 254,
+        fee_growth_global_0_x64: 0,
+        fee_growth_global_1_x64: 0,
+        fee_rate: 1_000,
+        protocol_fee_rate: 0,
+        protocol_fees_owed_0: 0,
+        protocol_fees_owed_1: 0,
+        pending_change: None,
+        status: PoolStatus::Active,
+        flash_loan: None,
+    };
+    let mut data = Vec::new();
+    pool.try_serialize(&mut data).unwrap();
+    let decoded = decode_pool(&data).unwrap();
+    assert_eq!(decoded.current_tick, -42);
+    assert_eq!(PoolKeys::from_pool(keys.pool, &decoded, TOKEN_PROGRAM_ID, TOKEN_PROGRAM_ID), keys);
+
+    let tick_array = TickArray {
+        pool: keys.pool,
+        starting_tick: -1800,
+        ..TickArray::default()
+    };
+    let mut data = TickArray::DISCRIMINATOR.to_vec();
+    data.extend_from_slice(bytemuck::bytes_of(&tick_array));
+    let decoded = decode_tick_array(&data).unwrap();
+    assert_eq!(decoded.pool, keys.pool);
+    assert_eq!(decoded.starting_tick, -1800);
+
+    assert!(decode_tick_array(&data[8..]).is_err());
+    assert!(decode_pool(&data).is_err());
+}
+
+#[test]
+fn decodes_event_cpi_data() {
+    let swapped = Swapped {
+        pool: Pubkey::new_unique(),
+        swap_token_0_for_1: true,
+        amount_in: 50,
+        amount_out: 49,
+        fee_amount: 1,
+        sqrt_price_before_x96: 1 << 96,
+        sqrt_price_after_x96: (1 << 96) - 1_000_000_000,
+        tick: -1,
+        liquidity: 5_000,
+    };
+    // Mirrors what emit_cpi! puts in the inner instruction.
+    let mut data = EVENT_IX_TAG_LE.to_vec();
+    data.extend(swapped.data());
+    let event_authority = clmm_sdk::pda::find_event_authority_address().0;
+
+    assert_eq!(
+        decode_event_instruction(&clmm_sdk::ID, &[event_authority], &data).unwrap(),
+        Some(ClmmEvent::Swapped(swapped))
+    );
+    assert_eq!(decode_event_instruction(&Pubkey::new_unique(), &[event_authority], &data).unwrap(), None);
+    assert_eq!(decode_event_instruction(&clmm_sdk::ID, &[Pubkey::new_unique()], &data).unwrap(), None);
+
+    let collected = FeesCollected {
+        pool: Pubkey::new_unique(),
+        position: Pubkey::new_unique(),
+        bundle_index: Some(3),
+        amount_0: 7,
+        amount_1: 0,
+    };
+    let mut collected_data = EVENT_IX_TAG_LE.to_vec();
+    collected_data.extend(collected.data());
+    assert_eq!(decode_event(&collected_data).unwrap(), Some(ClmmEvent::FeesCollected(collected)));
+
+    let initialized = GlobalConfigInitialized {
+        authority: Pubkey::new_unique(),
+        timelock_delay: 86_400,
+    };
+    let mut initialized_data = EVENT_IX_TAG_LE.to_vec();
+    initialized_data.extend(initialized.data());
+    assert_eq!(
+        decode_event(&initialized_data).unwrap(),
+        Some(ClmmEvent::GlobalConfigInitialized(initialized))
+    );
+
+    // Regular instruction data isn't an event; a tagged but unknown event is an error.
+    assert_eq!(decode_event(&data[8..]).unwrap(), None);
+    assert!(decode_event(&EVENT_IX_TAG_LE.repeat(2)).is_err());
+    assert!(decode_event(&data[..data.len() - 1]).is_err());
+}
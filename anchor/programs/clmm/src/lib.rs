@@ -1,15 +1,337 @@
 #![allow(clippy::result_large_err)]
 #![allow(unexpected_cfgs)]
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
-use bytemuck::{Pod, Zeroable};
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke;
+use anchor_lang::solana_program::sysvar::instructions::{
+    self as instructions_sysvar, load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_spl::token_2022::spl_token_2022::{
+    extension::{
+        transfer_fee::{TransferFee, TransferFeeConfig},
+        BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+    },
+    onchain::invoke_transfer_checked,
+    state::Mint as MintState,
+};
+use anchor_spl::token_interface::{
+    self, CloseAccount, InitializeAccount3, Mint, SyncNative, TokenAccount, TokenInterface,
+};
+use std::cell::RefMut;
 
 declare_id!("88KQMA65EwtZwyFCF16mAMZgNPjdcQCSwr2PXnMsKFEZ");
 
 #[program]
 pub mod clmm {
     use super::*;
-    
+
+    pub fn initialize_global_config(ctx: Context<InitializeGlobalConfig>, timelock_delay: i64) -> Result<()> {
+        let global_config = &mut ctx.accounts.global_config;
+
+        require!(
+            (0..=MAX_TIMELOCK_DELAY).contains(&timelock_delay),
+            ClmmError::InvalidTimelockDelay
+        );
+
+        global_config.authority = ctx.accounts.payer.key();
+        global_config.pending_authority = Pubkey::default();
+        global_config.pause_flags = 0;
+        global_config.bump = ctx.bumps.global_config;
+        global_config.fee_manager = Pubkey::default();
+        global_config.pauser = Pubkey::default();
+        global_config.reward_funder = Pubkey::default();
+        global_config.pool_creator = Pubkey::default();
+        global_config.timelock_delay = timelock_delay;
+        global_config.pending_change = None;
+
+        emit_cpi!(GlobalConfigInitialized {
+            authority: global_config.authority,
+            timelock_delay,
+        });
+        Ok(())
+    }
+
+    pub fn transfer_authority(ctx: Context<TransferAuthority>, new_authority: Pubkey) -> Result<()> {
+        ctx.accounts.global_config.pending_authority = new_authority;
+
+        emit_cpi!(AuthorityTransferStarted {
+            authority: ctx.accounts.global_config.authority,
+            pending_authority: new_authority,
+        });
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let global_config = &mut ctx.accounts.global_config;
+
+        let previous_authority = global_config.authority;
+        global_config.authority = global_config.pending_authority;
+        global_config.pending_authority = Pubkey::default();
+
+        emit_cpi!(AuthorityTransferred {
+            previous_authority,
+            authority: global_config.authority,
+        });
+        Ok(())
+    }
+
+    pub fn grant_role(ctx: Context<ManageRole>, role: Role, member: Pubkey) -> Result<()> {
+        require!(member != Pubkey::default(), ClmmError::InvalidRoleMember);
+        *ctx.accounts.global_config.role_holder_mut(role) = member;
+
+        emit_cpi!(RoleChanged { role, member });
+        Ok(())
+    }
+
+    pub fn revoke_role(ctx: Context<ManageRole>, role: Role) -> Result<()> {
+        *ctx.accounts.global_config.role_holder_mut(role) = Pubkey::default();
+
+        emit_cpi!(RoleChanged {
+            role,
+            member: Pubkey::default(),
+        });
+        Ok(())
+    }
+
+    pub fn set_pool_status(ctx: Context<SetPoolStatus>, status: PoolStatus) -> Result<()> {
+        ctx.accounts.pool.status = status;
+
+        emit_cpi!(PoolStatusChanged {
+            pool: ctx.accounts.pool.key(),
+            status,
+        });
+        Ok(())
+    }
+
+    pub fn set_pause_flags(ctx: Context<SetPauseFlags>, pause_flags: u8) -> Result<()> {
+        require!(
+            pause_flags & !GlobalConfig::PAUSE_ALL == 0,
+            ClmmError::InvalidPauseFlags
+        );
+        ctx.accounts.global_config.pause_flags = pause_flags;
+
+        emit_cpi!(PauseFlagsChanged { pause_flags });
+        Ok(())
+    }
+
+    pub fn queue_fee_rate_change(ctx: Context<QueuePoolChange>, fee_rate: u32) -> Result<()> {
+        require!(fee_rate <= MAX_FEE_RATE, ClmmError::InvalidFeeRate);
+
+        let pool = &mut ctx.accounts.pool;
+        require!(pool.pending_change.is_none(), ClmmError::PendingChangeExists);
+        let pending_change = PendingChange::new(
+            ParameterChange::FeeRate(fee_rate),
+            ctx.accounts.global_config.timelock_delay,
+        )?;
+        pool.pending_change = Some(pending_change);
+
+        emit_cpi!(PendingChangeQueued {
+            target: pool.key(),
+            change: pending_change.change,
+            execute_after: pending_change.execute_after,
+        });
+        Ok(())
+    }
+
+    pub fn queue_protocol_fee_rate_change(ctx: Context<QueuePoolChange>, protocol_fee_rate: u16) -> Result<()> {
+        require!(
+            protocol_fee_rate <= MAX_PROTOCOL_FEE_RATE,
+            ClmmError::InvalidFeeRate
+        );
+
+        let pool = &mut ctx.accounts.pool;
+        require!(pool.pending_change.is_none(), ClmmError::PendingChangeExists);
+        let pending_change = PendingChange::new(
+            ParameterChange::ProtocolFeeRate(protocol_fee_rate),
+            ctx.accounts.global_config.timelock_delay,
+        )?;
+        pool.pending_change = Some(pending_change);
+
+        emit_cpi!(PendingChangeQueued {
+            target: pool.key(),
+            change: pending_change.change,
+            execute_after: pending_change.execute_after,
+        });
+        Ok(())
+    }
+
+    pub fn queue_timelock_delay_change(ctx: Context<QueueConfigChange>, timelock_delay: i64) -> Result<()> {
+        require!(
+            (0..=MAX_TIMELOCK_DELAY).contains(&timelock_delay),
+            ClmmError::InvalidTimelockDelay
+        );
+
+        let global_config = &mut ctx.accounts.global_config;
+        require!(global_config.pending_change.is_none(), ClmmError::PendingChangeExists);
+        let pending_change = PendingChange::new(
+            ParameterChange::TimelockDelay(timelock_delay),
+            global_config.timelock_delay,
+        )?;
+        global_config.pending_change = Some(pending_change);
+
+        emit_cpi!(PendingChangeQueued {
+            target: global_config.key(),
+            change: pending_change.change,
+            execute_after: pending_change.execute_after,
+        });
+        Ok(())
+    }
+
+    // Anyone may apply a queued change once its delay has elapsed. Passing a pool
+    // applies that pool's change; omitting it applies the config's change.
+    pub fn execute_pending_change(ctx: Context<ExecutePendingChange>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        if let Some(pool) = ctx.accounts.pool.as_mut() {
+            let pending_change = pool.pending_change.ok_or(ClmmError::NoPendingChange)?;
+            require!(now >= pending_change.execute_after, ClmmError::PendingChangeNotReady);
+
+            match pending_change.change {
+                ParameterChange::FeeRate(fee_rate) => pool.fee_rate = fee_rate,
+                ParameterChange::ProtocolFeeRate(protocol_fee_rate) => pool.protocol_fee_rate = protocol_fee_rate,
+                ParameterChange::TimelockDelay(_) => return err!(ClmmError::InvalidPendingChange),
+            }
+            pool.pending_change = None;
+
+            emit_cpi!(PendingChangeExecuted {
+                target: pool.key(),
+                change: pending_change.change,
+            });
+        } else {
+            let global_config = &mut ctx.accounts.global_config;
+            let pending_change = global_config.pending_change.ok_or(ClmmError::NoPendingChange)?;
+            require!(now >= pending_change.execute_after, ClmmError::PendingChangeNotReady);
+
+            match pending_change.change {
+                ParameterChange::TimelockDelay(timelock_delay) => global_config.timelock_delay = timelock_delay,
+                _ => return err!(ClmmError::InvalidPendingChange),
+            }
+            global_config.pending_change = None;
+
+            emit_cpi!(PendingChangeExecuted {
+                target: global_config.key(),
+                change: pending_change.change,
+            });
+        }
+
+        Ok(())
+    }
+
+    // Pool changes are cancelled by the fee manager, config changes by the authority.
+    pub fn cancel_pending_change(ctx: Context<CancelPendingChange>) -> Result<()> {
+        let admin = ctx.accounts.admin.key();
+
+        if let Some(pool) = ctx.accounts.pool.as_mut() {
+            require!(
+                ctx.accounts.global_config.has_role(Role::FeeManager, &admin),
+                ClmmError::Unauthorized
+            );
+            let pending_change = pool.pending_change.take().ok_or(ClmmError::NoPendingChange)?;
+
+            emit_cpi!(PendingChangeCancelled {
+                target: pool.key(),
+                change: pending_change.change,
+            });
+        } else {
+            let global_config = &mut ctx.accounts.global_config;
+            require!(global_config.authority == admin, ClmmError::Unauthorized);
+            let pending_change = global_config.pending_change.take().ok_or(ClmmError::NoPendingChange)?;
+
+            emit_cpi!(PendingChangeCancelled {
+                target: global_config.key(),
+                change: pending_change.change,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn collect_protocol_fees<'info>(ctx: Context<'_, '_, 'info, 'info, CollectProtocolFees<'info>>) -> Result<(u64, u64)> {
+        let pool = &mut ctx.accounts.pool;
+
+        let amount_0 = pool.protocol_fees_owed_0;
+        let amount_1 = pool.protocol_fees_owed_1;
+        pool.protocol_fees_owed_0 = 0;
+        pool.protocol_fees_owed_1 = 0;
+
+        if amount_0 > 0 {
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.recipient_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                amount_0,
+            )?;
+        }
+
+        if amount_1 > 0 {
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.recipient_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                amount_1,
+            )?;
+        }
+
+        emit_cpi!(ProtocolFeesCollected {
+            pool: pool.key(),
+            amount_0,
+            amount_1,
+        });
+
+        Ok((amount_0, amount_1))
+    }
+
+    pub fn fund_rewards<'info>(ctx: Context<'_, '_, 'info, 'info, FundRewards<'info>>, amount_0: u64, amount_1: u64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        require!(amount_0 > 0 || amount_1 > 0, ClmmError::InsufficientInputAmount);
+        require!(pool.global_liquidity > 0, ClmmError::InsufficientPoolLiquidity);
+
+        // Rewards are paid out to LPs pro rata, alongside swap fees
+        if amount_0 > 0 {
+            let transfer_0 = quote_transfer_from_gross(&ctx.accounts.token_mint_0, amount_0)?;
+            pool.credit_fee_growth(transfer_0.net_amount, true)?;
+            transfer_from_user(
+                ctx.accounts.reward_funder.to_account_info(),
+                &ctx.accounts.funder_token_0,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                amount_0,
+            )?;
+        }
+
+        if amount_1 > 0 {
+            let transfer_1 = quote_transfer_from_gross(&ctx.accounts.token_mint_1, amount_1)?;
+            pool.credit_fee_growth(transfer_1.net_amount, false)?;
+            transfer_from_user(
+                ctx.accounts.reward_funder.to_account_info(),
+                &ctx.accounts.funder_token_1,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                amount_1,
+            )?;
+        }
+
+        emit_cpi!(RewardsFunded {
+            pool: pool.key(),
+            funder: ctx.accounts.reward_funder.key(),
+            amount_0,
+            amount_1,
+        });
+
+        Ok(())
+    }
+
     pub fn initialize_pool(
         ctx: Context<InitializePool>,
         tick_spacing: i32,
@@ -17,11 +339,17 @@ pub mod clmm {
     ) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
 
-        require!(tick_spacing > 0, ClmmError::InvalidTickSpacing);
+        require!(VALID_TICK_SPACINGS.contains(&tick_spacing), ClmmError::InvalidTickSpacing);
         require!(
             ctx.accounts.token_mint_0.key() != ctx.accounts.token_mint_1.key(),
             ClmmError::InvalidTokenPair
         );
+        require!(
+            ctx.accounts.token_mint_0.key() < ctx.accounts.token_mint_1.key(),
+            ClmmError::TokenMintsNotOrdered
+        );
+        validate_mint_extensions(&ctx.accounts.token_mint_0)?;
+        validate_mint_extensions(&ctx.accounts.token_mint_1)?;
 
         pool.token_mint_0 = ctx.accounts.token_mint_0.key();
         pool.token_mint_1 = ctx.accounts.token_mint_1.key();
@@ -32,22 +360,47 @@ pub mod clmm {
         pool.current_tick = get_tick_at_sqrt_price(initial_sqrt_price)?;
         pool.tick_spacing = tick_spacing;
         pool.bump = ctx.bumps.pool;
-        
+        pool.fee_growth_global_0_x64 = 0;
+        pool.fee_growth_global_1_x64 = 0;
+        pool.fee_rate = DEFAULT_FEE_RATE;
+        pool.protocol_fee_rate = 0;
+        pool.protocol_fees_owed_0 = 0;
+        pool.protocol_fees_owed_1 = 0;
+        pool.pending_change = None;
+        pool.status = PoolStatus::Active;
+        pool.flash_loan = None;
+
+        emit_cpi!(PoolInitialized {
+            pool: pool.key(),
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            tick_spacing,
+            sqrt_price_x96: pool.sqrt_price_x96,
+            tick: pool.current_tick,
+            fee_rate: pool.fee_rate,
+        });
+
         Ok(())
     }
 
-    pub fn open_position(
-        ctx: Context<OpenPosition>,
+    #[allow(clippy::too_many_arguments)]
+    pub fn open_position<'info>(
+        ctx: Context<'_, '_, 'info, 'info, OpenPosition<'info>>,
         owner: Pubkey,
         lower_tick: i32,
         upper_tick: i32,
         liquidity_amount: u128,
         _tick_array_lower_start_index: i32,
         _tick_array_upper_start_index: i32,
+        amount_0_max: u64,
+        amount_1_max: u64,
+        deadline: Option<i64>,
+        expected_sqrt_price: Option<ExpectedSqrtPrice>,
     ) -> Result<(u64, u64)> {
         let pool = &mut ctx.accounts.pool;
         let position = &mut ctx.accounts.position;
 
+        check_execution_guards(pool, deadline, expected_sqrt_price)?;
         require!(lower_tick < upper_tick, ClmmError::InvalidTickRange);
         require!(
             lower_tick % pool.tick_spacing == 0,
@@ -60,8 +413,8 @@ pub mod clmm {
         require!(liquidity_amount > 0, ClmmError::InsufficientInputAmount);
 
         // Load and initialize tick arrays if needed
-        let lower_tick_array = &mut ctx.accounts.lower_tick_array.load_init()?;
-        let upper_tick_array = &mut ctx.accounts.upper_tick_array.load_init()?;
+        let lower_tick_array = &mut load_tick_array_init_if_needed(&ctx.accounts.lower_tick_array)?;
+        let upper_tick_array = &mut load_tick_array_init_if_needed(&ctx.accounts.upper_tick_array)?;
 
         if lower_tick_array.pool == Pubkey::default() {
             lower_tick_array.pool = pool.key();
@@ -73,6 +426,15 @@ pub mod clmm {
             upper_tick_array.starting_tick = _tick_array_upper_start_index;
         }
 
+        require!(
+            lower_tick_array.contains_tick(lower_tick, pool.tick_spacing),
+            ClmmError::InvalidTickArray
+        );
+        require!(
+            upper_tick_array.contains_tick(upper_tick, pool.tick_spacing),
+            ClmmError::InvalidTickArray
+        );
+
         // Update tick info
         let lower_tick_info = lower_tick_array.get_tick_info_mutable(lower_tick, pool.tick_spacing)?;
         let upper_tick_info = upper_tick_array.get_tick_info_mutable(upper_tick, pool.tick_spacing)?;
@@ -87,19 +449,23 @@ pub mod clmm {
             liquidity_amount,
         )?;
 
-        if position.liquidity == 0 && position.owner == Pubkey::default() {
+        let is_new_position = position.liquidity == 0 && position.owner == Pubkey::default();
+        if is_new_position {
             position.owner = owner;
             position.pool = pool.key();
             position.tick_lower = lower_tick;
             position.tick_upper = upper_tick;
             position.liquidity = liquidity_amount;
             position.bump = ctx.bumps.position;
+            position.fee_growth_inside_0_last_x64 = pool.fee_growth_global_0_x64;
+            position.fee_growth_inside_1_last_x64 = pool.fee_growth_global_1_x64;
         } else {
             require!(position.owner == owner, ClmmError::InvalidPositionOwner);
             require!(
                 position.tick_lower == lower_tick && position.tick_upper == upper_tick,
                 ClmmError::InvalidPositionRange
             );
+            position.update_fees(pool.fee_growth_global_0_x64, pool.fee_growth_global_1_x64)?;
             position.liquidity = position
                 .liquidity
                 .checked_add(liquidity_amount)
@@ -111,40 +477,98 @@ pub mod clmm {
             .checked_add(liquidity_amount)
             .ok_or(ClmmError::ArithmeticOverflow)?;
 
-        if amount_0 > 0 {
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.user_token_0.to_account_info(),
-                        to: ctx.accounts.pool_token_0.to_account_info(),
-                        authority: ctx.accounts.payer.to_account_info(),
-                    },
-                ),
-                amount_0,
+        let (transfer_0, transfer_1) = quote_deposit(
+            &ctx.accounts.token_mint_0,
+            &ctx.accounts.token_mint_1,
+            amount_0,
+            amount_1,
+            amount_0_max,
+            amount_1_max,
+        )?;
+        let (user_token_0, wrapped_0) = get_or_wrap_user_token_account(
+            &ctx.accounts.user_token_0,
+            &ctx.accounts.wsol_account,
+            ctx.bumps.wsol_account,
+            &ctx.accounts.payer,
+            &ctx.accounts.token_mint_0,
+            &ctx.accounts.token_program_0,
+            &ctx.accounts.system_program,
+            transfer_0.gross_amount,
+        )?;
+        let (user_token_1, wrapped_1) = get_or_wrap_user_token_account(
+            &ctx.accounts.user_token_1,
+            &ctx.accounts.wsol_account,
+            ctx.bumps.wsol_account,
+            &ctx.accounts.payer,
+            &ctx.accounts.token_mint_1,
+            &ctx.accounts.token_program_1,
+            &ctx.accounts.system_program,
+            transfer_1.gross_amount,
+        )?;
+
+        if transfer_0.gross_amount > 0 {
+            transfer_from_user(
+                ctx.accounts.payer.to_account_info(),
+                &user_token_0,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                transfer_0.gross_amount,
             )?;
         }
 
-        if amount_1 > 0 {
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.user_token_1.to_account_info(),
-                        to: ctx.accounts.pool_token_1.to_account_info(),
-                        authority: ctx.accounts.payer.to_account_info(),
-                    },
-                ),
-                amount_1,
+        if transfer_1.gross_amount > 0 {
+            transfer_from_user(
+                ctx.accounts.payer.to_account_info(),
+                &user_token_1,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                transfer_1.gross_amount,
             )?;
         }
 
-        Ok((amount_0, amount_1))
+        if wrapped_0 {
+            close_wsol_account(&ctx.accounts.payer, user_token_0, &ctx.accounts.token_program_0)?;
+        }
+        if wrapped_1 {
+            close_wsol_account(&ctx.accounts.payer, user_token_1, &ctx.accounts.token_program_1)?;
+        }
+
+        if is_new_position {
+            emit_cpi!(PositionOpened {
+                pool: pool.key(),
+                position: position.key(),
+                bundle_index: None,
+                owner,
+                tick_lower: lower_tick,
+                tick_upper: upper_tick,
+                liquidity: liquidity_amount,
+                amount_0: transfer_0.gross_amount,
+                amount_1: transfer_1.gross_amount,
+            });
+        } else {
+            emit_cpi!(LiquidityIncreased {
+                pool: pool.key(),
+                position: position.key(),
+                bundle_index: None,
+                liquidity_delta: liquidity_amount,
+                liquidity: position.liquidity,
+                amount_0: transfer_0.gross_amount,
+                amount_1: transfer_1.gross_amount,
+            });
+        }
+
+        Ok((transfer_0.gross_amount, transfer_1.gross_amount))
     }
 
-    pub fn increase_liquidity(
-        ctx: Context<IncreaseLiquidity>,
+    pub fn increase_liquidity<'info>(
+        ctx: Context<'_, '_, 'info, 'info, IncreaseLiquidity<'info>>,
         liquidity_amount: u128,
+        amount_0_max: u64,
+        amount_1_max: u64,
     ) -> Result<(u64, u64)> {
         let pool = &mut ctx.accounts.pool;
         let position = &mut ctx.accounts.position;
@@ -161,6 +585,7 @@ pub mod clmm {
         lower_tick_info.update_liquidity(liquidity_amount as i128, true)?;
         upper_tick_info.update_liquidity(liquidity_amount as i128, false)?;
 
+        position.update_fees(pool.fee_growth_global_0_x64, pool.fee_growth_global_1_x64)?;
         position.liquidity = position
             .liquidity
             .checked_add(liquidity_amount)
@@ -178,44 +603,64 @@ pub mod clmm {
             .checked_add(liquidity_amount)
             .ok_or(ClmmError::ArithmeticOverflow)?;
 
-        if amount_0 > 0 {
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.user_token_0.to_account_info(),
-                        to: ctx.accounts.pool_token_0.to_account_info(),
-                        authority: ctx.accounts.payer.to_account_info(),
-                    },
-                ),
-                amount_0,
+        let (transfer_0, transfer_1) = quote_deposit(
+            &ctx.accounts.token_mint_0,
+            &ctx.accounts.token_mint_1,
+            amount_0,
+            amount_1,
+            amount_0_max,
+            amount_1_max,
+        )?;
+
+        if transfer_0.gross_amount > 0 {
+            transfer_from_user(
+                ctx.accounts.payer.to_account_info(),
+                &ctx.accounts.user_token_0,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                transfer_0.gross_amount,
             )?;
         }
 
-        if amount_1 > 0 {
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.user_token_1.to_account_info(),
-                        to: ctx.accounts.pool_token_1.to_account_info(),
-                        authority: ctx.accounts.payer.to_account_info(),
-                    },
-                ),
-                amount_1,
+        if transfer_1.gross_amount > 0 {
+            transfer_from_user(
+                ctx.accounts.payer.to_account_info(),
+                &ctx.accounts.user_token_1,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                transfer_1.gross_amount,
             )?;
         }
 
-        Ok((amount_0, amount_1))
+        emit_cpi!(LiquidityIncreased {
+            pool: pool.key(),
+            position: position.key(),
+            bundle_index: None,
+            liquidity_delta: liquidity_amount,
+            liquidity: position.liquidity,
+            amount_0: transfer_0.gross_amount,
+            amount_1: transfer_1.gross_amount,
+        });
+
+        Ok((transfer_0.gross_amount, transfer_1.gross_amount))
     }
 
-    pub fn decrease_liquidity(
-        ctx: Context<DecreaseLiquidity>,
+    pub fn decrease_liquidity<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DecreaseLiquidity<'info>>,
         liquidity_amount: u128,
+        amount_0_min: u64,
+        amount_1_min: u64,
+        deadline: Option<i64>,
+        expected_sqrt_price: Option<ExpectedSqrtPrice>,
     ) -> Result<(u64, u64)> {
         let pool = &mut ctx.accounts.pool;
         let position = &mut ctx.accounts.position;
 
+        check_execution_guards(pool, deadline, expected_sqrt_price)?;
         require!(liquidity_amount > 0, ClmmError::InsufficientInputAmount);
         require!(
             position.liquidity >= liquidity_amount,
@@ -232,6 +677,7 @@ pub mod clmm {
         lower_tick_info.update_liquidity_decrease(liquidity_amount as i128, true)?;
         upper_tick_info.update_liquidity_decrease(liquidity_amount as i128, false)?;
 
+        position.update_fees(pool.fee_growth_global_0_x64, pool.fee_growth_global_1_x64)?;
         position.liquidity = position
             .liquidity
             .checked_sub(liquidity_amount)
@@ -249,155 +695,1408 @@ pub mod clmm {
             .checked_sub(liquidity_amount)
             .ok_or(ClmmError::ArithmeticOverflow)?;
 
+        // Withdrawals bear the transfer fee, so the minimums apply to what the user receives
+        let received_0 = quote_transfer_from_gross(&ctx.accounts.token_mint_0, amount_0)?.net_amount;
+        let received_1 = quote_transfer_from_gross(&ctx.accounts.token_mint_1, amount_1)?.net_amount;
+        require!(
+            received_0 >= amount_0_min && received_1 >= amount_1_min,
+            ClmmError::SlippageExceeded
+        );
+
         if amount_0 > 0 {
-            let seeds = [
-                b"pool",
-                pool.token_mint_0.as_ref(),
-                pool.token_mint_1.as_ref(),
-                &pool.tick_spacing.to_le_bytes(),
-                &[pool.bump],
-            ];
-            let signer_seeds = &[&seeds[..]];
-
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.pool_token_0.to_account_info(),
-                        to: ctx.accounts.user_token_0.to_account_info(),
-                        authority: pool.to_account_info(),
-                    },
-                    signer_seeds,
-                ),
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.user_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
                 amount_0,
             )?;
         }
 
         if amount_1 > 0 {
-            let seeds = [
-                b"pool",
-                pool.token_mint_0.as_ref(),
-                pool.token_mint_1.as_ref(),
-                &pool.tick_spacing.to_le_bytes(),
-                &[pool.bump],
-            ];
-            let signer_seeds = &[&seeds[..]];
-
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.pool_token_1.to_account_info(),
-                        to: ctx.accounts.user_token_1.to_account_info(),
-                        authority: pool.to_account_info(),
-                    },
-                    signer_seeds,
-                ),
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.user_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
                 amount_1,
             )?;
         }
         
+        emit_cpi!(LiquidityDecreased {
+            pool: pool.key(),
+            position: position.key(),
+            bundle_index: None,
+            liquidity_delta: liquidity_amount,
+            liquidity: position.liquidity,
+            amount_0,
+            amount_1,
+        });
+
+        Ok((amount_0, amount_1))
+    }
+
+    // Pays out the position's pro rata share of the vaults instead of the tick math,
+    // for use when a pool has been put into withdraw-only mode during an incident.
+    // The tick arrays are optional: when given, the liquidity also leaves its ticks, but only
+    // if both ticks update cleanly, so a broken tick can never keep an LP from exiting.
+    pub fn emergency_withdraw<'info>(ctx: Context<'_, '_, 'info, 'info, EmergencyWithdraw<'info>>) -> Result<(u64, u64)> {
+        let pool = &mut ctx.accounts.pool;
+        let position = &mut ctx.accounts.position;
+
+        require!(position.liquidity > 0, ClmmError::NoLiquidityToRemove);
+
+        let amount_0 = get_emergency_withdraw_amount(
+            ctx.accounts.pool_token_0.amount,
+            pool.protocol_fees_owed_0,
+            position.liquidity,
+            pool.global_liquidity,
+        )?;
+        let amount_1 = get_emergency_withdraw_amount(
+            ctx.accounts.pool_token_1.amount,
+            pool.protocol_fees_owed_1,
+            position.liquidity,
+            pool.global_liquidity,
+        )?;
+
+        let liquidity_amount = position.liquidity;
+        if let (Some(lower_tick_array), Some(upper_tick_array)) =
+            (&ctx.accounts.lower_tick_array, &ctx.accounts.upper_tick_array)
+        {
+            // A failed tick update leaves the ticks as they were instead of blocking the exit
+            let _ = clear_position_ticks(
+                pool,
+                lower_tick_array,
+                upper_tick_array,
+                position.tick_lower,
+                position.tick_upper,
+                liquidity_amount,
+            );
+        }
+
+        pool.global_liquidity = pool.global_liquidity.saturating_sub(liquidity_amount);
+        position.liquidity = 0;
+        position.tokens_owed_0 = 0;
+        position.tokens_owed_1 = 0;
+
+        if amount_0 > 0 {
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.user_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                amount_0,
+            )?;
+        }
+
+        if amount_1 > 0 {
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.user_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                amount_1,
+            )?;
+        }
+
+        emit_cpi!(LiquidityDecreased {
+            pool: pool.key(),
+            position: position.key(),
+            bundle_index: None,
+            liquidity_delta: liquidity_amount,
+            liquidity: 0,
+            amount_0,
+            amount_1,
+        });
+
+        Ok((amount_0, amount_1))
+    }
+
+    // Same as `emergency_withdraw`, for a position held in a bundle slot. The slot is freed.
+    pub fn emergency_withdraw_bundled<'info>(
+        ctx: Context<'_, '_, 'info, 'info, EmergencyWithdrawBundled<'info>>,
+        bundle_index: u16,
+    ) -> Result<(u64, u64)> {
+        let pool = &mut ctx.accounts.pool;
+        let position_bundle = &mut ctx.accounts.position_bundle.load_mut()?;
+        let position = position_bundle.get_position_mutable(bundle_index)?;
+
+        let liquidity_amount = position.get_liquidity();
+        require!(liquidity_amount > 0, ClmmError::NoLiquidityToRemove);
+
+        let amount_0 = get_emergency_withdraw_amount(
+            ctx.accounts.pool_token_0.amount,
+            pool.protocol_fees_owed_0,
+            liquidity_amount,
+            pool.global_liquidity,
+        )?;
+        let amount_1 = get_emergency_withdraw_amount(
+            ctx.accounts.pool_token_1.amount,
+            pool.protocol_fees_owed_1,
+            liquidity_amount,
+            pool.global_liquidity,
+        )?;
+
+        if let (Some(lower_tick_array), Some(upper_tick_array)) =
+            (&ctx.accounts.lower_tick_array, &ctx.accounts.upper_tick_array)
+        {
+            let _ = clear_position_ticks(
+                pool,
+                lower_tick_array,
+                upper_tick_array,
+                position.tick_lower,
+                position.tick_upper,
+                liquidity_amount,
+            );
+        }
+
+        pool.global_liquidity = pool.global_liquidity.saturating_sub(liquidity_amount);
+        position.set_liquidity(0);
+        position.tokens_owed_0 = 0;
+        position.tokens_owed_1 = 0;
+        position_bundle.close_position_if_empty(bundle_index)?;
+
+        if amount_0 > 0 {
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.user_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                amount_0,
+            )?;
+        }
+
+        if amount_1 > 0 {
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.user_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                amount_1,
+            )?;
+        }
+
+        emit_cpi!(LiquidityDecreased {
+            pool: pool.key(),
+            position: ctx.accounts.position_bundle.key(),
+            bundle_index: Some(bundle_index),
+            liquidity_delta: liquidity_amount,
+            liquidity: 0,
+            amount_0,
+            amount_1,
+        });
+
         Ok((amount_0, amount_1))
     }
 
-    pub fn swap(
-        ctx: Context<Swap>,
+    pub fn swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Swap<'info>>,
         amount_in: u64,
         swap_token_0_for_1: bool,
         amount_out_minimum: u64,
+        deadline: Option<i64>,
+        expected_sqrt_price: Option<ExpectedSqrtPrice>,
     ) -> Result<u64> {
         let pool = &mut ctx.accounts.pool;
+        check_execution_guards(pool, deadline, expected_sqrt_price)?;
 
-        require!(pool.global_liquidity > 0, ClmmError::InsufficientPoolLiquidity);
-        require!(amount_in > 0, ClmmError::InsufficientInputAmount);
-
-        let (amount_in_used, amount_out_calculated, new_sqrt_price_x96) =
-            swap_segment(pool.sqrt_price_x96, pool.global_liquidity, amount_in, swap_token_0_for_1)?;
+        let (mint_in, mint_out) = if swap_token_0_for_1 {
+            (&ctx.accounts.token_mint_0, &ctx.accounts.token_mint_1)
+        } else {
+            (&ctx.accounts.token_mint_1, &ctx.accounts.token_mint_0)
+        };
 
+        let quote = compute_swap(pool, mint_in, amount_in, swap_token_0_for_1)?;
         require!(
-            amount_out_calculated >= amount_out_minimum,
+            quote.amount_out >= amount_out_minimum,
             ClmmError::SlippageExceeded
         );
 
-        let seeds = [
-            b"pool",
-            pool.token_mint_0.as_ref(),
-            pool.token_mint_1.as_ref(),
-            &pool.tick_spacing.to_le_bytes(),
-            &[pool.bump],
-        ];
-        let signer_seeds = &[&seeds[..]];
+        // Gross up the output so the user receives the quoted amount after the
+        // output mint's transfer fee.
+        let transfer_out = quote_transfer_for_net(mint_out, quote.amount_out)?;
+
+        // A native SOL input is wrapped for the transfer; a native SOL output is unwrapped
+        // when the temporary account is closed back to the payer.
+        let wrap_amount_0 = if swap_token_0_for_1 { quote.amount_in } else { 0 };
+        let wrap_amount_1 = if swap_token_0_for_1 { 0 } else { quote.amount_in };
+        let (user_token_0, wrapped_0) = get_or_wrap_user_token_account(
+            &ctx.accounts.user_token_0,
+            &ctx.accounts.wsol_account,
+            ctx.bumps.wsol_account,
+            &ctx.accounts.payer,
+            &ctx.accounts.token_mint_0,
+            &ctx.accounts.token_program_0,
+            &ctx.accounts.system_program,
+            wrap_amount_0,
+        )?;
+        let (user_token_1, wrapped_1) = get_or_wrap_user_token_account(
+            &ctx.accounts.user_token_1,
+            &ctx.accounts.wsol_account,
+            ctx.bumps.wsol_account,
+            &ctx.accounts.payer,
+            &ctx.accounts.token_mint_1,
+            &ctx.accounts.token_program_1,
+            &ctx.accounts.system_program,
+            wrap_amount_1,
+        )?;
 
         if swap_token_0_for_1 {
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.user_token_0.to_account_info(),
-                        to: ctx.accounts.pool_token_0.to_account_info(),
-                        authority: ctx.accounts.payer.to_account_info(),
-                    },
-                ),
-                amount_in_used,
+            transfer_from_user(
+                ctx.accounts.payer.to_account_info(),
+                &user_token_0,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                quote.amount_in,
             )?;
 
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.pool_token_1.to_account_info(),
-                        to: ctx.accounts.user_token_1.to_account_info(),
-                        authority: pool.to_account_info(),
-                    },
-                    signer_seeds,
-                ),
-                amount_out_calculated,
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_1,
+                &user_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                transfer_out.gross_amount,
             )?;
         } else {
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.user_token_1.to_account_info(),
-                        to: ctx.accounts.pool_token_1.to_account_info(),
-                        authority: ctx.accounts.payer.to_account_info(),
-                    },
-                ),
-                amount_in_used,
+            transfer_from_user(
+                ctx.accounts.payer.to_account_info(),
+                &user_token_1,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                quote.amount_in,
             )?;
 
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.pool_token_0.to_account_info(),
-                        to: ctx.accounts.user_token_0.to_account_info(),
-                        authority: pool.to_account_info(),
-                    },
-                    signer_seeds,
-                ),
-                amount_out_calculated,
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_0,
+                &user_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                transfer_out.gross_amount,
             )?;
         }
 
-        pool.sqrt_price_x96 = new_sqrt_price_x96;
-        pool.current_tick = get_tick_at_sqrt_price(new_sqrt_price_x96)?;
+        if wrapped_0 {
+            close_wsol_account(&ctx.accounts.payer, user_token_0, &ctx.accounts.token_program_0)?;
+        }
+        if wrapped_1 {
+            close_wsol_account(&ctx.accounts.payer, user_token_1, &ctx.accounts.token_program_1)?;
+        }
 
-        Ok(amount_out_calculated)
-    }
-}
+        let sqrt_price_before_x96 = pool.sqrt_price_x96;
+        pool.accrue_swap_fee(quote.fee_amount, swap_token_0_for_1)?;
 
-#[derive(Accounts)]
-#[instruction(tick_spacing: i32)]
-pub struct InitializePool<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
+        pool.sqrt_price_x96 = quote.sqrt_price_x96;
+        pool.current_tick = quote.tick;
 
-    #[account(
-        init,
-        payer = payer,
-        space = Pool::SPACE,
-        seeds = [
+        emit_cpi!(Swapped {
+            pool: pool.key(),
+            swap_token_0_for_1,
+            amount_in: quote.amount_in,
+            amount_out: quote.amount_out,
+            fee_amount: quote.fee_amount,
+            sqrt_price_before_x96,
+            sqrt_price_after_x96: pool.sqrt_price_x96,
+            tick: pool.current_tick,
+            liquidity: pool.global_liquidity,
+        });
+
+        Ok(quote.amount_out)
+    }
+
+    // Hops are read from the first hop_count * ROUTE_HOP_ACCOUNTS remaining accounts; anything
+    // after them is forwarded as transfer-hook extra accounts. Each hop's output is sent straight
+    // into the next pool's vault and priced on what arrives there.
+    pub fn swap_route<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapRoute<'info>>,
+        amount_in: u64,
+        amount_out_minimum: u64,
+        hop_count: u8,
+    ) -> Result<u64> {
+        let hop_count = hop_count as usize;
+        require!((1..=MAX_ROUTE_HOPS).contains(&hop_count), ClmmError::InvalidRoute);
+        require!(amount_in > 0, ClmmError::InsufficientInputAmount);
+        require!(
+            ctx.remaining_accounts.len() >= hop_count * ROUTE_HOP_ACCOUNTS,
+            ClmmError::InvalidRoute
+        );
+
+        let (hop_accounts, hook_accounts) = ctx.remaining_accounts.split_at(hop_count * ROUTE_HOP_ACCOUNTS);
+        let mut hops = hop_accounts
+            .chunks_exact(ROUTE_HOP_ACCOUNTS)
+            .map(RouteHop::load)
+            .collect::<Result<Vec<_>>>()?;
+        for (i, hop) in hops.iter().enumerate() {
+            require!(
+                hops[..i].iter().all(|previous| previous.pool.key() != hop.pool.key()),
+                ClmmError::InvalidRoute
+            );
+        }
+
+        let mut mint_in = ctx.accounts.user_token_in.mint;
+        let mut hop_amount_in = amount_in;
+        let mut legs = Vec::with_capacity(hop_count);
+        for hop in hops.iter_mut() {
+            let swap_token_0_for_1 = hop.direction(&mint_in)?;
+            let quote = compute_swap(&hop.pool, hop.mint(swap_token_0_for_1), hop_amount_in, swap_token_0_for_1)?;
+
+            let pool = &mut hop.pool;
+            let sqrt_price_before_x96 = pool.sqrt_price_x96;
+            pool.accrue_swap_fee(quote.fee_amount, swap_token_0_for_1)?;
+            pool.sqrt_price_x96 = quote.sqrt_price_x96;
+            pool.current_tick = quote.tick;
+
+            emit_cpi!(Swapped {
+                pool: pool.key(),
+                swap_token_0_for_1,
+                amount_in: quote.amount_in,
+                amount_out: quote.amount_out,
+                fee_amount: quote.fee_amount,
+                sqrt_price_before_x96,
+                sqrt_price_after_x96: pool.sqrt_price_x96,
+                tick: pool.current_tick,
+                liquidity: pool.global_liquidity,
+            });
+
+            legs.push((swap_token_0_for_1, quote.amount_in, quote.amount_out));
+            mint_in = hop.mint(!swap_token_0_for_1).key();
+            hop_amount_in = quote.amount_out;
+        }
+
+        require!(mint_in == ctx.accounts.user_token_out.mint, ClmmError::InvalidRoute);
+        require!(hop_amount_in >= amount_out_minimum, ClmmError::SlippageExceeded);
+
+        let (first_swap_token_0_for_1, first_amount_in, _) = legs[0];
+        transfer_from_user(
+            ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.user_token_in,
+            hops[0].vault(first_swap_token_0_for_1),
+            hops[0].mint(first_swap_token_0_for_1),
+            hops[0].token_program(first_swap_token_0_for_1),
+            hook_accounts,
+            first_amount_in,
+        )?;
+
+        for (i, &(swap_token_0_for_1, _, amount_out_calculated)) in legs.iter().enumerate() {
+            let hop = &hops[i];
+            let mint_out = hop.mint(!swap_token_0_for_1);
+            match hops.get(i + 1) {
+                Some(next_hop) => transfer_from_pool(
+                    &hop.pool,
+                    hop.vault(!swap_token_0_for_1),
+                    next_hop.vault(legs[i + 1].0),
+                    mint_out,
+                    hop.token_program(!swap_token_0_for_1),
+                    hook_accounts,
+                    amount_out_calculated,
+                )?,
+                None => transfer_from_pool(
+                    &hop.pool,
+                    hop.vault(!swap_token_0_for_1),
+                    &ctx.accounts.user_token_out,
+                    mint_out,
+                    hop.token_program(!swap_token_0_for_1),
+                    hook_accounts,
+                    quote_transfer_for_net(mint_out, amount_out_calculated)?.gross_amount,
+                )?,
+            }
+        }
+
+        for hop in &hops {
+            hop.pool.exit(&crate::ID)?;
+        }
+
+        Ok(hop_amount_in)
+    }
+
+    // Lends vault inventory to the caller. The transaction must contain a flash_repay for this
+    // pool after this instruction, which pulls back the loan plus the pool's fee.
+    pub fn flash_loan<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FlashLoan<'info>>,
+        amount_0: u64,
+        amount_1: u64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        require!(amount_0 > 0 || amount_1 > 0, ClmmError::InsufficientInputAmount);
+        require!(pool.global_liquidity > 0, ClmmError::InsufficientPoolLiquidity);
+        require!(pool.flash_loan.is_none(), ClmmError::FlashLoanInProgress);
+        verify_flash_repay(&ctx.accounts.instructions, pool.key())?;
+
+        let loan = OutstandingFlashLoan {
+            amount_0,
+            amount_1,
+            fee_0: get_flash_loan_fee(amount_0, pool.fee_rate)?,
+            fee_1: get_flash_loan_fee(amount_1, pool.fee_rate)?,
+        };
+        pool.flash_loan = Some(loan);
+
+        if amount_0 > 0 {
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.user_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                amount_0,
+            )?;
+        }
+        if amount_1 > 0 {
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.user_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                amount_1,
+            )?;
+        }
+
+        emit_cpi!(FlashLoanTaken {
+            pool: pool.key(),
+            amount_0,
+            amount_1,
+            fee_0: loan.fee_0,
+            fee_1: loan.fee_1,
+        });
+
+        Ok(())
+    }
+
+    pub fn flash_repay<'info>(ctx: Context<'_, '_, 'info, 'info, FlashRepay<'info>>) -> Result<(u64, u64)> {
+        let pool = &mut ctx.accounts.pool;
+        let loan = pool.flash_loan.take().ok_or(ClmmError::NoOutstandingFlashLoan)?;
+
+        let repay_0 = loan.amount_0.checked_add(loan.fee_0).ok_or(ClmmError::ArithmeticOverflow)?;
+        let repay_1 = loan.amount_1.checked_add(loan.fee_1).ok_or(ClmmError::ArithmeticOverflow)?;
+
+        // The vault must get the full repayment back after any transfer fee.
+        if repay_0 > 0 {
+            transfer_from_user(
+                ctx.accounts.payer.to_account_info(),
+                &ctx.accounts.user_token_0,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                quote_transfer_for_net(&ctx.accounts.token_mint_0, repay_0)?.gross_amount,
+            )?;
+        }
+        if repay_1 > 0 {
+            transfer_from_user(
+                ctx.accounts.payer.to_account_info(),
+                &ctx.accounts.user_token_1,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                quote_transfer_for_net(&ctx.accounts.token_mint_1, repay_1)?.gross_amount,
+            )?;
+        }
+
+        if loan.fee_0 > 0 {
+            pool.credit_fee_growth(loan.fee_0, true)?;
+        }
+        if loan.fee_1 > 0 {
+            pool.credit_fee_growth(loan.fee_1, false)?;
+        }
+
+        emit_cpi!(FlashLoanRepaid {
+            pool: pool.key(),
+            amount_0: repay_0,
+            amount_1: repay_1,
+        });
+
+        Ok((repay_0, repay_1))
+    }
+
+    // Pays out first, then calls flash_swap_callback on callback_program with the amounts owed
+    // and the remaining accounts. The swap fails unless the input vault has grown by at least
+    // the input it was priced on by the time the callback returns.
+    pub fn flash_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FlashSwap<'info>>,
+        amount_in: u64,
+        swap_token_0_for_1: bool,
+        amount_out_minimum: u64,
+        callback_data: Vec<u8>,
+    ) -> Result<u64> {
+        let (mint_in, mint_out) = if swap_token_0_for_1 {
+            (&ctx.accounts.token_mint_0, &ctx.accounts.token_mint_1)
+        } else {
+            (&ctx.accounts.token_mint_1, &ctx.accounts.token_mint_0)
+        };
+
+        let quote = compute_swap(&ctx.accounts.pool, mint_in, amount_in, swap_token_0_for_1)?;
+        require!(
+            quote.amount_out >= amount_out_minimum,
+            ClmmError::SlippageExceeded
+        );
+        let required_amount_in = quote_transfer_from_gross(mint_in, quote.amount_in)?.net_amount;
+        let transfer_out = quote_transfer_for_net(mint_out, quote.amount_out)?;
+
+        let vault_in_before = if swap_token_0_for_1 {
+            ctx.accounts.pool_token_0.amount
+        } else {
+            ctx.accounts.pool_token_1.amount
+        };
+
+        if swap_token_0_for_1 {
+            transfer_from_pool(
+                &ctx.accounts.pool,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.user_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                transfer_out.gross_amount,
+            )?;
+        } else {
+            transfer_from_pool(
+                &ctx.accounts.pool,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.user_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                transfer_out.gross_amount,
+            )?;
+        }
+
+        invoke_flash_swap_callback(
+            &ctx.accounts.callback_program,
+            ctx.remaining_accounts,
+            FlashSwapCallbackArgs {
+                amount_0_owed: if swap_token_0_for_1 { quote.amount_in } else { 0 },
+                amount_1_owed: if swap_token_0_for_1 { 0 } else { quote.amount_in },
+                data: callback_data,
+            },
+        )?;
+
+        let vault_in_after = if swap_token_0_for_1 {
+            ctx.accounts.pool_token_0.reload()?;
+            ctx.accounts.pool_token_0.amount
+        } else {
+            ctx.accounts.pool_token_1.reload()?;
+            ctx.accounts.pool_token_1.amount
+        };
+        require!(
+            vault_in_after.saturating_sub(vault_in_before) >= required_amount_in,
+            ClmmError::FlashSwapUnderpaid
+        );
+
+        let pool = &mut ctx.accounts.pool;
+        let sqrt_price_before_x96 = pool.sqrt_price_x96;
+        pool.accrue_swap_fee(quote.fee_amount, swap_token_0_for_1)?;
+        pool.sqrt_price_x96 = quote.sqrt_price_x96;
+        pool.current_tick = quote.tick;
+
+        emit_cpi!(Swapped {
+            pool: pool.key(),
+            swap_token_0_for_1,
+            amount_in: quote.amount_in,
+            amount_out: quote.amount_out,
+            fee_amount: quote.fee_amount,
+            sqrt_price_before_x96,
+            sqrt_price_after_x96: pool.sqrt_price_x96,
+            tick: pool.current_tick,
+            liquidity: pool.global_liquidity,
+        });
+
+        Ok(quote.amount_out)
+    }
+
+    pub fn collect_fees<'info>(ctx: Context<'_, '_, 'info, 'info, CollectFees<'info>>) -> Result<(u64, u64)> {
+        let pool = &ctx.accounts.pool;
+        let position = &mut ctx.accounts.position;
+
+        position.update_fees(pool.fee_growth_global_0_x64, pool.fee_growth_global_1_x64)?;
+
+        let amount_0 = position.tokens_owed_0;
+        let amount_1 = position.tokens_owed_1;
+        position.tokens_owed_0 = 0;
+        position.tokens_owed_1 = 0;
+
+        if amount_0 > 0 {
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.user_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                amount_0,
+            )?;
+        }
+
+        if amount_1 > 0 {
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.user_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                amount_1,
+            )?;
+        }
+
+        emit_cpi!(FeesCollected {
+            pool: pool.key(),
+            position: position.key(),
+            bundle_index: None,
+            amount_0,
+            amount_1,
+        });
+
+        Ok((amount_0, amount_1))
+    }
+
+    pub fn initialize_position_bundle(ctx: Context<InitializePositionBundle>) -> Result<()> {
+        let position_bundle = &mut ctx.accounts.position_bundle.load_init()?;
+
+        position_bundle.owner = ctx.accounts.payer.key();
+        position_bundle.pool = ctx.accounts.pool.key();
+        position_bundle.position_bitmap = 0;
+        position_bundle.bump = ctx.bumps.position_bundle;
+
+        emit_cpi!(PositionBundleInitialized {
+            pool: ctx.accounts.pool.key(),
+            position_bundle: ctx.accounts.position_bundle.key(),
+            owner: ctx.accounts.payer.key(),
+        });
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn open_bundled_position<'info>(
+        ctx: Context<'_, '_, 'info, 'info, OpenBundledPosition<'info>>,
+        bundle_index: u16,
+        lower_tick: i32,
+        upper_tick: i32,
+        liquidity_amount: u128,
+        tick_array_lower_start_index: i32,
+        tick_array_upper_start_index: i32,
+        amount_0_max: u64,
+        amount_1_max: u64,
+    ) -> Result<(u64, u64)> {
+        let pool = &mut ctx.accounts.pool;
+        let position_bundle = &mut ctx.accounts.position_bundle.load_mut()?;
+
+        require!(lower_tick < upper_tick, ClmmError::InvalidTickRange);
+        require!(
+            lower_tick % pool.tick_spacing == 0,
+            ClmmError::InvalidTickRange
+        );
+        require!(
+            upper_tick % pool.tick_spacing == 0,
+            ClmmError::InvalidTickRange
+        );
+        require!(liquidity_amount > 0, ClmmError::InsufficientInputAmount);
+
+        let lower_tick_array = &mut load_tick_array_init_if_needed(&ctx.accounts.lower_tick_array)?;
+        let upper_tick_array = &mut load_tick_array_init_if_needed(&ctx.accounts.upper_tick_array)?;
+
+        if lower_tick_array.pool == Pubkey::default() {
+            lower_tick_array.pool = pool.key();
+            lower_tick_array.starting_tick = tick_array_lower_start_index;
+        }
+
+        if upper_tick_array.pool == Pubkey::default() {
+            upper_tick_array.pool = pool.key();
+            upper_tick_array.starting_tick = tick_array_upper_start_index;
+        }
+
+        require!(
+            lower_tick_array.contains_tick(lower_tick, pool.tick_spacing),
+            ClmmError::InvalidTickArray
+        );
+        require!(
+            upper_tick_array.contains_tick(upper_tick, pool.tick_spacing),
+            ClmmError::InvalidTickArray
+        );
+
+        let lower_tick_info = lower_tick_array.get_tick_info_mutable(lower_tick, pool.tick_spacing)?;
+        let upper_tick_info = upper_tick_array.get_tick_info_mutable(upper_tick, pool.tick_spacing)?;
+
+        lower_tick_info.update_liquidity(liquidity_amount as i128, true)?;
+        upper_tick_info.update_liquidity(liquidity_amount as i128, false)?;
+
+        let (amount_0, amount_1) = get_amounts_for_liquidity(
+            pool.sqrt_price_x96,
+            get_sqrt_price_from_tick(lower_tick)?,
+            get_sqrt_price_from_tick(upper_tick)?,
+            liquidity_amount,
+        )?;
+
+        let position = position_bundle.open_position(bundle_index)?;
+        position.tick_lower = lower_tick;
+        position.tick_upper = upper_tick;
+        position.set_liquidity(liquidity_amount);
+        position.set_fee_growth_inside_last_x64(pool.fee_growth_global_0_x64, pool.fee_growth_global_1_x64);
+
+        pool.global_liquidity = pool
+            .global_liquidity
+            .checked_add(liquidity_amount)
+            .ok_or(ClmmError::ArithmeticOverflow)?;
+
+        let (transfer_0, transfer_1) = quote_deposit(
+            &ctx.accounts.token_mint_0,
+            &ctx.accounts.token_mint_1,
+            amount_0,
+            amount_1,
+            amount_0_max,
+            amount_1_max,
+        )?;
+
+        if transfer_0.gross_amount > 0 {
+            transfer_from_user(
+                ctx.accounts.payer.to_account_info(),
+                &ctx.accounts.user_token_0,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                transfer_0.gross_amount,
+            )?;
+        }
+
+        if transfer_1.gross_amount > 0 {
+            transfer_from_user(
+                ctx.accounts.payer.to_account_info(),
+                &ctx.accounts.user_token_1,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                transfer_1.gross_amount,
+            )?;
+        }
+
+        emit_cpi!(PositionOpened {
+            pool: pool.key(),
+            position: ctx.accounts.position_bundle.key(),
+            bundle_index: Some(bundle_index),
+            owner: position_bundle.owner,
+            tick_lower: lower_tick,
+            tick_upper: upper_tick,
+            liquidity: liquidity_amount,
+            amount_0: transfer_0.gross_amount,
+            amount_1: transfer_1.gross_amount,
+        });
+
+        Ok((transfer_0.gross_amount, transfer_1.gross_amount))
+    }
+
+    pub fn increase_bundled_liquidity<'info>(
+        ctx: Context<'_, '_, 'info, 'info, IncreaseBundledLiquidity<'info>>,
+        bundle_index: u16,
+        liquidity_amount: u128,
+        amount_0_max: u64,
+        amount_1_max: u64,
+    ) -> Result<(u64, u64)> {
+        let pool = &mut ctx.accounts.pool;
+        let position_bundle = &mut ctx.accounts.position_bundle.load_mut()?;
+        let position = position_bundle.get_position_mutable(bundle_index)?;
+
+        require!(liquidity_amount > 0, ClmmError::InsufficientInputAmount);
+
+        let lower_tick_array = &mut ctx.accounts.lower_tick_array.load_mut()?;
+        let upper_tick_array = &mut ctx.accounts.upper_tick_array.load_mut()?;
+
+        let lower_tick_info = lower_tick_array.get_tick_info_mutable(position.tick_lower, pool.tick_spacing)?;
+        let upper_tick_info = upper_tick_array.get_tick_info_mutable(position.tick_upper, pool.tick_spacing)?;
+
+        lower_tick_info.update_liquidity(liquidity_amount as i128, true)?;
+        upper_tick_info.update_liquidity(liquidity_amount as i128, false)?;
+
+        position.update_fees(pool.fee_growth_global_0_x64, pool.fee_growth_global_1_x64)?;
+        let new_liquidity = position
+            .get_liquidity()
+            .checked_add(liquidity_amount)
+            .ok_or(ClmmError::ArithmeticOverflow)?;
+        position.set_liquidity(new_liquidity);
+
+        let (amount_0, amount_1) = get_amounts_for_liquidity(
+            pool.sqrt_price_x96,
+            get_sqrt_price_from_tick(position.tick_lower)?,
+            get_sqrt_price_from_tick(position.tick_upper)?,
+            liquidity_amount,
+        )?;
+
+        pool.global_liquidity = pool
+            .global_liquidity
+            .checked_add(liquidity_amount)
+            .ok_or(ClmmError::ArithmeticOverflow)?;
+
+        let (transfer_0, transfer_1) = quote_deposit(
+            &ctx.accounts.token_mint_0,
+            &ctx.accounts.token_mint_1,
+            amount_0,
+            amount_1,
+            amount_0_max,
+            amount_1_max,
+        )?;
+
+        if transfer_0.gross_amount > 0 {
+            transfer_from_user(
+                ctx.accounts.payer.to_account_info(),
+                &ctx.accounts.user_token_0,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                transfer_0.gross_amount,
+            )?;
+        }
+
+        if transfer_1.gross_amount > 0 {
+            transfer_from_user(
+                ctx.accounts.payer.to_account_info(),
+                &ctx.accounts.user_token_1,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                transfer_1.gross_amount,
+            )?;
+        }
+
+        emit_cpi!(LiquidityIncreased {
+            pool: pool.key(),
+            position: ctx.accounts.position_bundle.key(),
+            bundle_index: Some(bundle_index),
+            liquidity_delta: liquidity_amount,
+            liquidity: new_liquidity,
+            amount_0: transfer_0.gross_amount,
+            amount_1: transfer_1.gross_amount,
+        });
+
+        Ok((transfer_0.gross_amount, transfer_1.gross_amount))
+    }
+
+    pub fn decrease_bundled_liquidity<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DecreaseBundledLiquidity<'info>>,
+        bundle_index: u16,
+        liquidity_amount: u128,
+        amount_0_min: u64,
+        amount_1_min: u64,
+    ) -> Result<(u64, u64)> {
+        let pool = &mut ctx.accounts.pool;
+        let position_bundle = &mut ctx.accounts.position_bundle.load_mut()?;
+        let position = position_bundle.get_position_mutable(bundle_index)?;
+
+        require!(liquidity_amount > 0, ClmmError::InsufficientInputAmount);
+        require!(
+            position.get_liquidity() >= liquidity_amount,
+            ClmmError::NoLiquidityToRemove
+        );
+
+        let lower_tick_array = &mut ctx.accounts.lower_tick_array.load_mut()?;
+        let upper_tick_array = &mut ctx.accounts.upper_tick_array.load_mut()?;
+
+        let lower_tick_info = lower_tick_array.get_tick_info_mutable(position.tick_lower, pool.tick_spacing)?;
+        let upper_tick_info = upper_tick_array.get_tick_info_mutable(position.tick_upper, pool.tick_spacing)?;
+
+        lower_tick_info.update_liquidity_decrease(liquidity_amount as i128, true)?;
+        upper_tick_info.update_liquidity_decrease(liquidity_amount as i128, false)?;
+
+        position.update_fees(pool.fee_growth_global_0_x64, pool.fee_growth_global_1_x64)?;
+        let new_liquidity = position
+            .get_liquidity()
+            .checked_sub(liquidity_amount)
+            .ok_or(ClmmError::ArithmeticOverflow)?;
+        position.set_liquidity(new_liquidity);
+
+        let (amount_0, amount_1) = get_amounts_for_liquidity(
+            pool.sqrt_price_x96,
+            get_sqrt_price_from_tick(position.tick_lower)?,
+            get_sqrt_price_from_tick(position.tick_upper)?,
+            liquidity_amount,
+        )?;
+
+        // Withdrawals bear the transfer fee, so the minimums apply to what the user receives
+        let received_0 = quote_transfer_from_gross(&ctx.accounts.token_mint_0, amount_0)?.net_amount;
+        let received_1 = quote_transfer_from_gross(&ctx.accounts.token_mint_1, amount_1)?.net_amount;
+        require!(
+            received_0 >= amount_0_min && received_1 >= amount_1_min,
+            ClmmError::SlippageExceeded
+        );
+
+        position_bundle.close_position_if_empty(bundle_index)?;
+
+        pool.global_liquidity = pool
+            .global_liquidity
+            .checked_sub(liquidity_amount)
+            .ok_or(ClmmError::ArithmeticOverflow)?;
+
+        if amount_0 > 0 {
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.user_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                amount_0,
+            )?;
+        }
+
+        if amount_1 > 0 {
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.user_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                amount_1,
+            )?;
+        }
+
+        emit_cpi!(LiquidityDecreased {
+            pool: pool.key(),
+            position: ctx.accounts.position_bundle.key(),
+            bundle_index: Some(bundle_index),
+            liquidity_delta: liquidity_amount,
+            liquidity: new_liquidity,
+            amount_0,
+            amount_1,
+        });
+
+        Ok((amount_0, amount_1))
+    }
+
+    pub fn collect_bundled_fees<'info>(ctx: Context<'_, '_, 'info, 'info, CollectBundledFees<'info>>, bundle_index: u16) -> Result<(u64, u64)> {
+        let pool = &ctx.accounts.pool;
+        let position_bundle = &mut ctx.accounts.position_bundle.load_mut()?;
+        let position = position_bundle.get_position_mutable(bundle_index)?;
+
+        position.update_fees(pool.fee_growth_global_0_x64, pool.fee_growth_global_1_x64)?;
+
+        let amount_0 = position.tokens_owed_0;
+        let amount_1 = position.tokens_owed_1;
+        position.tokens_owed_0 = 0;
+        position.tokens_owed_1 = 0;
+
+        position_bundle.close_position_if_empty(bundle_index)?;
+
+        if amount_0 > 0 {
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_0,
+                &ctx.accounts.user_token_0,
+                &ctx.accounts.token_mint_0,
+                &ctx.accounts.token_program_0,
+                ctx.remaining_accounts,
+                amount_0,
+            )?;
+        }
+
+        if amount_1 > 0 {
+            transfer_from_pool(
+                pool,
+                &ctx.accounts.pool_token_1,
+                &ctx.accounts.user_token_1,
+                &ctx.accounts.token_mint_1,
+                &ctx.accounts.token_program_1,
+                ctx.remaining_accounts,
+                amount_1,
+            )?;
+        }
+
+        emit_cpi!(FeesCollected {
+            pool: pool.key(),
+            position: ctx.accounts.position_bundle.key(),
+            bundle_index: Some(bundle_index),
+            amount_0,
+            amount_1,
+        });
+
+        Ok((amount_0, amount_1))
+    }
+
+    pub fn get_position_value(ctx: Context<GetPositionValue>) -> Result<PositionValue> {
+        let pool = &ctx.accounts.pool;
+        let position = &ctx.accounts.position;
+
+        let lower_sqrt_price_x96 = get_sqrt_price_from_tick(position.tick_lower)?;
+        let upper_sqrt_price_x96 = get_sqrt_price_from_tick(position.tick_upper)?;
+
+        let (amount_0, amount_1) = get_amounts_for_liquidity(
+            pool.sqrt_price_x96,
+            lower_sqrt_price_x96,
+            upper_sqrt_price_x96,
+            position.liquidity,
+        )?;
+
+        let fees_owed_0 = get_fees_owed(
+            position.liquidity,
+            pool.fee_growth_global_0_x64,
+            position.fee_growth_inside_0_last_x64,
+        )?
+        .checked_add(position.tokens_owed_0)
+        .ok_or(ClmmError::ArithmeticOverflow)?;
+        let fees_owed_1 = get_fees_owed(
+            position.liquidity,
+            pool.fee_growth_global_1_x64,
+            position.fee_growth_inside_1_last_x64,
+        )?
+        .checked_add(position.tokens_owed_1)
+        .ok_or(ClmmError::ArithmeticOverflow)?;
+
+        Ok(PositionValue {
+            amount_0,
+            amount_1,
+            fees_owed_0,
+            fees_owed_1,
+            in_range: pool.sqrt_price_x96 >= lower_sqrt_price_x96
+                && pool.sqrt_price_x96 < upper_sqrt_price_x96,
+        })
+    }
+
+    // Runs the same computation as swap without moving tokens or writing to the pool;
+    // intended to be called through simulateTransaction.
+    // remaining_accounts: the tick arrays at get_crossed_tick_array_start_indexes, in that
+    // order, so the initialized ticks crossed can be counted. Arrays that were never
+    // created hold no initialized ticks and are passed as their empty addresses.
+    pub fn quote_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, QuoteSwap<'info>>,
+        amount_in: u64,
+        swap_token_0_for_1: bool,
+    ) -> Result<SwapQuote> {
+        let pool = &ctx.accounts.pool;
+        let mint_in = if swap_token_0_for_1 {
+            &ctx.accounts.token_mint_0
+        } else {
+            &ctx.accounts.token_mint_1
+        };
+        let mut quote = compute_swap(pool, mint_in, amount_in, swap_token_0_for_1)?;
+
+        let start_indexes = get_crossed_tick_array_start_indexes(pool.tick_spacing, pool.current_tick, quote.tick);
+        require!(
+            ctx.remaining_accounts.len() == start_indexes.len(),
+            ClmmError::MissingTickArray
+        );
+        let mut tick_arrays = Vec::with_capacity(start_indexes.len());
+        for (account, start_index) in ctx.remaining_accounts.iter().zip(start_indexes) {
+            let (address, _) = Pubkey::find_program_address(
+                &[b"tick_array", pool.key().as_ref(), &start_index.to_le_bytes()],
+                &crate::ID,
+            );
+            require_keys_eq!(account.key(), address, ClmmError::InvalidTickArray);
+            if account.data_is_empty() {
+                continue;
+            }
+            tick_arrays.push(*AccountLoader::<TickArray>::try_from(account)?.load()?);
+        }
+        quote.ticks_crossed =
+            count_initialized_ticks_crossed(&tick_arrays, pool.tick_spacing, pool.current_tick, quote.tick);
+
+        Ok(quote)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PositionValue {
+    pub amount_0: u64,
+    pub amount_1: u64,
+    pub fees_owed_0: u64,
+    pub fees_owed_1: u64,
+    pub in_range: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SwapQuote {
+    // Sent by the user, including the input mint's transfer fee.
+    pub amount_in: u64,
+    // Received by the user, after the output mint's transfer fee.
+    pub amount_out: u64,
+    pub fee_amount: u64,
+    pub sqrt_price_x96: u128,
+    pub tick: i32,
+    // Initialized ticks between the current and the end tick. Only quote_swap counts them,
+    // from the tick arrays it is passed; compute_swap leaves 0 for the swap instructions.
+    pub ticks_crossed: u32,
+    // Relative move of the pool price, in FEE_RATE_DENOMINATOR units.
+    pub price_impact: u32,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitializeGlobalConfig<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = GlobalConfig::SPACE,
+        seeds = [b"global_config"],
+        bump,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        seeds = [crate::ID.as_ref()],
+        bump,
+        seeds::program = ProgramData::owner(),
+        constraint = program_data.upgrade_authority_address == Some(payer.key()) @ ClmmError::Unauthorized,
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct TransferAuthority<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ClmmError::Unauthorized,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    pub pending_authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = pending_authority @ ClmmError::Unauthorized,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ManageRole<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ClmmError::Unauthorized,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetPoolStatus<'info> {
+    pub pauser: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = global_config.has_role(Role::Pauser, &pauser.key()) @ ClmmError::Unauthorized,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetPauseFlags<'info> {
+    pub pauser: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = global_config.has_role(Role::Pauser, &pauser.key()) @ ClmmError::Unauthorized,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct QueuePoolChange<'info> {
+    pub fee_manager: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = global_config.has_role(Role::FeeManager, &fee_manager.key()) @ ClmmError::Unauthorized,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct QueueConfigChange<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ClmmError::Unauthorized,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ExecutePendingChange<'info> {
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub pool: Option<Account<'info, Pool>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CancelPendingChange<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub pool: Option<Account<'info, Pool>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CollectProtocolFees<'info> {
+    pub fee_manager: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = global_config.has_role(Role::FeeManager, &fee_manager.key()) @ ClmmError::Unauthorized,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_WITHDRAW) @ ClmmError::WithdrawPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        has_one = token_mint_0,
+        has_one = token_mint_1,
+        constraint = pool.flash_loan.is_none() @ ClmmError::FlashLoanInProgress,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = token_mint_0)]
+    pub recipient_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = token_mint_1)]
+    pub recipient_token_1: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct FundRewards<'info> {
+    pub reward_funder: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = global_config.has_role(Role::RewardFunder, &reward_funder.key()) @ ClmmError::Unauthorized,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_DEPOSIT) @ ClmmError::DepositPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        has_one = token_mint_0,
+        has_one = token_mint_1,
+        constraint = pool.status == PoolStatus::Active @ ClmmError::PoolWithdrawOnly,
+        constraint = pool.flash_loan.is_none() @ ClmmError::FlashLoanInProgress,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut, token::mint = token_mint_0)]
+    pub funder_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = token_mint_1)]
+    pub funder_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(tick_spacing: i32)]
+pub struct InitializePool<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_CREATE_POOL) @ ClmmError::CreatePoolPaused,
+        constraint = global_config.has_role(Role::PoolCreator, &payer.key()) @ ClmmError::Unauthorized,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = Pool::SPACE,
+        seeds = [
             b"pool",
             token_mint_0.key().as_ref(),
             token_mint_1.key().as_ref(),
@@ -407,37 +2106,653 @@ pub struct InitializePool<'info> {
     )]
     pub pool: Account<'info, Pool>,
 
-    pub token_mint_0: Account<'info, Mint>,
-    pub token_mint_1: Account<'info, Mint>,
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"token_vault", pool.key().as_ref(), token_mint_0.key().as_ref()],
+        bump,
+        token::mint = token_mint_0,
+        token::authority = pool,
+        token::token_program = token_program_0,
+    )]
+    pub token_vault_0: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"token_vault", pool.key().as_ref(), token_mint_1.key().as_ref()],
+        bump,
+        token::mint = token_mint_1,
+        token::authority = pool,
+        token::token_program = token_program_1,
+    )]
+    pub token_vault_1: InterfaceAccount<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(owner: Pubkey, lower_tick: i32, upper_tick: i32, liquidity_amount: u128, tick_array_lower_start_index: i32, tick_array_upper_start_index: i32)]
+pub struct OpenPosition<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_DEPOSIT) @ ClmmError::DepositPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        has_one = token_mint_0,
+        has_one = token_mint_1,
+        constraint = pool.status == PoolStatus::Active @ ClmmError::PoolWithdrawOnly,
+        constraint = pool.flash_loan.is_none() @ ClmmError::FlashLoanInProgress,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + std::mem::size_of::<TickArray>(),
+        seeds = [b"tick_array", pool.key().as_ref(), &tick_array_lower_start_index.to_le_bytes()],
+        bump
+    )]
+    pub lower_tick_array: AccountLoader<'info, TickArray>,
+    
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + std::mem::size_of::<TickArray>(),
+        seeds = [b"tick_array", pool.key().as_ref(), &tick_array_upper_start_index.to_le_bytes()],
+        bump
+    )]
+    pub upper_tick_array: AccountLoader<'info, TickArray>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = Position::SPACE,
+        seeds = [
+            b"position",
+            owner.as_ref(),
+            pool.key().as_ref(),
+            &lower_tick.to_le_bytes(),
+            &upper_tick.to_le_bytes(),
+        ],
+        bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::authority = payer,
+    )]
+    pub user_token_0: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::authority = payer,
+    )]
+    pub user_token_1: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: created and closed within the instruction when the native mint side's user token
+    /// account is omitted; the seeds bind it to the payer.
+    #[account(mut, seeds = [b"wsol", payer.key().as_ref()], bump)]
+    pub wsol_account: Option<UncheckedAccount<'info>>,
+
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub system_program: Program<'info, System>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct IncreaseLiquidity<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_DEPOSIT) @ ClmmError::DepositPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        has_one = token_mint_0,
+        has_one = token_mint_1,
+        constraint = pool.status == PoolStatus::Active @ ClmmError::PoolWithdrawOnly,
+        constraint = pool.flash_loan.is_none() @ ClmmError::FlashLoanInProgress,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"tick_array", pool.key().as_ref(), &lower_tick_array.load()?.starting_tick.to_le_bytes()],
+        bump,
+        constraint = lower_tick_array.load()?.pool == pool.key() @ ClmmError::InvalidTickArray,
+        constraint = lower_tick_array.load()?.contains_tick(position.tick_lower, pool.tick_spacing) @ ClmmError::InvalidTickArray,
+    )]
+    pub lower_tick_array: AccountLoader<'info, TickArray>,
+
+    #[account(
+        mut,
+        seeds = [b"tick_array", pool.key().as_ref(), &upper_tick_array.load()?.starting_tick.to_le_bytes()],
+        bump,
+        constraint = upper_tick_array.load()?.pool == pool.key() @ ClmmError::InvalidTickArray,
+        constraint = upper_tick_array.load()?.contains_tick(position.tick_upper, pool.tick_spacing) @ ClmmError::InvalidTickArray,
+    )]
+    pub upper_tick_array: AccountLoader<'info, TickArray>,
+
+    #[account(
+        mut,
+        constraint = position.pool == pool.key() @ ClmmError::InvalidPositionRange,
+        constraint = position.owner == payer.key() @ ClmmError::InvalidPositionOwner,
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::authority = payer,
+    )]
+    pub user_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::authority = payer,
+    )]
+    pub user_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct DecreaseLiquidity<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_WITHDRAW) @ ClmmError::WithdrawPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    #[account(
+        mut,
+        has_one = token_mint_0,
+        has_one = token_mint_1,
+        constraint = pool.flash_loan.is_none() @ ClmmError::FlashLoanInProgress,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"tick_array", pool.key().as_ref(), &lower_tick_array.load()?.starting_tick.to_le_bytes()],
+        bump,
+        constraint = lower_tick_array.load()?.pool == pool.key() @ ClmmError::InvalidTickArray,
+        constraint = lower_tick_array.load()?.contains_tick(position.tick_lower, pool.tick_spacing) @ ClmmError::InvalidTickArray,
+    )]
+    pub lower_tick_array: AccountLoader<'info, TickArray>,
+
+    #[account(
+        mut,
+        seeds = [b"tick_array", pool.key().as_ref(), &upper_tick_array.load()?.starting_tick.to_le_bytes()],
+        bump,
+        constraint = upper_tick_array.load()?.pool == pool.key() @ ClmmError::InvalidTickArray,
+        constraint = upper_tick_array.load()?.contains_tick(position.tick_upper, pool.tick_spacing) @ ClmmError::InvalidTickArray,
+    )]
+    pub upper_tick_array: AccountLoader<'info, TickArray>,
+
+    #[account(
+        mut,
+        constraint = position.pool == pool.key() @ ClmmError::InvalidPositionRange,
+        constraint = position.owner == payer.key() @ ClmmError::InvalidPositionOwner,
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::authority = payer,
+    )]
+    pub user_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::authority = payer,
+    )]
+    pub user_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub system_program: Program<'info, System>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct EmergencyWithdraw<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_WITHDRAW) @ ClmmError::WithdrawPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = token_mint_0,
+        has_one = token_mint_1,
+        constraint = pool.status == PoolStatus::WithdrawOnly @ ClmmError::PoolNotWithdrawOnly,
+        constraint = pool.flash_loan.is_none() @ ClmmError::FlashLoanInProgress,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    // Checked by clear_position_ticks rather than here, so a bad tick array can't block the exit.
+    #[account(mut)]
+    pub lower_tick_array: Option<AccountLoader<'info, TickArray>>,
+    #[account(mut)]
+    pub upper_tick_array: Option<AccountLoader<'info, TickArray>>,
+
+    #[account(
+        mut,
+        constraint = position.pool == pool.key() @ ClmmError::InvalidPositionRange,
+        constraint = position.owner == payer.key() @ ClmmError::InvalidPositionOwner,
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(mut, token::mint = token_mint_0)]
+    pub user_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = token_mint_1)]
+    pub user_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(bundle_index: u16)]
+pub struct EmergencyWithdrawBundled<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_WITHDRAW) @ ClmmError::WithdrawPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = token_mint_0,
+        has_one = token_mint_1,
+        constraint = pool.status == PoolStatus::WithdrawOnly @ ClmmError::PoolNotWithdrawOnly,
+        constraint = pool.flash_loan.is_none() @ ClmmError::FlashLoanInProgress,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut)]
+    pub lower_tick_array: Option<AccountLoader<'info, TickArray>>,
+    #[account(mut)]
+    pub upper_tick_array: Option<AccountLoader<'info, TickArray>>,
+
+    #[account(
+        mut,
+        seeds = [b"position_bundle", payer.key().as_ref(), pool.key().as_ref()],
+        bump = position_bundle.load()?.bump,
+    )]
+    pub position_bundle: AccountLoader<'info, PositionBundle>,
+
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::authority = payer,
+    )]
+    pub user_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::authority = payer,
+    )]
+    pub user_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_SWAP) @ ClmmError::SwapPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        has_one = token_mint_0,
+        has_one = token_mint_1,
+        constraint = pool.status == PoolStatus::Active @ ClmmError::PoolWithdrawOnly,
+        constraint = pool.flash_loan.is_none() @ ClmmError::FlashLoanInProgress,
+    )]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::authority = payer,
+    )]
+    pub user_token_0: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::authority = payer,
+    )]
+    pub user_token_1: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: created and closed within the instruction when the native mint side's user token
+    /// account is omitted; the seeds bind it to the payer.
+    #[account(mut, seeds = [b"wsol", payer.key().as_ref()], bump)]
+    pub wsol_account: Option<UncheckedAccount<'info>>,
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub system_program: Program<'info, System>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SwapRoute<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_SWAP) @ ClmmError::SwapPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut, token::authority = payer)]
+    pub user_token_in: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::authority = payer)]
+    pub user_token_out: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct FlashLoan<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_SWAP) @ ClmmError::SwapPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        has_one = token_mint_0,
+        has_one = token_mint_1,
+        constraint = pool.status == PoolStatus::Active @ ClmmError::PoolWithdrawOnly,
+    )]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, token::mint = token_mint_0)]
+    pub user_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = token_mint_1)]
+    pub user_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+    pub payer: Signer<'info>,
+
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+    /// CHECK: the instructions sysvar, used to find the matching flash_repay.
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+// verify_flash_repay expects pool to stay the first account.
+#[event_cpi]
+#[derive(Accounts)]
+pub struct FlashRepay<'info> {
+    #[account(mut, has_one = token_mint_0, has_one = token_mint_1)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::authority = payer,
+    )]
+    pub user_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::authority = payer,
+    )]
+    pub user_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+    pub payer: Signer<'info>,
+
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct FlashSwap<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_SWAP) @ ClmmError::SwapPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        has_one = token_mint_0,
+        has_one = token_mint_1,
+        constraint = pool.status == PoolStatus::Active @ ClmmError::PoolWithdrawOnly,
+        constraint = pool.flash_loan.is_none() @ ClmmError::FlashLoanInProgress,
+    )]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, token::mint = token_mint_0)]
+    pub user_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = token_mint_1)]
+    pub user_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+    pub payer: Signer<'info>,
+
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+    /// CHECK: only invoked, with no pool signature; calling back into this program is refused.
+    #[account(
+        executable,
+        constraint = callback_program.key() != crate::ID @ ClmmError::InvalidCallbackProgram,
+    )]
+    pub callback_program: UncheckedAccount<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CollectFees<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_WITHDRAW) @ ClmmError::WithdrawPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        has_one = token_mint_0,
+        has_one = token_mint_1,
+        constraint = pool.flash_loan.is_none() @ ClmmError::FlashLoanInProgress,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        constraint = position.pool == pool.key() @ ClmmError::InvalidPositionRange,
+        constraint = position.owner == payer.key() @ ClmmError::InvalidPositionOwner,
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(mut, token::mint = token_mint_0)]
+    pub user_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = token_mint_1)]
+    pub user_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+
+    pub payer: Signer<'info>,
+
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+}
 
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitializePositionBundle<'info> {
     #[account(
-        init,
-        payer = payer,
-        token::mint = token_mint_0,
-        token::authority = pool,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_DEPOSIT) @ ClmmError::DepositPaused,
     )]
-    pub token_vault_0: Account<'info, TokenAccount>,
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub pool: Account<'info, Pool>,
 
     #[account(
         init,
         payer = payer,
-        token::mint = token_mint_1,
-        token::authority = pool,
+        space = 8 + std::mem::size_of::<PositionBundle>(),
+        seeds = [b"position_bundle", payer.key().as_ref(), pool.key().as_ref()],
+        bump
     )]
-    pub token_vault_1: Account<'info, TokenAccount>,
+    pub position_bundle: AccountLoader<'info, PositionBundle>,
 
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
-#[instruction(owner: Pubkey, lower_tick: i32, upper_tick: i32, liquidity_amount: u128, tick_array_lower_start_index: i32, tick_array_upper_start_index: i32)]
-pub struct OpenPosition<'info> {
+#[instruction(bundle_index: u16, lower_tick: i32, upper_tick: i32, liquidity_amount: u128, tick_array_lower_start_index: i32, tick_array_upper_start_index: i32)]
+pub struct OpenBundledPosition<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_DEPOSIT) @ ClmmError::DepositPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
     #[account(
         mut,
         has_one = token_mint_0,
         has_one = token_mint_1,
+        constraint = pool.status == PoolStatus::Active @ ClmmError::PoolWithdrawOnly,
+        constraint = pool.flash_loan.is_none() @ ClmmError::FlashLoanInProgress,
     )]
     pub pool: Account<'info, Pool>,
 
@@ -449,7 +2764,7 @@ pub struct OpenPosition<'info> {
         bump
     )]
     pub lower_tick_array: AccountLoader<'info, TickArray>,
-    
+
     #[account(
         init_if_needed,
         payer = payer,
@@ -460,135 +2775,535 @@ pub struct OpenPosition<'info> {
     pub upper_tick_array: AccountLoader<'info, TickArray>,
 
     #[account(
-        init_if_needed,
-        payer = payer,
-        space = Position::SPACE,
-        seeds = [
-            b"position",
-            owner.as_ref(),
-            pool.key().as_ref(),
-            &lower_tick.to_le_bytes(),
-            &upper_tick.to_le_bytes(),
-        ],
-        bump
+        mut,
+        seeds = [b"position_bundle", payer.key().as_ref(), pool.key().as_ref()],
+        bump = position_bundle.load()?.bump,
     )]
-    pub position: Box<Account<'info, Position>>,
+    pub position_bundle: AccountLoader<'info, PositionBundle>,
 
-    #[account(mut)]
-    pub user_token_0: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub user_token_1: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub pool_token_0: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub pool_token_1: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::authority = payer,
+    )]
+    pub user_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::authority = payer,
+    )]
+    pub user_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
     pub payer: Signer<'info>,
 
-    pub token_mint_0: Account<'info, Mint>,
-    pub token_mint_1: Account<'info, Mint>,
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
     pub rent: Sysvar<'info, Rent>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
-pub struct IncreaseLiquidity<'info> {
+#[instruction(bundle_index: u16)]
+pub struct IncreaseBundledLiquidity<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_DEPOSIT) @ ClmmError::DepositPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
     #[account(
         mut,
         has_one = token_mint_0,
         has_one = token_mint_1,
+        constraint = pool.status == PoolStatus::Active @ ClmmError::PoolWithdrawOnly,
+        constraint = pool.flash_loan.is_none() @ ClmmError::FlashLoanInProgress,
     )]
     pub pool: Account<'info, Pool>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"tick_array", pool.key().as_ref(), &lower_tick_array.load()?.starting_tick.to_le_bytes()],
+        bump,
+        constraint = lower_tick_array.load()?.pool == pool.key() @ ClmmError::InvalidTickArray,
+        constraint = lower_tick_array
+            .load()?
+            .contains_tick(position_bundle.load()?.get_position(bundle_index)?.tick_lower, pool.tick_spacing)
+            @ ClmmError::InvalidTickArray,
+    )]
     pub lower_tick_array: AccountLoader<'info, TickArray>,
-    #[account(mut)]
+
+    #[account(
+        mut,
+        seeds = [b"tick_array", pool.key().as_ref(), &upper_tick_array.load()?.starting_tick.to_le_bytes()],
+        bump,
+        constraint = upper_tick_array.load()?.pool == pool.key() @ ClmmError::InvalidTickArray,
+        constraint = upper_tick_array
+            .load()?
+            .contains_tick(position_bundle.load()?.get_position(bundle_index)?.tick_upper, pool.tick_spacing)
+            @ ClmmError::InvalidTickArray,
+    )]
     pub upper_tick_array: AccountLoader<'info, TickArray>,
 
     #[account(
         mut,
-        constraint = position.pool == pool.key() @ ClmmError::InvalidPositionRange,
-        constraint = position.owner == payer.key() @ ClmmError::InvalidPositionOwner,
+        seeds = [b"position_bundle", payer.key().as_ref(), pool.key().as_ref()],
+        bump = position_bundle.load()?.bump,
     )]
-    pub position: Box<Account<'info, Position>>,
+    pub position_bundle: AccountLoader<'info, PositionBundle>,
 
-    #[account(mut, token::mint = token_mint_0)]
-    pub user_token_0: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_mint_1)]
-    pub user_token_1: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_mint_0)]
-    pub pool_token_0: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_mint_1)]
-    pub pool_token_1: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::authority = payer,
+    )]
+    pub user_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::authority = payer,
+    )]
+    pub user_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
 
     #[account(mut)]
     pub payer: Signer<'info>,
 
-    pub token_mint_0: Account<'info, Mint>,
-    pub token_mint_1: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
-pub struct DecreaseLiquidity<'info> {
+#[instruction(bundle_index: u16)]
+pub struct DecreaseBundledLiquidity<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_WITHDRAW) @ ClmmError::WithdrawPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
     #[account(mut)]
     pub payer: Signer<'info>,
-    
+
     #[account(
         mut,
         has_one = token_mint_0,
         has_one = token_mint_1,
+        constraint = pool.flash_loan.is_none() @ ClmmError::FlashLoanInProgress,
     )]
     pub pool: Account<'info, Pool>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"tick_array", pool.key().as_ref(), &lower_tick_array.load()?.starting_tick.to_le_bytes()],
+        bump,
+        constraint = lower_tick_array.load()?.pool == pool.key() @ ClmmError::InvalidTickArray,
+        constraint = lower_tick_array
+            .load()?
+            .contains_tick(position_bundle.load()?.get_position(bundle_index)?.tick_lower, pool.tick_spacing)
+            @ ClmmError::InvalidTickArray,
+    )]
     pub lower_tick_array: AccountLoader<'info, TickArray>,
-    #[account(mut)]
+
+    #[account(
+        mut,
+        seeds = [b"tick_array", pool.key().as_ref(), &upper_tick_array.load()?.starting_tick.to_le_bytes()],
+        bump,
+        constraint = upper_tick_array.load()?.pool == pool.key() @ ClmmError::InvalidTickArray,
+        constraint = upper_tick_array
+            .load()?
+            .contains_tick(position_bundle.load()?.get_position(bundle_index)?.tick_upper, pool.tick_spacing)
+            @ ClmmError::InvalidTickArray,
+    )]
     pub upper_tick_array: AccountLoader<'info, TickArray>,
 
     #[account(
         mut,
+        seeds = [b"position_bundle", payer.key().as_ref(), pool.key().as_ref()],
+        bump = position_bundle.load()?.bump,
+    )]
+    pub position_bundle: AccountLoader<'info, PositionBundle>,
+
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::authority = payer,
+    )]
+    pub user_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::authority = payer,
+    )]
+    pub user_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CollectBundledFees<'info> {
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = !global_config.is_paused(GlobalConfig::PAUSE_WITHDRAW) @ ClmmError::WithdrawPaused,
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub payer: Signer<'info>,
+
+    #[account(
+        has_one = token_mint_0,
+        has_one = token_mint_1,
+        constraint = pool.flash_loan.is_none() @ ClmmError::FlashLoanInProgress,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"position_bundle", payer.key().as_ref(), pool.key().as_ref()],
+        bump = position_bundle.load()?.bump,
+    )]
+    pub position_bundle: AccountLoader<'info, PositionBundle>,
+
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::authority = payer,
+    )]
+    pub user_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::authority = payer,
+    )]
+    pub user_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_0 @ ClmmError::InvalidVault)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = pool.token_vault_1 @ ClmmError::InvalidVault)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mint::token_program = token_program_0)]
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_1)]
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct GetPositionValue<'info> {
+    pub pool: Account<'info, Pool>,
+
+    #[account(
         constraint = position.pool == pool.key() @ ClmmError::InvalidPositionRange,
-        constraint = position.owner == payer.key() @ ClmmError::InvalidPositionOwner,
     )]
     pub position: Box<Account<'info, Position>>,
+}
 
-    #[account(mut, token::mint = token_mint_0)]
-    pub user_token_0: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_mint_1)]
-    pub user_token_1: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_mint_0)]
-    pub pool_token_0: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_mint_1)]
-    pub pool_token_1: Account<'info, TokenAccount>,
+#[derive(Accounts)]
+pub struct QuoteSwap<'info> {
+    #[account(
+        has_one = token_mint_0,
+        has_one = token_mint_1,
+        constraint = pool.status == PoolStatus::Active @ ClmmError::PoolWithdrawOnly,
+    )]
+    pub pool: Account<'info, Pool>,
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+}
 
-    pub token_mint_0: Account<'info, Mint>,
-    pub token_mint_1: Account<'info, Mint>,
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub rent: Sysvar<'info, Rent>,
+#[account]
+#[derive(InitSpace)]
+pub struct GlobalConfig {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub pause_flags: u8,
+    pub bump: u8,
+    pub fee_manager: Pubkey,
+    pub pauser: Pubkey,
+    pub reward_funder: Pubkey,
+    pub pool_creator: Pubkey,
+    pub timelock_delay: i64,
+    pub pending_change: Option<PendingChange>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum ParameterChange {
+    FeeRate(u32),
+    ProtocolFeeRate(u16),
+    TimelockDelay(i64),
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct PendingChange {
+    pub change: ParameterChange,
+    pub execute_after: i64,
+}
+
+impl PendingChange {
+    pub fn new(change: ParameterChange, delay: i64) -> Result<Self> {
+        let execute_after = Clock::get()?
+            .unix_timestamp
+            .checked_add(delay)
+            .ok_or(ClmmError::ArithmeticOverflow)?;
+        Ok(Self { change, execute_after })
+    }
+}
+
+pub const MAX_TIMELOCK_DELAY: i64 = 30 * 24 * 60 * 60;
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PendingChangeQueued {
+    pub target: Pubkey,
+    pub change: ParameterChange,
+    pub execute_after: i64,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PendingChangeExecuted {
+    pub target: Pubkey,
+    pub change: ParameterChange,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PendingChangeCancelled {
+    pub target: Pubkey,
+    pub change: ParameterChange,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolInitialized {
+    pub pool: Pubkey,
+    pub token_mint_0: Pubkey,
+    pub token_mint_1: Pubkey,
+    pub tick_spacing: i32,
+    pub sqrt_price_x96: u128,
+    pub tick: i32,
+    pub fee_rate: u32,
+}
+
+// Bundled positions report the bundle account as the position, with the slot in bundle_index.
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PositionOpened {
+    pub pool: Pubkey,
+    pub position: Pubkey,
+    pub bundle_index: Option<u16>,
+    pub owner: Pubkey,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+    pub amount_0: u64,
+    pub amount_1: u64,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiquidityIncreased {
+    pub pool: Pubkey,
+    pub position: Pubkey,
+    pub bundle_index: Option<u16>,
+    pub liquidity_delta: u128,
+    pub liquidity: u128,
+    pub amount_0: u64,
+    pub amount_1: u64,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiquidityDecreased {
+    pub pool: Pubkey,
+    pub position: Pubkey,
+    pub bundle_index: Option<u16>,
+    pub liquidity_delta: u128,
+    pub liquidity: u128,
+    pub amount_0: u64,
+    pub amount_1: u64,
+}
+
+// Named Swapped rather than Swap, which is already the swap instruction's accounts struct.
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Swapped {
+    pub pool: Pubkey,
+    pub swap_token_0_for_1: bool,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee_amount: u64,
+    pub sqrt_price_before_x96: u128,
+    pub sqrt_price_after_x96: u128,
+    pub tick: i32,
+    pub liquidity: u128,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeesCollected {
+    pub pool: Pubkey,
+    pub position: Pubkey,
+    pub bundle_index: Option<u16>,
+    pub amount_0: u64,
+    pub amount_1: u64,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProtocolFeesCollected {
+    pub pool: Pubkey,
+    pub amount_0: u64,
+    pub amount_1: u64,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RewardsFunded {
+    pub pool: Pubkey,
+    pub funder: Pubkey,
+    pub amount_0: u64,
+    pub amount_1: u64,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlashLoanTaken {
+    pub pool: Pubkey,
+    pub amount_0: u64,
+    pub amount_1: u64,
+    pub fee_0: u64,
+    pub fee_1: u64,
+}
+
+// The repaid amounts include the fee.
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlashLoanRepaid {
+    pub pool: Pubkey,
+    pub amount_0: u64,
+    pub amount_1: u64,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolStatusChanged {
+    pub pool: Pubkey,
+    pub status: PoolStatus,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PauseFlagsChanged {
+    pub pause_flags: u8,
+}
+
+// A revoked role reports the default pubkey as its member.
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RoleChanged {
+    pub role: Role,
+    pub member: Pubkey,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuthorityTransferStarted {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuthorityTransferred {
+    pub previous_authority: Pubkey,
+    pub authority: Pubkey,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PositionBundleInitialized {
+    pub pool: Pubkey,
+    pub position_bundle: Pubkey,
+    pub owner: Pubkey,
+}
+
+#[event]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GlobalConfigInitialized {
+    pub authority: Pubkey,
+    pub timelock_delay: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    FeeManager,
+    Pauser,
+    RewardFunder,
+    PoolCreator,
 }
 
-#[derive(Accounts)]
-pub struct Swap<'info> {
-    #[account(mut)]
-    pub pool: Account<'info, Pool>,
-    #[account(mut)]
-    pub user_token_0: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub user_token_1: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub pool_token_0: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub pool_token_1: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub rent: Sysvar<'info, Rent>,
+impl GlobalConfig {
+    pub const SPACE: usize = 8 + 32 + 32 + 1 + 1 + 32 + 32 + 32 + 32 + 8 + 1 + PendingChange::INIT_SPACE;
+
+    pub const PAUSE_SWAP: u8 = 1 << 0;
+    pub const PAUSE_DEPOSIT: u8 = 1 << 1;
+    pub const PAUSE_WITHDRAW: u8 = 1 << 2;
+    pub const PAUSE_CREATE_POOL: u8 = 1 << 3;
+    pub const PAUSE_ALL: u8 =
+        Self::PAUSE_SWAP | Self::PAUSE_DEPOSIT | Self::PAUSE_WITHDRAW | Self::PAUSE_CREATE_POOL;
+
+    pub fn is_paused(&self, flag: u8) -> bool {
+        self.pause_flags & flag != 0
+    }
+
+    fn role_holder_mut(&mut self, role: Role) -> &mut Pubkey {
+        match role {
+            Role::FeeManager => &mut self.fee_manager,
+            Role::Pauser => &mut self.pauser,
+            Role::RewardFunder => &mut self.reward_funder,
+            Role::PoolCreator => &mut self.pool_creator,
+        }
+    }
+
+    pub fn has_role(&self, role: Role, member: &Pubkey) -> bool {
+        let holder = match role {
+            Role::FeeManager => &self.fee_manager,
+            Role::Pauser => &self.pauser,
+            Role::RewardFunder => &self.reward_funder,
+            Role::PoolCreator => &self.pool_creator,
+        };
+        *holder != Pubkey::default() && holder == member
+    }
 }
 
 #[account]
@@ -603,12 +3318,80 @@ pub struct Pool {
     pub current_tick: i32,
     pub tick_spacing: i32,
     pub bump: u8,
+    pub fee_growth_global_0_x64: u128,
+    pub fee_growth_global_1_x64: u128,
+    pub fee_rate: u32,
+    pub protocol_fee_rate: u16,
+    pub protocol_fees_owed_0: u64,
+    pub protocol_fees_owed_1: u64,
+    pub pending_change: Option<PendingChange>,
+    pub status: PoolStatus,
+    pub flash_loan: Option<OutstandingFlashLoan>,
+}
+
+// Set by flash_loan and cleared by the flash_repay in the same transaction.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct OutstandingFlashLoan {
+    pub amount_0: u64,
+    pub amount_1: u64,
+    pub fee_0: u64,
+    pub fee_1: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum PoolStatus {
+    Active,
+    // Swaps and deposits are rejected; LPs can still withdraw and collect fees.
+    WithdrawOnly,
 }
 
 impl Pool {
-    pub const SPACE: usize = 8 + 32 + 32 + 32 + 32 + 16 + 16 + 4 + 4 + 1;
+    pub const SPACE: usize =
+        8 + 32 + 32 + 32 + 32 + 16 + 16 + 4 + 4 + 1 + 16 + 16 + 4 + 2 + 8 + 8 + 1 + PendingChange::INIT_SPACE + 1
+        + 1 + OutstandingFlashLoan::INIT_SPACE;
+
+    // Spreads an amount over every unit of liquidity in the pool.
+    pub fn credit_fee_growth(&mut self, amount: u64, is_token_0: bool) -> Result<()> {
+        let fee_growth_delta_x64 = get_fee_growth_delta_x64(amount, self.global_liquidity)?;
+        if is_token_0 {
+            self.fee_growth_global_0_x64 = self.fee_growth_global_0_x64.wrapping_add(fee_growth_delta_x64);
+        } else {
+            self.fee_growth_global_1_x64 = self.fee_growth_global_1_x64.wrapping_add(fee_growth_delta_x64);
+        }
+        Ok(())
+    }
+
+    pub fn accrue_swap_fee(&mut self, fee_amount: u64, is_token_0: bool) -> Result<()> {
+        let protocol_fee = (fee_amount as u128)
+            .checked_mul(self.protocol_fee_rate as u128)
+            .ok_or(ClmmError::ArithmeticOverflow)?
+            / PROTOCOL_FEE_RATE_DENOMINATOR as u128;
+        let protocol_fee = protocol_fee as u64;
+
+        if is_token_0 {
+            self.protocol_fees_owed_0 = self
+                .protocol_fees_owed_0
+                .checked_add(protocol_fee)
+                .ok_or(ClmmError::ArithmeticOverflow)?;
+        } else {
+            self.protocol_fees_owed_1 = self
+                .protocol_fees_owed_1
+                .checked_add(protocol_fee)
+                .ok_or(ClmmError::ArithmeticOverflow)?;
+        }
+
+        self.credit_fee_growth(fee_amount - protocol_fee, is_token_0)
+    }
 }
 
+pub const FEE_RATE_DENOMINATOR: u32 = 1_000_000;
+pub const DEFAULT_FEE_RATE: u32 = 1_000;
+pub const MAX_FEE_RATE: u32 = 100_000;
+pub const PROTOCOL_FEE_RATE_DENOMINATOR: u16 = 10_000;
+pub const MAX_PROTOCOL_FEE_RATE: u16 = 2_500;
+// One pool per mint pair and spacing, so the spacings are fixed to keep pools from fragmenting.
+pub const VALID_TICK_SPACINGS: [i32; 4] = [1, 10, 60, 200];
+
 #[account]
 #[derive(InitSpace)]
 pub struct Position {
@@ -618,14 +3401,37 @@ pub struct Position {
     pub owner: Pubkey,
     pub pool: Pubkey,
     pub bump: u8,
+    pub fee_growth_inside_0_last_x64: u128,
+    pub fee_growth_inside_1_last_x64: u128,
+    pub tokens_owed_0: u64,
+    pub tokens_owed_1: u64,
 }
 
 impl Position {
-    pub const SPACE: usize = 8 + 16 + 4 + 4 + 32 + 32 + 1;
+    pub const SPACE: usize = 8 + 16 + 4 + 4 + 32 + 32 + 1 + 16 + 16 + 8 + 8;
+
+    // Swaps draw on the pool's global liquidity, so every position earns the global fee growth.
+    pub fn update_fees(&mut self, fee_growth_global_0_x64: u128, fee_growth_global_1_x64: u128) -> Result<()> {
+        let fees_0 = get_fees_owed(self.liquidity, fee_growth_global_0_x64, self.fee_growth_inside_0_last_x64)?;
+        let fees_1 = get_fees_owed(self.liquidity, fee_growth_global_1_x64, self.fee_growth_inside_1_last_x64)?;
+
+        self.tokens_owed_0 = self
+            .tokens_owed_0
+            .checked_add(fees_0)
+            .ok_or(ClmmError::ArithmeticOverflow)?;
+        self.tokens_owed_1 = self
+            .tokens_owed_1
+            .checked_add(fees_1)
+            .ok_or(ClmmError::ArithmeticOverflow)?;
+        self.fee_growth_inside_0_last_x64 = fee_growth_global_0_x64;
+        self.fee_growth_inside_1_last_x64 = fee_growth_global_1_x64;
+        Ok(())
+    }
 }
 
 #[zero_copy]
 #[repr(C)]
+#[derive(Default)]
 pub struct TickInfo {
     pub liquidity_gross_lower: u64,
     pub liquidity_gross_upper: u64,
@@ -634,18 +3440,6 @@ pub struct TickInfo {
     pub initialized: u64,
 }
 
-impl Default for TickInfo {
-    fn default() -> Self {
-        Self {
-            liquidity_gross_lower: 0,
-            liquidity_gross_upper: 0,
-            liquidity_net_lower: 0,
-            liquidity_net_upper: 0,
-            initialized: 0,
-        }
-    }
-}
-
 impl TickInfo {
     pub fn is_initialized(&self) -> bool {
         self.initialized != 0
@@ -767,6 +3561,12 @@ impl TickArray {
             .expect("Mul overflow")
     }
 
+    pub fn contains_tick(&self, tick: i32, tick_spacing: i32) -> bool {
+        let start = self.starting_tick as i64;
+        let end = start + TICKS_PER_ARRAY as i64 * tick_spacing as i64;
+        (start..end).contains(&(tick as i64))
+    }
+
     pub fn get_tick_info_mutable(&mut self, tick: i32, tick_spacing: i32) -> Result<&mut TickInfo> {
         let ticks_per_array_i32 = TICKS_PER_ARRAY as i32;
         let offset = (tick
@@ -786,6 +3586,619 @@ impl TickArray {
     }
 }
 
+pub const POSITION_BUNDLE_SIZE: usize = 64;
+
+#[zero_copy]
+#[repr(C)]
+#[derive(Default)]
+pub struct BundledPosition {
+    pub liquidity_lower: u64,
+    pub liquidity_upper: u64,
+    pub fee_growth_inside_0_last_lower: u64,
+    pub fee_growth_inside_0_last_upper: u64,
+    pub fee_growth_inside_1_last_lower: u64,
+    pub fee_growth_inside_1_last_upper: u64,
+    pub tokens_owed_0: u64,
+    pub tokens_owed_1: u64,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+}
+
+impl BundledPosition {
+    pub fn get_liquidity(&self) -> u128 {
+        ((self.liquidity_upper as u128) << 64) | (self.liquidity_lower as u128)
+    }
+
+    pub fn set_liquidity(&mut self, value: u128) {
+        self.liquidity_lower = value as u64;
+        self.liquidity_upper = (value >> 64) as u64;
+    }
+
+    pub fn get_fee_growth_inside_0_last_x64(&self) -> u128 {
+        ((self.fee_growth_inside_0_last_upper as u128) << 64) | (self.fee_growth_inside_0_last_lower as u128)
+    }
+
+    pub fn get_fee_growth_inside_1_last_x64(&self) -> u128 {
+        ((self.fee_growth_inside_1_last_upper as u128) << 64) | (self.fee_growth_inside_1_last_lower as u128)
+    }
+
+    fn set_fee_growth_inside_last_x64(&mut self, fee_growth_0_x64: u128, fee_growth_1_x64: u128) {
+        self.fee_growth_inside_0_last_lower = fee_growth_0_x64 as u64;
+        self.fee_growth_inside_0_last_upper = (fee_growth_0_x64 >> 64) as u64;
+        self.fee_growth_inside_1_last_lower = fee_growth_1_x64 as u64;
+        self.fee_growth_inside_1_last_upper = (fee_growth_1_x64 >> 64) as u64;
+    }
+
+    pub fn update_fees(&mut self, fee_growth_global_0_x64: u128, fee_growth_global_1_x64: u128) -> Result<()> {
+        let liquidity = self.get_liquidity();
+        let fees_0 = get_fees_owed(liquidity, fee_growth_global_0_x64, self.get_fee_growth_inside_0_last_x64())?;
+        let fees_1 = get_fees_owed(liquidity, fee_growth_global_1_x64, self.get_fee_growth_inside_1_last_x64())?;
+
+        self.tokens_owed_0 = self
+            .tokens_owed_0
+            .checked_add(fees_0)
+            .ok_or(ClmmError::ArithmeticOverflow)?;
+        self.tokens_owed_1 = self
+            .tokens_owed_1
+            .checked_add(fees_1)
+            .ok_or(ClmmError::ArithmeticOverflow)?;
+        self.set_fee_growth_inside_last_x64(fee_growth_global_0_x64, fee_growth_global_1_x64);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.get_liquidity() == 0 && self.tokens_owed_0 == 0 && self.tokens_owed_1 == 0
+    }
+}
+
+#[account(zero_copy)]
+#[repr(C)]
+pub struct PositionBundle {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub position_bitmap: u64,
+    pub bump: u8,
+    pub _padding: [u8; 7],
+    pub positions: [BundledPosition; POSITION_BUNDLE_SIZE],
+}
+
+impl PositionBundle {
+    fn check_bundle_index(bundle_index: u16) -> Result<usize> {
+        let index = bundle_index as usize;
+        require!(index < POSITION_BUNDLE_SIZE, ClmmError::InvalidBundleIndex);
+        Ok(index)
+    }
+
+    pub fn is_position_open(&self, bundle_index: u16) -> Result<bool> {
+        let index = Self::check_bundle_index(bundle_index)?;
+        Ok(self.position_bitmap & (1u64 << index) != 0)
+    }
+
+    pub fn open_position(&mut self, bundle_index: u16) -> Result<&mut BundledPosition> {
+        require!(
+            !self.is_position_open(bundle_index)?,
+            ClmmError::BundledPositionAlreadyOpen
+        );
+        let index = bundle_index as usize;
+        self.position_bitmap |= 1u64 << index;
+        self.positions[index] = BundledPosition::default();
+        Ok(&mut self.positions[index])
+    }
+
+    pub fn get_position(&self, bundle_index: u16) -> Result<&BundledPosition> {
+        require!(
+            self.is_position_open(bundle_index)?,
+            ClmmError::BundledPositionNotOpen
+        );
+        Ok(&self.positions[bundle_index as usize])
+    }
+
+    pub fn get_position_mutable(&mut self, bundle_index: u16) -> Result<&mut BundledPosition> {
+        require!(
+            self.is_position_open(bundle_index)?,
+            ClmmError::BundledPositionNotOpen
+        );
+        Ok(&mut self.positions[bundle_index as usize])
+    }
+
+    // Frees the slot once the position holds neither liquidity nor uncollected fees.
+    pub fn close_position_if_empty(&mut self, bundle_index: u16) -> Result<()> {
+        let index = Self::check_bundle_index(bundle_index)?;
+        if self.positions[index].is_empty() {
+            self.position_bitmap &= !(1u64 << index);
+            self.positions[index] = BundledPosition::default();
+        }
+        Ok(())
+    }
+}
+
+pub fn load_tick_array_init_if_needed<'a>(
+    tick_array_loader: &'a AccountLoader<TickArray>,
+) -> Result<RefMut<'a, TickArray>> {
+    match tick_array_loader.load_mut() {
+        Ok(tick_array) => Ok(tick_array),
+        Err(_) => tick_array_loader.load_init(),
+    }
+}
+
+// Takes a withdrawn position's liquidity off its two ticks. Both ticks are updated on copies
+// and only written back once both succeed, so an error leaves the tick arrays untouched.
+pub fn clear_position_ticks(
+    pool: &Account<Pool>,
+    lower_tick_array: &AccountLoader<TickArray>,
+    upper_tick_array: &AccountLoader<TickArray>,
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity: u128,
+) -> Result<()> {
+    let liquidity_delta = i128::try_from(liquidity).map_err(|_| ClmmError::ArithmeticOverflow)?;
+
+    let mut lower_array = lower_tick_array.load_mut()?;
+    require!(
+        lower_array.pool == pool.key() && lower_array.contains_tick(tick_lower, pool.tick_spacing),
+        ClmmError::InvalidTickArray
+    );
+    let mut lower_tick = *lower_array.get_tick_info_mutable(tick_lower, pool.tick_spacing)?;
+    lower_tick.update_liquidity_decrease(liquidity_delta, true)?;
+
+    if lower_tick_array.key() == upper_tick_array.key() {
+        require!(
+            lower_array.contains_tick(tick_upper, pool.tick_spacing),
+            ClmmError::InvalidTickArray
+        );
+        let mut upper_tick = *lower_array.get_tick_info_mutable(tick_upper, pool.tick_spacing)?;
+        upper_tick.update_liquidity_decrease(liquidity_delta, false)?;
+
+        *lower_array.get_tick_info_mutable(tick_lower, pool.tick_spacing)? = lower_tick;
+        *lower_array.get_tick_info_mutable(tick_upper, pool.tick_spacing)? = upper_tick;
+    } else {
+        let mut upper_array = upper_tick_array.load_mut()?;
+        require!(
+            upper_array.pool == pool.key() && upper_array.contains_tick(tick_upper, pool.tick_spacing),
+            ClmmError::InvalidTickArray
+        );
+        let mut upper_tick = *upper_array.get_tick_info_mutable(tick_upper, pool.tick_spacing)?;
+        upper_tick.update_liquidity_decrease(liquidity_delta, false)?;
+
+        *lower_array.get_tick_info_mutable(tick_lower, pool.tick_spacing)? = lower_tick;
+        *upper_array.get_tick_info_mutable(tick_upper, pool.tick_spacing)? = upper_tick;
+    }
+
+    Ok(())
+}
+
+// Callers may omit the user token account on the native mint side and pay in plain SOL:
+// a temporary wSOL account funded with wrap_amount lamports stands in for it, and must be
+// closed back to the payer with close_wsol_account once the transfers are done.
+#[allow(clippy::too_many_arguments)]
+pub fn get_or_wrap_user_token_account<'info>(
+    user_token: &Option<InterfaceAccount<'info, TokenAccount>>,
+    wsol_account: &Option<UncheckedAccount<'info>>,
+    wsol_bump: Option<u8>,
+    payer: &Signer<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    token_program: &Interface<'info, TokenInterface>,
+    system_program: &Program<'info, System>,
+    wrap_amount: u64,
+) -> Result<(AccountInfo<'info>, bool)> {
+    if let Some(user_token) = user_token {
+        return Ok((user_token.to_account_info(), false));
+    }
+
+    let (wsol_account, wsol_bump) = match (wsol_account, wsol_bump) {
+        (Some(wsol_account), Some(wsol_bump)) if mint.key() == anchor_spl::token::spl_token::native_mint::ID => {
+            (wsol_account.to_account_info(), wsol_bump)
+        }
+        _ => return err!(ClmmError::MissingUserTokenAccount),
+    };
+
+    let payer_key = payer.key();
+    let seeds = [b"wsol", payer_key.as_ref(), &[wsol_bump]];
+    let signer_seeds = &[&seeds[..]];
+    let space = anchor_spl::token::TokenAccount::LEN;
+    let rent_lamports = Rent::get()?.minimum_balance(space);
+
+    // create_account refuses addresses that already hold lamports, so a pre-funded
+    // address is topped up, allocated and assigned instead.
+    if wsol_account.lamports() == 0 {
+        anchor_lang::system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.to_account_info(),
+                anchor_lang::system_program::CreateAccount {
+                    from: payer.to_account_info(),
+                    to: wsol_account.clone(),
+                },
+                signer_seeds,
+            ),
+            rent_lamports,
+            space as u64,
+            token_program.key,
+        )?;
+    } else {
+        let top_up = rent_lamports.saturating_sub(wsol_account.lamports());
+        if top_up > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: payer.to_account_info(),
+                        to: wsol_account.clone(),
+                    },
+                ),
+                top_up,
+            )?;
+        }
+        anchor_lang::system_program::allocate(
+            CpiContext::new_with_signer(
+                system_program.to_account_info(),
+                anchor_lang::system_program::Allocate {
+                    account_to_allocate: wsol_account.clone(),
+                },
+                signer_seeds,
+            ),
+            space as u64,
+        )?;
+        anchor_lang::system_program::assign(
+            CpiContext::new_with_signer(
+                system_program.to_account_info(),
+                anchor_lang::system_program::Assign {
+                    account_to_assign: wsol_account.clone(),
+                },
+                signer_seeds,
+            ),
+            token_program.key,
+        )?;
+    }
+
+    token_interface::initialize_account3(CpiContext::new(
+        token_program.to_account_info(),
+        InitializeAccount3 {
+            account: wsol_account.clone(),
+            mint: mint.to_account_info(),
+            authority: payer.to_account_info(),
+        },
+    ))?;
+
+    if wrap_amount > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: payer.to_account_info(),
+                    to: wsol_account.clone(),
+                },
+            ),
+            wrap_amount,
+        )?;
+        token_interface::sync_native(CpiContext::new(
+            token_program.to_account_info(),
+            SyncNative {
+                account: wsol_account.clone(),
+            },
+        ))?;
+    }
+
+    Ok((wsol_account, true))
+}
+
+// Returns the wrapped balance and the rent to the payer as plain SOL.
+pub fn close_wsol_account<'info>(
+    payer: &Signer<'info>,
+    wsol_account: AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    token_interface::close_account(CpiContext::new(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: wsol_account,
+            destination: payer.to_account_info(),
+            authority: payer.to_account_info(),
+        },
+    ))
+}
+
+// Transfer-hook mints resolve their extra accounts (the hook program, its validation
+// account and whatever that lists) from hook_accounts, normally the remaining accounts.
+pub fn transfer_from_user<'info>(
+    authority: AccountInfo<'info>,
+    from: &impl ToAccountInfo<'info>,
+    to: &impl ToAccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    token_program: &Interface<'info, TokenInterface>,
+    hook_accounts: &[AccountInfo<'info>],
+    amount: u64,
+) -> Result<()> {
+    invoke_transfer_checked(
+        token_program.key,
+        from.to_account_info(),
+        mint.to_account_info(),
+        to.to_account_info(),
+        authority,
+        hook_accounts,
+        amount,
+        mint.decimals,
+        &[],
+    )
+    .map_err(Into::into)
+}
+
+pub fn transfer_from_pool<'info>(
+    pool: &Account<'info, Pool>,
+    from: &impl ToAccountInfo<'info>,
+    to: &impl ToAccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    token_program: &Interface<'info, TokenInterface>,
+    hook_accounts: &[AccountInfo<'info>],
+    amount: u64,
+) -> Result<()> {
+    let seeds = [
+        b"pool",
+        pool.token_mint_0.as_ref(),
+        pool.token_mint_1.as_ref(),
+        &pool.tick_spacing.to_le_bytes(),
+        &[pool.bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    invoke_transfer_checked(
+        token_program.key,
+        from.to_account_info(),
+        mint.to_account_info(),
+        to.to_account_info(),
+        pool.to_account_info(),
+        hook_accounts,
+        amount,
+        mint.decimals,
+        signer_seeds,
+    )
+    .map_err(Into::into)
+}
+
+pub const MAX_ROUTE_HOPS: usize = 4;
+// pool, token_vault_0, token_vault_1, token_mint_0, token_mint_1, token_program_0, token_program_1
+pub const ROUTE_HOP_ACCOUNTS: usize = 7;
+
+// One pool of a swap_route, checked the same way the Swap accounts are.
+pub struct RouteHop<'info> {
+    pub pool: Account<'info, Pool>,
+    pub token_vault_0: &'info AccountInfo<'info>,
+    pub token_vault_1: &'info AccountInfo<'info>,
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+}
+
+impl<'info> RouteHop<'info> {
+    pub fn load(accounts: &'info [AccountInfo<'info>]) -> Result<Self> {
+        let pool = Account::<Pool>::try_from(&accounts[0])?;
+        require!(pool.status == PoolStatus::Active, ClmmError::PoolWithdrawOnly);
+        require!(pool.flash_loan.is_none(), ClmmError::FlashLoanInProgress);
+        require!(pool.global_liquidity > 0, ClmmError::InsufficientPoolLiquidity);
+        require_keys_eq!(accounts[1].key(), pool.token_vault_0, ClmmError::InvalidVault);
+        require_keys_eq!(accounts[2].key(), pool.token_vault_1, ClmmError::InvalidVault);
+
+        let token_mint_0 = InterfaceAccount::<Mint>::try_from(&accounts[3])?;
+        let token_mint_1 = InterfaceAccount::<Mint>::try_from(&accounts[4])?;
+        require_keys_eq!(token_mint_0.key(), pool.token_mint_0, ClmmError::InvalidTokenPair);
+        require_keys_eq!(token_mint_1.key(), pool.token_mint_1, ClmmError::InvalidTokenPair);
+
+        let token_program_0 = Interface::<TokenInterface>::try_from(&accounts[5])?;
+        let token_program_1 = Interface::<TokenInterface>::try_from(&accounts[6])?;
+        require_keys_eq!(*accounts[3].owner, token_program_0.key(), ClmmError::InvalidTokenPair);
+        require_keys_eq!(*accounts[4].owner, token_program_1.key(), ClmmError::InvalidTokenPair);
+
+        Ok(Self {
+            pool,
+            token_vault_0: &accounts[1],
+            token_vault_1: &accounts[2],
+            token_mint_0,
+            token_mint_1,
+            token_program_0,
+            token_program_1,
+        })
+    }
+
+    // Whether a hop entered with mint_in swaps token 0 for token 1.
+    pub fn direction(&self, mint_in: &Pubkey) -> Result<bool> {
+        if *mint_in == self.pool.token_mint_0 {
+            Ok(true)
+        } else if *mint_in == self.pool.token_mint_1 {
+            Ok(false)
+        } else {
+            err!(ClmmError::InvalidRoute)
+        }
+    }
+
+    pub fn vault(&self, is_token_0: bool) -> &'info AccountInfo<'info> {
+        if is_token_0 { self.token_vault_0 } else { self.token_vault_1 }
+    }
+
+    pub fn mint(&self, is_token_0: bool) -> &InterfaceAccount<'info, Mint> {
+        if is_token_0 { &self.token_mint_0 } else { &self.token_mint_1 }
+    }
+
+    pub fn token_program(&self, is_token_0: bool) -> &Interface<'info, TokenInterface> {
+        if is_token_0 { &self.token_program_0 } else { &self.token_program_1 }
+    }
+}
+
+// flash_loan must be a top-level instruction followed by a flash_repay for the same pool.
+pub fn verify_flash_repay(instructions: &AccountInfo, pool: Pubkey) -> Result<()> {
+    let current_index = load_current_index_checked(instructions)? as usize;
+    let current_instruction = load_instruction_at_checked(current_index, instructions)?;
+    require_keys_eq!(current_instruction.program_id, crate::ID, ClmmError::FlashLoanCpiNotAllowed);
+
+    let mut index = current_index + 1;
+    while let Ok(next_instruction) = load_instruction_at_checked(index, instructions) {
+        if next_instruction.program_id == crate::ID
+            && next_instruction.data.starts_with(crate::instruction::FlashRepay::DISCRIMINATOR)
+            && next_instruction.accounts.first().is_some_and(|account| account.pubkey == pool)
+        {
+            return Ok(());
+        }
+        index += 1;
+    }
+    err!(ClmmError::FlashRepayMissing)
+}
+
+// sha256("global:flash_swap_callback")[..8], so an Anchor program can implement the callback
+// as an ordinary flash_swap_callback(amount_0_owed, amount_1_owed, data) instruction.
+pub const FLASH_SWAP_CALLBACK_DISCRIMINATOR: [u8; 8] = [225, 54, 80, 1, 45, 208, 202, 124];
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FlashSwapCallbackArgs {
+    pub amount_0_owed: u64,
+    pub amount_1_owed: u64,
+    pub data: Vec<u8>,
+}
+
+// Forwards accounts to the callback with the signer and writable flags they arrived with.
+pub fn invoke_flash_swap_callback<'info>(
+    callback_program: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    args: FlashSwapCallbackArgs,
+) -> Result<()> {
+    let mut data = FLASH_SWAP_CALLBACK_DISCRIMINATOR.to_vec();
+    args.serialize(&mut data)?;
+
+    let instruction = Instruction {
+        program_id: callback_program.key(),
+        accounts: accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: account.key(),
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data,
+    };
+    let mut account_infos = accounts.to_vec();
+    account_infos.push(callback_program.clone());
+
+    invoke(&instruction, &account_infos).map_err(Into::into)
+}
+
+// Extensions the pool can account for. Anything else (permanent delegates,
+// frozen default state, confidential transfers...) is rejected.
+pub const SUPPORTED_MINT_EXTENSIONS: [ExtensionType; 10] = [
+    ExtensionType::TransferFeeConfig,
+    ExtensionType::TransferHook,
+    ExtensionType::MintCloseAuthority,
+    ExtensionType::InterestBearingConfig,
+    ExtensionType::MetadataPointer,
+    ExtensionType::TokenMetadata,
+    ExtensionType::GroupPointer,
+    ExtensionType::TokenGroup,
+    ExtensionType::GroupMemberPointer,
+    ExtensionType::TokenGroupMember,
+];
+
+// Legacy SPL mints unpack with no extensions, so they always pass.
+pub fn validate_mint_extensions(mint: &InterfaceAccount<Mint>) -> Result<()> {
+    let mint_info = mint.to_account_info();
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<MintState>::unpack(&mint_data)?;
+
+    for extension_type in mint_state.get_extension_types()? {
+        require!(
+            SUPPORTED_MINT_EXTENSIONS.contains(&extension_type),
+            ClmmError::UnsupportedMintExtension
+        );
+    }
+    Ok(())
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransferFeeQuote {
+    pub gross_amount: u64,
+    pub net_amount: u64,
+    pub transfer_fee: u64,
+}
+
+// The transfer fee in effect this epoch, or None for mints without the extension.
+pub fn get_epoch_transfer_fee(mint: &InterfaceAccount<Mint>) -> Result<Option<TransferFee>> {
+    let mint_info = mint.to_account_info();
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<MintState>::unpack(&mint_data)?;
+
+    match mint_state.get_extension::<TransferFeeConfig>() {
+        Ok(transfer_fee_config) => Ok(Some(*transfer_fee_config.get_epoch_fee(Clock::get()?.epoch))),
+        Err(_) => Ok(None),
+    }
+}
+
+// What arrives when gross_amount is sent.
+pub fn quote_transfer_from_gross(mint: &InterfaceAccount<Mint>, gross_amount: u64) -> Result<TransferFeeQuote> {
+    let transfer_fee = match get_epoch_transfer_fee(mint)? {
+        Some(fee) => fee.calculate_fee(gross_amount).ok_or(ClmmError::ArithmeticOverflow)?,
+        None => 0,
+    };
+    Ok(TransferFeeQuote {
+        gross_amount,
+        net_amount: gross_amount - transfer_fee,
+        transfer_fee,
+    })
+}
+
+// What must be sent for net_amount to arrive.
+pub fn quote_transfer_for_net(mint: &InterfaceAccount<Mint>, net_amount: u64) -> Result<TransferFeeQuote> {
+    let gross_amount = match get_epoch_transfer_fee(mint)? {
+        Some(fee) => fee.calculate_pre_fee_amount(net_amount).ok_or(ClmmError::ArithmeticOverflow)?,
+        None => net_amount,
+    };
+    Ok(TransferFeeQuote {
+        gross_amount,
+        net_amount,
+        transfer_fee: gross_amount - net_amount,
+    })
+}
+
+// Grosses a deposit up so the vaults receive the full amounts after any transfer fee, and
+// checks what the user would pay against their maximums before any tokens move.
+pub fn quote_deposit(
+    token_mint_0: &InterfaceAccount<Mint>,
+    token_mint_1: &InterfaceAccount<Mint>,
+    amount_0: u64,
+    amount_1: u64,
+    amount_0_max: u64,
+    amount_1_max: u64,
+) -> Result<(TransferFeeQuote, TransferFeeQuote)> {
+    let transfer_0 = quote_transfer_for_net(token_mint_0, amount_0)?;
+    let transfer_1 = quote_transfer_for_net(token_mint_1, amount_1)?;
+    require!(
+        transfer_0.gross_amount <= amount_0_max && transfer_1.gross_amount <= amount_1_max,
+        ClmmError::SlippageExceeded
+    );
+    Ok((transfer_0, transfer_1))
+}
+
+// Orders an unordered mint pair the way initialize_pool requires (token_mint_0 < token_mint_1).
+// The returned flag is true when mint_a ends up as token_mint_1, i.e. prices quoted in
+// terms of mint_a must be inverted relative to the pool's token_1/token_0 price.
+pub fn sort_token_mints(mint_a: Pubkey, mint_b: Pubkey) -> (Pubkey, Pubkey, bool) {
+    if mint_a < mint_b {
+        (mint_a, mint_b, false)
+    } else {
+        (mint_b, mint_a, true)
+    }
+}
+
+pub fn find_pool_address(mint_a: Pubkey, mint_b: Pubkey, tick_spacing: i32) -> (Pubkey, bool) {
+    let (token_mint_0, token_mint_1, is_inverted) = sort_token_mints(mint_a, mint_b);
+    let (pool, _) = Pubkey::find_program_address(
+        &[
+            b"pool",
+            token_mint_0.as_ref(),
+            token_mint_1.as_ref(),
+            &tick_spacing.to_le_bytes(),
+        ],
+        &crate::ID,
+    );
+    (pool, is_inverted)
+}
+
 // Simplified tick math
 pub fn get_sqrt_price_from_tick(tick: i32) -> Result<u128> {
     let base_sqrt_price = 1u128 << 96;
@@ -834,20 +4247,71 @@ pub fn get_amounts_for_liquidity(
     Ok((amount0, amount1))
 }
 
+pub fn get_fees_owed(liquidity: u128, fee_growth_x64: u128, fee_growth_last_x64: u128) -> Result<u64> {
+    let fee_growth_delta_x64 = fee_growth_x64.wrapping_sub(fee_growth_last_x64);
+    let fees = liquidity
+        .checked_mul(fee_growth_delta_x64)
+        .ok_or(ClmmError::ArithmeticOverflow)?
+        >> 64;
+    u64::try_from(fees).map_err(|_| ClmmError::ArithmeticOverflow.into())
+}
+
+// A position's share of what the vault holds beyond the protocol's fees.
+pub fn get_emergency_withdraw_amount(
+    vault_amount: u64,
+    protocol_fees_owed: u64,
+    liquidity: u128,
+    global_liquidity: u128,
+) -> Result<u64> {
+    let available = vault_amount.saturating_sub(protocol_fees_owed) as u128;
+    let amount = available
+        .checked_mul(liquidity)
+        .ok_or(ClmmError::ArithmeticOverflow)?
+        .checked_div(global_liquidity)
+        .ok_or(ClmmError::InsufficientPoolLiquidity)?;
+    Ok(amount as u64)
+}
+
+pub fn get_fee_growth_delta_x64(fee_amount: u64, liquidity: u128) -> Result<u128> {
+    let fee_growth_delta_x64 = ((fee_amount as u128) << 64)
+        .checked_div(liquidity)
+        .ok_or(ClmmError::InsufficientLiquidity)?;
+    Ok(fee_growth_delta_x64)
+}
+
+pub fn get_fee_amount(amount: u64, fee_rate: u32) -> Result<u64> {
+    let fee_amount = (amount as u128)
+        .checked_mul(fee_rate as u128)
+        .ok_or(ClmmError::ArithmeticOverflow)?
+        / FEE_RATE_DENOMINATOR as u128;
+    Ok(fee_amount as u64)
+}
+
+// Rounds up, unlike the swap fee, so splitting a loan into small pieces can't make it free.
+pub fn get_flash_loan_fee(amount: u64, fee_rate: u32) -> Result<u64> {
+    let fee_amount = (amount as u128)
+        .checked_mul(fee_rate as u128)
+        .ok_or(ClmmError::ArithmeticOverflow)?
+        .div_ceil(FEE_RATE_DENOMINATOR as u128);
+    u64::try_from(fee_amount).map_err(|_| ClmmError::ArithmeticOverflow.into())
+}
+
 pub fn swap_segment(
     current_sqrt_price_x96: u128,
     global_liquidity: u128,
     amount_remaining_in: u64,
+    fee_rate: u32,
     swap_token_0_for_1: bool,
-) -> Result<(u64, u64, u128)> {
+) -> Result<(u64, u64, u64, u128)> {
     if global_liquidity == 0 {
         return Err(ClmmError::InsufficientLiquidity.into());
     }
 
     let amount_in_used = amount_remaining_in;
-    // Simplified calculation with 0.1% fee
+    // Simplified calculation: output is the input less the pool's fee
+    let fee_amount = get_fee_amount(amount_in_used, fee_rate)?;
     let amount_out_calculated = amount_in_used
-        .checked_sub(amount_in_used / 1000)
+        .checked_sub(fee_amount)
         .ok_or(ClmmError::ArithmeticOverflow)?;
 
     let new_sqrt_price = if swap_token_0_for_1 {
@@ -859,7 +4323,108 @@ pub fn swap_segment(
             .checked_add(1_000_000_000)
             .ok_or(ClmmError::ArithmeticOverflow)?
     };
-    Ok((amount_in_used, amount_out_calculated, new_sqrt_price))
+    Ok((amount_in_used, amount_out_calculated, fee_amount, new_sqrt_price))
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExpectedSqrtPrice {
+    pub sqrt_price_x96: u128,
+    // Largest accepted distance from sqrt_price_x96, in the same X96 units.
+    pub tolerance_x96: u128,
+}
+
+// Rejects instructions that land after their deadline or against a pool price that has
+// moved away from what the user signed for.
+pub fn check_execution_guards(
+    pool: &Pool,
+    deadline: Option<i64>,
+    expected_sqrt_price: Option<ExpectedSqrtPrice>,
+) -> Result<()> {
+    if let Some(deadline) = deadline {
+        require!(Clock::get()?.unix_timestamp <= deadline, ClmmError::DeadlineExceeded);
+    }
+    if let Some(expected) = expected_sqrt_price {
+        require!(
+            pool.sqrt_price_x96.abs_diff(expected.sqrt_price_x96) <= expected.tolerance_x96,
+            ClmmError::SqrtPriceOutOfTolerance
+        );
+    }
+    Ok(())
+}
+
+// Prices a swap on what reaches the vault after the input mint's transfer fee.
+pub fn compute_swap(pool: &Pool, mint_in: &InterfaceAccount<Mint>, amount_in: u64, swap_token_0_for_1: bool) -> Result<SwapQuote> {
+    require!(pool.status == PoolStatus::Active, ClmmError::PoolWithdrawOnly);
+    require!(pool.global_liquidity > 0, ClmmError::InsufficientPoolLiquidity);
+    require!(amount_in > 0, ClmmError::InsufficientInputAmount);
+
+    let net_amount_in = quote_transfer_from_gross(mint_in, amount_in)?.net_amount;
+    require!(net_amount_in > 0, ClmmError::InsufficientInputAmount);
+
+    let (amount_in_used, amount_out, fee_amount, sqrt_price_x96) =
+        swap_segment(pool.sqrt_price_x96, pool.global_liquidity, net_amount_in, pool.fee_rate, swap_token_0_for_1)?;
+    let tick = get_tick_at_sqrt_price(sqrt_price_x96)?;
+
+    // Grossing the net input back up picks the smallest amount with the same net, which can be
+    // below amount_in (e.g. at the fee cap), so a fully used input is charged as sent.
+    let gross_amount_in = if amount_in_used == net_amount_in {
+        amount_in
+    } else {
+        quote_transfer_for_net(mint_in, amount_in_used)?.gross_amount
+    };
+
+    Ok(SwapQuote {
+        amount_in: gross_amount_in,
+        amount_out,
+        fee_amount,
+        sqrt_price_x96,
+        tick,
+        ticks_crossed: 0,
+        price_impact: get_price_impact(pool.sqrt_price_x96, sqrt_price_x96)?,
+    })
+}
+
+// |p1 - p0| / p0 with p = sqrt_price^2, scaled by FEE_RATE_DENOMINATOR.
+// Moving the current tick from from_tick to to_tick crosses the ticks in
+// (min(from_tick, to_tick), max(from_tick, to_tick)]. These are the start indexes of the tick
+// arrays holding them, ascending, rounded toward negative infinity like the clients' indexes.
+pub fn get_crossed_tick_array_start_indexes(tick_spacing: i32, from_tick: i32, to_tick: i32) -> Vec<i32> {
+    if from_tick == to_tick {
+        return Vec::new();
+    }
+    let ticks_per_array = TICKS_PER_ARRAY as i64 * tick_spacing as i64;
+    let lowest = from_tick.min(to_tick) as i64 + 1;
+    let highest = from_tick.max(to_tick) as i64;
+    (lowest.div_euclid(ticks_per_array)..=highest.div_euclid(ticks_per_array))
+        .map(|array_index| (array_index * ticks_per_array) as i32)
+        .collect()
+}
+
+pub fn count_initialized_ticks_crossed(tick_arrays: &[TickArray], tick_spacing: i32, from_tick: i32, to_tick: i32) -> u32 {
+    let crossed = (from_tick.min(to_tick) as i64 + 1)..=(from_tick.max(to_tick) as i64);
+    tick_arrays
+        .iter()
+        .flat_map(|tick_array| {
+            tick_array.ticks.iter().enumerate().map(move |(offset, tick)| {
+                (tick_array.starting_tick as i64 + offset as i64 * tick_spacing as i64, tick)
+            })
+        })
+        .filter(|(tick_index, tick)| crossed.contains(tick_index) && tick.is_initialized())
+        .count() as u32
+}
+
+pub fn get_price_impact(sqrt_price_before_x96: u128, sqrt_price_after_x96: u128) -> Result<u32> {
+    let sqrt_price_delta = sqrt_price_before_x96.abs_diff(sqrt_price_after_x96);
+    let sqrt_price_sum_scaled = sqrt_price_before_x96
+        .checked_add(sqrt_price_after_x96)
+        .and_then(|sum| sum.checked_mul(FEE_RATE_DENOMINATOR as u128))
+        .and_then(|sum| sum.checked_div(sqrt_price_before_x96))
+        .ok_or(ClmmError::ArithmeticOverflow)?;
+    let price_impact = sqrt_price_delta
+        .checked_mul(sqrt_price_sum_scaled)
+        .and_then(|product| product.checked_div(sqrt_price_before_x96))
+        .ok_or(ClmmError::ArithmeticOverflow)?;
+    Ok(price_impact.min(u32::MAX as u128) as u32)
 }
 
 #[error_code]
@@ -894,4 +4459,70 @@ pub enum ClmmError {
     TickNotFound,
     #[msg("Invalid Tick Array Index")]
     InvalidTickArrayIndex,
+    #[msg("Invalid Bundle Index")]
+    InvalidBundleIndex,
+    #[msg("Bundled Position Already Open")]
+    BundledPositionAlreadyOpen,
+    #[msg("Bundled Position Not Open")]
+    BundledPositionNotOpen,
+    #[msg("Unauthorized")]
+    Unauthorized,
+    #[msg("Invalid Pause Flags")]
+    InvalidPauseFlags,
+    #[msg("Swap Paused")]
+    SwapPaused,
+    #[msg("Deposit Paused")]
+    DepositPaused,
+    #[msg("Withdraw Paused")]
+    WithdrawPaused,
+    #[msg("Create Pool Paused")]
+    CreatePoolPaused,
+    #[msg("Token Mints Not Ordered")]
+    TokenMintsNotOrdered,
+    #[msg("Invalid Role Member")]
+    InvalidRoleMember,
+    #[msg("Invalid Fee Rate")]
+    InvalidFeeRate,
+    #[msg("Invalid Timelock Delay")]
+    InvalidTimelockDelay,
+    #[msg("Pending Change Exists")]
+    PendingChangeExists,
+    #[msg("No Pending Change")]
+    NoPendingChange,
+    #[msg("Pending Change Not Ready")]
+    PendingChangeNotReady,
+    #[msg("Invalid Pending Change")]
+    InvalidPendingChange,
+    #[msg("Pool Withdraw Only")]
+    PoolWithdrawOnly,
+    #[msg("Pool Not Withdraw Only")]
+    PoolNotWithdrawOnly,
+    #[msg("Unsupported Mint Extension")]
+    UnsupportedMintExtension,
+    #[msg("Missing User Token Account")]
+    MissingUserTokenAccount,
+    #[msg("Invalid Vault")]
+    InvalidVault,
+    #[msg("Invalid Tick Array")]
+    InvalidTickArray,
+    #[msg("Invalid Swap Route")]
+    InvalidRoute,
+    #[msg("Flash Loan Already In Progress")]
+    FlashLoanInProgress,
+    #[msg("Flash Loan Must Be Followed By A Matching Flash Repay")]
+    FlashRepayMissing,
+    #[msg("No Outstanding Flash Loan")]
+    NoOutstandingFlashLoan,
+    #[msg("Flash Loan Cannot Be Called Through CPI")]
+    FlashLoanCpiNotAllowed,
+    #[msg("Flash Swap Callback Did Not Pay The Required Input")]
+    FlashSwapUnderpaid,
+    #[msg("Invalid Flash Swap Callback Program")]
+    InvalidCallbackProgram,
+    #[msg("Transaction Deadline Exceeded")]
+    DeadlineExceeded,
+    #[msg("Pool Price Moved Beyond Tolerance")]
+    SqrtPriceOutOfTolerance,
+    #[msg("Tick Arrays Do Not Cover The Quoted Swap")]
+    MissingTickArray,
 }
\ No newline at end of file
@@ -0,0 +1,75 @@
+#![allow(clippy::result_large_err)]
+#![allow(unexpected_cfgs)]
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+
+declare_id!("9nYHQGHQQ62oSo73ErCc2DFyaEgsRQ3ZM49hCdkNMV9o");
+
+// Counterparty for the clmm flash swap tests. It pays the pool what it is owed from the
+// payer's token accounts, less an optional shortfall passed as a little-endian u64 in data.
+#[program]
+pub mod flash_swap_receiver {
+    use super::*;
+
+    pub fn flash_swap_callback(
+        ctx: Context<FlashSwapCallback>,
+        amount_0_owed: u64,
+        amount_1_owed: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let shortfall = data
+            .get(..8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .unwrap_or(0);
+
+        let accounts = &ctx.accounts;
+        let (amount_owed, from, to, mint, token_program) = if amount_0_owed > 0 {
+            (
+                amount_0_owed,
+                &accounts.payer_token_0,
+                &accounts.pool_token_0,
+                &accounts.token_mint_0,
+                &accounts.token_program_0,
+            )
+        } else {
+            (
+                amount_1_owed,
+                &accounts.payer_token_1,
+                &accounts.pool_token_1,
+                &accounts.token_mint_1,
+                &accounts.token_program_1,
+            )
+        };
+
+        token_interface::transfer_checked(
+            CpiContext::new(
+                token_program.to_account_info(),
+                TransferChecked {
+                    from: from.to_account_info(),
+                    mint: mint.to_account_info(),
+                    to: to.to_account_info(),
+                    authority: accounts.payer.to_account_info(),
+                },
+            ),
+            amount_owed.saturating_sub(shortfall),
+            mint.decimals,
+        )
+    }
+}
+
+#[derive(Accounts)]
+pub struct FlashSwapCallback<'info> {
+    pub payer: Signer<'info>,
+    #[account(mut)]
+    pub payer_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub payer_token_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub pool_token_0: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub pool_token_1: InterfaceAccount<'info, TokenAccount>,
+    pub token_mint_0: InterfaceAccount<'info, Mint>,
+    pub token_mint_1: InterfaceAccount<'info, Mint>,
+    pub token_program_0: Interface<'info, TokenInterface>,
+    pub token_program_1: Interface<'info, TokenInterface>,
+}
@@ -0,0 +1,92 @@
+#![allow(clippy::result_large_err)]
+#![allow(unexpected_cfgs)]
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+use spl_discriminator::SplDiscriminate;
+use spl_tlv_account_resolution::{account::ExtraAccountMeta, seeds::Seed, state::ExtraAccountMetaList};
+use spl_transfer_hook_interface::instruction::ExecuteInstruction;
+
+declare_id!("89upz2dgJcr2WrphqjYc12bZCAAgNo5Eo46yMokTchUA");
+
+// Transfer hook for the clmm tests. Every transfer of a mint pointing here bumps a per-mint
+// counter, so a test can tell that the pool forwarded the hook's extra accounts.
+#[program]
+pub mod transfer_hook_counter {
+    use super::*;
+
+    pub fn initialize_extra_account_meta_list(ctx: Context<InitializeExtraAccountMetaList>) -> Result<()> {
+        // The counter is derived from the mint, the second account of every Execute.
+        let extra_account_metas = [ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: b"counter".to_vec(),
+                },
+                Seed::AccountKey { index: 1 },
+            ],
+            false,
+            true,
+        )?];
+        ExtraAccountMetaList::init::<ExecuteInstruction>(
+            &mut ctx.accounts.extra_account_meta_list.try_borrow_mut_data()?,
+            &extra_account_metas,
+        )?;
+        Ok(())
+    }
+
+    #[instruction(discriminator = ExecuteInstruction::SPL_DISCRIMINATOR_SLICE)]
+    pub fn transfer_hook(ctx: Context<TransferHook>, amount: u64) -> Result<()> {
+        let counter = &mut ctx.accounts.counter;
+        counter.transfers = counter.transfers.checked_add(1).unwrap();
+        counter.amount = counter.amount.checked_add(amount).unwrap();
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializeExtraAccountMetaList<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: Written as a TLV ExtraAccountMetaList, which Anchor can't deserialize.
+    #[account(
+        init,
+        payer = payer,
+        space = ExtraAccountMetaList::size_of(1)?,
+        seeds = [b"extra-account-metas", mint.key().as_ref()],
+        bump,
+    )]
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + TransferCounter::INIT_SPACE,
+        seeds = [b"counter", mint.key().as_ref()],
+        bump,
+    )]
+    pub counter: Account<'info, TransferCounter>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+// Accounts in the order the transfer hook interface passes them to Execute.
+#[derive(Accounts)]
+pub struct TransferHook<'info> {
+    /// CHECK: Source token account; the token program has already validated the transfer.
+    pub source_token: UncheckedAccount<'info>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: Destination token account.
+    pub destination_token: UncheckedAccount<'info>,
+    /// CHECK: Source owner or delegate, which may be a PDA such as a clmm pool.
+    pub owner: UncheckedAccount<'info>,
+    /// CHECK: Checked by its seeds.
+    #[account(seeds = [b"extra-account-metas", mint.key().as_ref()], bump)]
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"counter", mint.key().as_ref()], bump)]
+    pub counter: Account<'info, TransferCounter>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct TransferCounter {
+    pub transfers: u64,
+    pub amount: u64,
+}
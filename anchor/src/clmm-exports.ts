@@ -27,3 +27,41 @@ export function getClmmProgramId(cluster: Cluster) {
       return CLMM_PROGRAM_ID
   }
 }
+
+// Orders an unordered mint pair the way `initialize_pool` requires (tokenMint0 < tokenMint1 by key bytes).
+// `isInverted` is true when `mintA` becomes tokenMint1, so prices quoted in terms of `mintA` must be inverted.
+export function sortTokenMints(mintA: PublicKey, mintB: PublicKey) {
+  const isInverted = Buffer.compare(mintA.toBuffer(), mintB.toBuffer()) > 0
+  return {
+    tokenMint0: isInverted ? mintB : mintA,
+    tokenMint1: isInverted ? mintA : mintB,
+    isInverted,
+  }
+}
+
+// Derives the canonical pool PDA for an unordered mint pair.
+export function findPoolAddress(mintA: PublicKey, mintB: PublicKey, tickSpacing: number, programId: PublicKey = CLMM_PROGRAM_ID) {
+  const { tokenMint0, tokenMint1, isInverted } = sortTokenMints(mintA, mintB)
+  const tickSpacingBytes = Buffer.alloc(4)
+  tickSpacingBytes.writeInt32LE(tickSpacing, 0)
+  const [pool] = PublicKey.findProgramAddressSync(
+    [Buffer.from('pool'), tokenMint0.toBuffer(), tokenMint1.toBuffer(), tickSpacingBytes],
+    programId,
+  )
+  return { pool, tokenMint0, tokenMint1, isInverted }
+}
+
+// Derives the PDA token vault that `initialize_pool` creates for one side of a pool.
+export function findTokenVaultAddress(pool: PublicKey, mint: PublicKey, programId: PublicKey = CLMM_PROGRAM_ID) {
+  const [vault] = PublicKey.findProgramAddressSync(
+    [Buffer.from('token_vault'), pool.toBuffer(), mint.toBuffer()],
+    programId,
+  )
+  return vault
+}
+
+// Signs the self-CPI the program emits its events through
+export function findEventAuthorityAddress(programId: PublicKey = CLMM_PROGRAM_ID) {
+  const [eventAuthority] = PublicKey.findProgramAddressSync([Buffer.from('__event_authority')], programId)
+  return eventAuthority
+}
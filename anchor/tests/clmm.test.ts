@@ -1,9 +1,37 @@
 import * as anchor from '@coral-xyz/anchor'
 import { Program } from '@coral-xyz/anchor'
-import { Keypair, PublicKey, SystemProgram, SYSVAR_RENT_PUBKEY } from '@solana/web3.js'
+import {
+  Keypair,
+  PublicKey,
+  sendAndConfirmTransaction,
+  SystemProgram,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+  SYSVAR_RENT_PUBKEY,
+  Transaction,
+  TransactionInstruction,
+} from '@solana/web3.js'
 import { Clmm } from '../target/types/clmm'
+import { TransferHookCounter } from '../target/types/transfer_hook_counter'
 import { BN } from 'bn.js'
-import { createAssociatedTokenAccount, createMint, getAccount, mintTo, TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import {
+  createAssociatedTokenAccount,
+  createInitializeMintInstruction,
+  createInitializePermanentDelegateInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  createInitializeTransferHookInstruction,
+  createMint,
+  ExtensionType,
+  getAccount,
+  getMintLen,
+  mintTo,
+  NATIVE_MINT,
+  TOKEN_2022_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+} from "@solana/spl-token";
+
+const BPF_LOADER_UPGRADEABLE_PROGRAM_ID = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+// Little-endian anchor_lang::event::EVENT_IX_TAG, the prefix of every self-CPI event.
+const EVENT_IX_TAG = Buffer.from("e445a52e51cb9a1d", "hex");
 
 describe('Clmm', () => {
   const provider = anchor.AnchorProvider.env()
@@ -18,17 +46,28 @@ describe('Clmm', () => {
   const LOWER_TICK = 0;
   const UPPER_TICK = 1800;
   const LIQUIDITY_AMOUNT = new BN(100000);
+  const TIMELOCK_DELAY = 2; // seconds
+  const U64_MAX = new BN("18446744073709551615");
 
   let tokenMint0: PublicKey;
   let tokenMint1: PublicKey;
   let poolPDA: PublicKey;
-  let tokenVault0Keypair: Keypair;
-  let tokenVault1Keypair: Keypair;
+  let tokenVault0: PublicKey;
+  let tokenVault1: PublicKey;
   let userTokenAccount0: PublicKey;
   let userTokenAccount1: PublicKey;
   let positionPda: PublicKey;
   let lowerTickArrayPda: PublicKey;
   let upperTickArrayPda: PublicKey;
+  let positionBundlePda: PublicKey;
+  let globalConfigPda: PublicKey;
+  let programDataPda: PublicKey;
+
+  const [eventAuthorityPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("__event_authority")],
+    program.programId
+  );
+  const eventCpiAccounts = { eventAuthority: eventAuthorityPda, program: program.programId };
 
   function i32ToLeBytes(value: number): Buffer {
     const buffer = Buffer.allocUnsafe(4);
@@ -42,7 +81,273 @@ describe('Clmm', () => {
     return arrayIdx * ticksPerArrayI32 * tickSpacing;
   }
 
+  async function fetchTickLiquidityGross(tickArray: PublicKey, tick: number): Promise<BN> {
+    const { startingTick, ticks } = await program.account.tickArray.fetch(tickArray);
+    const info = ticks[Math.trunc(tick / TICK_SPACING) - Math.trunc(startingTick / TICK_SPACING)];
+    return info.liquidityGrossUpper.shln(64).add(info.liquidityGrossLower);
+  }
+
+  // The toy swap math moves the sqrt price by 1e9 per swap
+  function getSwapEndTick(sqrtPriceX96: BN, swapToken0For1: boolean): number {
+    const priceMove = new BN(1_000_000_000);
+    const sqrtPriceAfter = swapToken0For1 ? sqrtPriceX96.sub(priceMove) : sqrtPriceX96.add(priceMove);
+    return sqrtPriceAfter.sub(INITIAL_SQRT_PRICE).div(new BN(1_000_000)).toNumber();
+  }
+
+  // quoteSwap counts the initialized ticks crossed from every tick array between the
+  // current and the end tick, passed in ascending order
+  async function getCrossedTickArrays(pool: PublicKey, swapToken0For1: boolean) {
+    const { sqrtPriceX96, currentTick } = await program.account.pool.fetch(pool);
+    const endTick = getSwapEndTick(sqrtPriceX96, swapToken0For1);
+    if (endTick === currentTick) {
+      return [];
+    }
+    const ticksPerArray = TICKS_PER_ARRAY * TICK_SPACING;
+    const first = Math.floor((Math.min(currentTick, endTick) + 1) / ticksPerArray);
+    const last = Math.floor(Math.max(currentTick, endTick) / ticksPerArray);
+    const tickArrays = [];
+    for (let arrayIndex = first; arrayIndex <= last; arrayIndex++) {
+      const [tickArray] = PublicKey.findProgramAddressSync(
+        [Buffer.from("tick_array"), pool.toBuffer(), i32ToLeBytes(arrayIndex * ticksPerArray)],
+        program.programId
+      );
+      tickArrays.push({ pubkey: tickArray, isSigner: false, isWritable: false });
+    }
+    return tickArrays;
+  }
+
+  function sortMintsWithPrograms(
+    a: [PublicKey, PublicKey],
+    b: [PublicKey, PublicKey]
+  ): [[PublicKey, PublicKey], [PublicKey, PublicKey]] {
+    return Buffer.compare(a[0].toBuffer(), b[0].toBuffer()) < 0 ? [a, b] : [b, a];
+  }
+
+  async function createToken2022Mint(
+    extensions: ExtensionType[],
+    extensionInstructions: (mint: PublicKey) => TransactionInstruction[]
+  ): Promise<PublicKey> {
+    const mintKeypair = Keypair.generate();
+    const mintLen = getMintLen(extensions);
+    const lamports = await provider.connection.getMinimumBalanceForRentExemption(mintLen);
+
+    await sendAndConfirmTransaction(
+      provider.connection,
+      new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: payer.publicKey,
+          newAccountPubkey: mintKeypair.publicKey,
+          space: mintLen,
+          lamports,
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        ...extensionInstructions(mintKeypair.publicKey),
+        createInitializeMintInstruction(
+          mintKeypair.publicKey,
+          6,
+          payer.publicKey,
+          null,
+          TOKEN_2022_PROGRAM_ID
+        )
+      ),
+      [payer.payer, mintKeypair]
+    );
+
+    return mintKeypair.publicKey;
+  }
+
+  function deriveTokenVault(pool: PublicKey, mint: PublicKey): PublicKey {
+    const [vault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("token_vault"), pool.toBuffer(), mint.toBuffer()],
+      program.programId
+    );
+    return vault;
+  }
+
+  async function initializePoolFor(
+    [mint0, tokenProgram0]: [PublicKey, PublicKey],
+    [mint1, tokenProgram1]: [PublicKey, PublicKey]
+  ) {
+    const [pool] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), mint0.toBuffer(), mint1.toBuffer(), i32ToLeBytes(TICK_SPACING)],
+      program.programId
+    );
+    const vault0 = deriveTokenVault(pool, mint0);
+    const vault1 = deriveTokenVault(pool, mint1);
+
+    await program.methods
+      .initializePool(TICK_SPACING, INITIAL_SQRT_PRICE)
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        payer: payer.publicKey,
+        pool,
+        tokenMint0: mint0,
+        tokenMint1: mint1,
+        tokenVault0: vault0,
+        tokenVault1: vault1,
+        systemProgram: SystemProgram.programId,
+        tokenProgram0,
+        tokenProgram1,
+        rent: SYSVAR_RENT_PUBKEY,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    return { pool, vault0, vault1 };
+  }
+
+  function derivePositionAccounts(pool: PublicKey) {
+    const lowerTickArrayStartIndex = getTickArrayStartIndex(LOWER_TICK, TICK_SPACING);
+    const upperTickArrayStartIndex = getTickArrayStartIndex(UPPER_TICK, TICK_SPACING);
+    const [lowerTickArray] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tick_array"), pool.toBuffer(), i32ToLeBytes(lowerTickArrayStartIndex)],
+      program.programId
+    );
+    const [upperTickArray] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tick_array"), pool.toBuffer(), i32ToLeBytes(upperTickArrayStartIndex)],
+      program.programId
+    );
+    const [position] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("position"),
+        payer.publicKey.toBuffer(),
+        pool.toBuffer(),
+        i32ToLeBytes(LOWER_TICK),
+        i32ToLeBytes(UPPER_TICK),
+      ],
+      program.programId
+    );
+    return { lowerTickArrayStartIndex, upperTickArrayStartIndex, lowerTickArray, upperTickArray, position };
+  }
+
+  async function expectAnchorError(request: Promise<unknown>, code: string) {
+    try {
+      await request;
+    } catch (error) {
+      expect(error.error?.errorCode?.code).toEqual(code);
+      return;
+    }
+    throw new Error(`expected ${code}`);
+  }
+
+  // Events are emitted through self-CPI, so they are read back from the program's
+  // inner instructions rather than the logs.
+  async function getEvents(signature: string) {
+    const transaction = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const accountKeys = transaction.transaction.message.getAccountKeys();
+    return (transaction.meta?.innerInstructions ?? [])
+      .flatMap((inner) => inner.instructions)
+      .filter((ix) => accountKeys.get(ix.programIdIndex).equals(program.programId))
+      .map((ix) => Buffer.from(anchor.utils.bytes.bs58.decode(ix.data)))
+      .filter((data) => data.subarray(0, 8).equals(EVENT_IX_TAG))
+      .map((data) => program.coder.events.decode(data.subarray(8).toString("base64")));
+  }
+
+  async function createForeignTokenAccount(mint: PublicKey): Promise<PublicKey> {
+    return createAssociatedTokenAccount(provider.connection, payer.payer, mint, Keypair.generate().publicKey);
+  }
+
+  function mainPoolTokenAccounts() {
+    return {
+      pool: poolPDA,
+      userToken0: userTokenAccount0,
+      userToken1: userTokenAccount1,
+      poolToken0: tokenVault0,
+      poolToken1: tokenVault1,
+      payer: payer.publicKey,
+      tokenMint0,
+      tokenMint1,
+      tokenProgram0: TOKEN_PROGRAM_ID,
+      tokenProgram1: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      rent: SYSVAR_RENT_PUBKEY,
+    };
+  }
+
+  function swapRequest(overrides: Record<string, PublicKey>) {
+    return program.methods
+      .swap(new BN(50), true, new BN(0), null, null)
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        ...mainPoolTokenAccounts(),
+        wsolAccount: null,
+        ...eventCpiAccounts,
+        ...overrides,
+      })
+      .rpc()
+  }
+
+  function openPositionRequest(overrides: Record<string, PublicKey>) {
+    return program.methods
+      .openPosition(
+        payer.publicKey,
+        LOWER_TICK,
+        UPPER_TICK,
+        LIQUIDITY_AMOUNT,
+        getTickArrayStartIndex(LOWER_TICK, TICK_SPACING),
+        getTickArrayStartIndex(UPPER_TICK, TICK_SPACING),
+        U64_MAX,
+        U64_MAX,
+        null,
+        null
+      )
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        ...mainPoolTokenAccounts(),
+        lowerTickArray: lowerTickArrayPda,
+        upperTickArray: upperTickArrayPda,
+        position: positionPda,
+        wsolAccount: null,
+        ...eventCpiAccounts,
+        ...overrides,
+      })
+      .rpc()
+  }
+
+  function increaseLiquidityRequest(overrides: Record<string, PublicKey>) {
+    return program.methods
+      .increaseLiquidity(new BN(1000), U64_MAX, U64_MAX)
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        ...mainPoolTokenAccounts(),
+        lowerTickArray: lowerTickArrayPda,
+        upperTickArray: upperTickArrayPda,
+        position: positionPda,
+        ...eventCpiAccounts,
+        ...overrides,
+      })
+      .rpc()
+  }
+
+  function decreaseLiquidityRequest(overrides: Record<string, PublicKey>) {
+    return program.methods
+      .decreaseLiquidity(new BN(1000), new BN(0), new BN(0), null, null)
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        ...mainPoolTokenAccounts(),
+        lowerTickArray: lowerTickArrayPda,
+        upperTickArray: upperTickArrayPda,
+        position: positionPda,
+        ...eventCpiAccounts,
+        ...overrides,
+      })
+      .rpc()
+  }
+
   beforeAll(async() => {
+    [globalConfigPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("global_config")],
+      program.programId
+    );
+
+    [programDataPda] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      BPF_LOADER_UPGRADEABLE_PROGRAM_ID
+    );
+
     // Create token mints
     tokenMint0 = await createMint(
       provider.connection,
@@ -60,15 +365,20 @@ describe('Clmm', () => {
       6
     );
 
+    // Pools require canonically ordered mints
+    if (Buffer.compare(tokenMint0.toBuffer(), tokenMint1.toBuffer()) > 0) {
+      [tokenMint0, tokenMint1] = [tokenMint1, tokenMint0];
+    }
+
     // Derive pool PDA
     [poolPDA] = PublicKey.findProgramAddressSync(
       [Buffer.from("pool"), tokenMint0.toBuffer(), tokenMint1.toBuffer(), i32ToLeBytes(TICK_SPACING)], 
       program.programId
     );
 
-    // Generate token vault keypairs
-    tokenVault0Keypair = Keypair.generate();
-    tokenVault1Keypair = Keypair.generate();
+    // Derive token vault PDAs
+    tokenVault0 = deriveTokenVault(poolPDA, tokenMint0);
+    tokenVault1 = deriveTokenVault(poolPDA, tokenMint1);
 
     // Create user token accounts
     userTokenAccount0 = await createAssociatedTokenAccount(
@@ -136,23 +446,77 @@ describe('Clmm', () => {
       ],
       program.programId
     );
+
+    [positionBundlePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("position_bundle"), payer.publicKey.toBuffer(), poolPDA.toBuffer()],
+      program.programId
+    );
+  })
+
+  it('Initialize Global Config', async () => {
+    const tx = await program.methods
+      .initializeGlobalConfig(new BN(TIMELOCK_DELAY))
+      .accountsStrict({
+        payer: payer.publicKey,
+        globalConfig: globalConfigPda,
+        programData: programDataPda,
+        systemProgram: SystemProgram.programId,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+      console.log("initGlobalConfig tx: ", tx);
+
+    const events = await getEvents(tx);
+    expect(events.map((event) => event.name)).toEqual(["globalConfigInitialized"]);
+    expect(events[0].data.authority.toBase58()).toEqual(payer.publicKey.toBase58());
+    expect(events[0].data.timelockDelay.toNumber()).toEqual(TIMELOCK_DELAY);
+
+    const globalConfig = await program.account.globalConfig.fetch(globalConfigPda);
+    expect(globalConfig.authority.toString()).toEqual(payer.publicKey.toString());
+    expect(globalConfig.pendingAuthority.toString()).toEqual(PublicKey.default.toString());
+    expect(globalConfig.pauseFlags).toEqual(0);
+    expect(globalConfig.timelockDelay.toNumber()).toEqual(TIMELOCK_DELAY);
+  })
+
+  it('Grants operational roles', async () => {
+    const roles = [{ feeManager: {} }, { pauser: {} }, { rewardFunder: {} }, { poolCreator: {} }];
+
+    for (const role of roles) {
+      await program.methods
+        .grantRole(role as any, payer.publicKey)
+        .accountsStrict({
+          authority: payer.publicKey,
+          globalConfig: globalConfigPda,
+          ...eventCpiAccounts,
+        })
+        .rpc()
+    }
+
+    const globalConfig = await program.account.globalConfig.fetch(globalConfigPda);
+    expect(globalConfig.feeManager.toString()).toEqual(payer.publicKey.toString());
+    expect(globalConfig.pauser.toString()).toEqual(payer.publicKey.toString());
+    expect(globalConfig.rewardFunder.toString()).toEqual(payer.publicKey.toString());
+    expect(globalConfig.poolCreator.toString()).toEqual(payer.publicKey.toString());
   })
 
   it('Initialize Pool', async () => {
     const tx = await program.methods
       .initializePool(TICK_SPACING, INITIAL_SQRT_PRICE)
       .accountsStrict({
+        globalConfig: globalConfigPda,
         payer: payer.publicKey,
         pool: poolPDA,
         tokenMint0,
         tokenMint1,
-        tokenVault0: tokenVault0Keypair.publicKey,
-        tokenVault1: tokenVault1Keypair.publicKey,
+        tokenVault0,
+        tokenVault1,
         systemProgram: SystemProgram.programId,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
         rent: SYSVAR_RENT_PUBKEY,
+        ...eventCpiAccounts,
       })
-      .signers([tokenVault0Keypair, tokenVault1Keypair])
       .rpc()
 
       console.log("initPool tx: ", tx);
@@ -161,16 +525,124 @@ describe('Clmm', () => {
     console.log("PoolPda: ", poolPDA.toBase58());
     console.log("tokenMint0: ", tokenMint0.toBase58());
     console.log("tokenMint1: ", tokenMint1.toBase58());
-    console.log("tokenVault0: ", tokenVault0Keypair.publicKey.toBase58());
-    console.log("tokenVault1: ", tokenVault1Keypair.publicKey.toBase58());
+    console.log("tokenVault0: ", tokenVault0.toBase58());
+    console.log("tokenVault1: ", tokenVault1.toBase58());
 
     expect(poolAccount.tickSpacing).toEqual(TICK_SPACING);
     expect(poolAccount.tokenMint0.toString()).toEqual(tokenMint0.toString());
     expect(poolAccount.tokenMint1.toString()).toEqual(tokenMint1.toString());
+    expect(poolAccount.tokenVault0.toString()).toEqual(tokenVault0.toString());
+    expect(poolAccount.tokenVault1.toString()).toEqual(tokenVault1.toString());
     expect(poolAccount.globalLiquidity.toNumber()).toEqual(0);
     expect(poolAccount.sqrtPriceX96.toString()).toEqual(INITIAL_SQRT_PRICE.toString());
   })
 
+  it('Fails to initialize a pool with unordered mints', async () => {
+    const [reversedPoolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), tokenMint1.toBuffer(), tokenMint0.toBuffer(), i32ToLeBytes(TICK_SPACING)],
+      program.programId
+    );
+    try {
+      await program.methods
+        .initializePool(TICK_SPACING, INITIAL_SQRT_PRICE)
+        .accountsStrict({
+          globalConfig: globalConfigPda,
+          payer: payer.publicKey,
+          pool: reversedPoolPda,
+          tokenMint0: tokenMint1,
+          tokenMint1: tokenMint0,
+          tokenVault0: deriveTokenVault(reversedPoolPda, tokenMint1),
+          tokenVault1: deriveTokenVault(reversedPoolPda, tokenMint0),
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+          ...eventCpiAccounts,
+        })
+        .rpc()
+
+      expect(true).toBe(false);
+    } catch (error) {
+      expect(error.error?.errorCode?.code).toEqual("TokenMintsNotOrdered");
+    }
+  })
+
+  it('Rejects a pool with a tick spacing outside the allowed set', async () => {
+    const invalidTickSpacing = 7;
+    const [invalidPoolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), tokenMint0.toBuffer(), tokenMint1.toBuffer(), i32ToLeBytes(invalidTickSpacing)],
+      program.programId
+    );
+
+    await expectAnchorError(
+      program.methods
+        .initializePool(invalidTickSpacing, INITIAL_SQRT_PRICE)
+        .accountsStrict({
+          globalConfig: globalConfigPda,
+          payer: payer.publicKey,
+          pool: invalidPoolPda,
+          tokenMint0,
+          tokenMint1,
+          tokenVault0: deriveTokenVault(invalidPoolPda, tokenMint0),
+          tokenVault1: deriveTokenVault(invalidPoolPda, tokenMint1),
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+          ...eventCpiAccounts,
+        })
+        .rpc(),
+      "InvalidTickSpacing"
+    );
+  })
+
+  it('Initializes a pool pairing a legacy mint with a Token-2022 mint', async () => {
+    const token2022Mint = await createMint(
+      provider.connection,
+      payer.payer,
+      payer.publicKey,
+      null,
+      9,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    const [side0, side1] = sortMintsWithPrograms(
+      [tokenMint0, TOKEN_PROGRAM_ID],
+      [token2022Mint, TOKEN_2022_PROGRAM_ID]
+    );
+    const { pool, vault0, vault1 } = await initializePoolFor(side0, side1);
+
+    const poolAccount = await program.account.pool.fetch(pool);
+    expect(poolAccount.tokenMint0.toString()).toEqual(side0[0].toString());
+    expect(poolAccount.tokenMint1.toString()).toEqual(side1[0].toString());
+
+    const vault0Info = await provider.connection.getAccountInfo(vault0);
+    const vault1Info = await provider.connection.getAccountInfo(vault1);
+    expect(vault0Info.owner.toString()).toEqual(side0[1].toString());
+    expect(vault1Info.owner.toString()).toEqual(side1[1].toString());
+  })
+
+  it('Rejects Token-2022 mints with unsupported extensions', async () => {
+    const delegateMint = await createToken2022Mint(
+      [ExtensionType.PermanentDelegate],
+      (mint) => [createInitializePermanentDelegateInstruction(mint, payer.publicKey, TOKEN_2022_PROGRAM_ID)]
+    );
+
+    const [side0, side1] = sortMintsWithPrograms(
+      [tokenMint0, TOKEN_PROGRAM_ID],
+      [delegateMint, TOKEN_2022_PROGRAM_ID]
+    );
+
+    try {
+      await initializePoolFor(side0, side1);
+      expect(true).toBe(false);
+    } catch (error) {
+      expect(error.error?.errorCode?.code).toEqual("UnsupportedMintExtension");
+    }
+  })
+
   it('Open Position in pool', async () => {
     const lowerTickArrayStartIndex = getTickArrayStartIndex(LOWER_TICK, TICK_SPACING);
     const upperTickArrayStartIndex = getTickArrayStartIndex(UPPER_TICK, TICK_SPACING);
@@ -195,22 +667,29 @@ describe('Clmm', () => {
         UPPER_TICK,
         LIQUIDITY_AMOUNT,
         lowerTickArrayStartIndex,
-        upperTickArrayStartIndex 
+        upperTickArrayStartIndex,
+        U64_MAX,
+        U64_MAX,
+        null,
+        null
       )
       .accountsStrict({ 
+        globalConfig: globalConfigPda,
         pool: poolPDA,
         lowerTickArray: lowerTickArrayPda,
         upperTickArray: upperTickArrayPda,
         position: positionPda,
         userToken0: userTokenAccount0,
         userToken1: userTokenAccount1,
-        poolToken0: tokenVault0Keypair.publicKey,
-        poolToken1: tokenVault1Keypair.publicKey,
+        poolToken0: tokenVault0,
+        poolToken1: tokenVault1,
         payer: payer.publicKey,
+        wsolAccount: null,
         tokenMint0: tokenMint0,
         tokenMint1: tokenMint1,
         systemProgram: SystemProgram.programId,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
         rent: SYSVAR_RENT_PUBKEY,
       })
       .rpc({ skipPreflight: true })
@@ -246,22 +725,25 @@ describe('Clmm', () => {
     const positionBefore = await program.account.position.fetch(positionPda);
 
     const tx = await program.methods
-      .increaseLiquidity(additionalLiquidity)
+      .increaseLiquidity(additionalLiquidity, U64_MAX, U64_MAX)
       .accountsStrict({
+        globalConfig: globalConfigPda,
         pool: poolPDA,
         lowerTickArray: lowerTickArrayPda,
         upperTickArray: upperTickArrayPda,
         position: positionPda,
         userToken0: userTokenAccount0,
         userToken1: userTokenAccount1,
-        poolToken0: tokenVault0Keypair.publicKey,
-        poolToken1: tokenVault1Keypair.publicKey,
+        poolToken0: tokenVault0,
+        poolToken1: tokenVault1,
         payer: payer.publicKey,
         tokenMint0: tokenMint0,
         tokenMint1: tokenMint1,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
         systemProgram: SystemProgram.programId,
         rent: SYSVAR_RENT_PUBKEY,
+        ...eventCpiAccounts,
       })
       .rpc({ skipPreflight: true })
 
@@ -301,17 +783,23 @@ describe('Clmm', () => {
     const poolBefore = await program.account.pool.fetch(poolPDA);
 
     const tx = await program.methods
-      .swap(amountIn, swapToken0For1, amountOutMinimum)
+      .swap(amountIn, swapToken0For1, amountOutMinimum, null, null)
       .accountsStrict({
+        globalConfig: globalConfigPda,
         pool: poolPDA,
         userToken0: userTokenAccount0,
         userToken1: userTokenAccount1,
-        poolToken0: tokenVault0Keypair.publicKey,
-        poolToken1: tokenVault1Keypair.publicKey,
+        poolToken0: tokenVault0,
+        poolToken1: tokenVault1,
         payer: payer.publicKey,
+        wsolAccount: null,
+        tokenMint0: tokenMint0,
+        tokenMint1: tokenMint1,
         systemProgram: SystemProgram.programId,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
         rent: SYSVAR_RENT_PUBKEY,
+        ...eventCpiAccounts,
       })
       .rpc({ skipPreflight: true })
 
@@ -332,6 +820,18 @@ describe('Clmm', () => {
     expect(poolAfter.sqrtPriceX96.toString()).not.toEqual(poolBefore.sqrtPriceX96.toString());
     console.log("Price before:", poolBefore.sqrtPriceX96.toString());
     console.log("Price after:", poolAfter.sqrtPriceX96.toString());
+
+    const events = await getEvents(tx);
+    expect(events.map((event) => event.name)).toEqual(["swapped"]);
+    const swapped = events[0].data;
+    expect(swapped.pool.toBase58()).toEqual(poolPDA.toBase58());
+    expect(swapped.swapToken0For1).toEqual(true);
+    expect(swapped.amountIn.toNumber()).toEqual(amountIn.toNumber());
+    expect(swapped.amountOut.toNumber()).toEqual(expectedOutput);
+    expect(swapped.sqrtPriceBeforeX96.toString()).toEqual(poolBefore.sqrtPriceX96.toString());
+    expect(swapped.sqrtPriceAfterX96.toString()).toEqual(poolAfter.sqrtPriceX96.toString());
+    expect(swapped.tick).toEqual(poolAfter.currentTick);
+    expect(swapped.liquidity.toString()).toEqual(poolAfter.globalLiquidity.toString());
   })
 
   it('Swap token 1 for token 0', async () => {
@@ -343,17 +843,23 @@ describe('Clmm', () => {
     const userToken1Before = await getAccount(provider.connection, userTokenAccount1);
 
     const tx = await program.methods
-      .swap(amountIn, swapToken0For1, amountOutMinimum)
+      .swap(amountIn, swapToken0For1, amountOutMinimum, null, null)
       .accountsStrict({
+        globalConfig: globalConfigPda,
         pool: poolPDA,
         userToken0: userTokenAccount0,
         userToken1: userTokenAccount1,
-        poolToken0: tokenVault0Keypair.publicKey,
-        poolToken1: tokenVault1Keypair.publicKey,
+        poolToken0: tokenVault0,
+        poolToken1: tokenVault1,
         payer: payer.publicKey,
+        wsolAccount: null,
+        tokenMint0: tokenMint0,
+        tokenMint1: tokenMint1,
         systemProgram: SystemProgram.programId,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
         rent: SYSVAR_RENT_PUBKEY,
+        ...eventCpiAccounts,
       })
       .rpc({ skipPreflight: true })
 
@@ -378,8 +884,9 @@ describe('Clmm', () => {
     const poolBefore = await program.account.pool.fetch(poolPDA);
 
     const tx = await program.methods
-      .decreaseLiquidity(liquidityToRemove)
+      .decreaseLiquidity(liquidityToRemove, new BN(0), new BN(0), null, null)
       .accountsStrict({
+        globalConfig: globalConfigPda,
         payer: payer.publicKey,
         pool: poolPDA,
         lowerTickArray: lowerTickArrayPda,
@@ -387,13 +894,15 @@ describe('Clmm', () => {
         position: positionPda,
         userToken0: userTokenAccount0,
         userToken1: userTokenAccount1,
-        poolToken0: tokenVault0Keypair.publicKey,
-        poolToken1: tokenVault1Keypair.publicKey,
+        poolToken0: tokenVault0,
+        poolToken1: tokenVault1,
         tokenMint0: tokenMint0,
         tokenMint1: tokenMint1,
         systemProgram: SystemProgram.programId,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
         rent: SYSVAR_RENT_PUBKEY,
+        ...eventCpiAccounts,
       })
       .rpc({ skipPreflight: true })
 
@@ -421,6 +930,174 @@ describe('Clmm', () => {
     expect(Number(userToken1After.amount)).toEqual(Number(userToken1Before.amount) + expectedAmount);
   })
 
+  it('Rejects a swap against a substituted pool vault', async () => {
+    await expectAnchorError(swapRequest({ poolToken1: userTokenAccount1 }), "InvalidVault");
+  })
+
+  it('Rejects a swap from a user token account of the wrong mint', async () => {
+    await expectAnchorError(swapRequest({ userToken0: userTokenAccount1 }), "ConstraintTokenMint");
+  })
+
+  it('Rejects a swap paying out to a token account the payer does not own', async () => {
+    const foreignTokenAccount1 = await createForeignTokenAccount(tokenMint1);
+    await expectAnchorError(swapRequest({ userToken1: foreignTokenAccount1 }), "ConstraintTokenOwner");
+  })
+
+  it('Rejects a deposit into a substituted pool vault', async () => {
+    await expectAnchorError(openPositionRequest({ poolToken0: userTokenAccount0 }), "InvalidVault");
+  })
+
+  it('Rejects a deposit from a token account the payer does not own', async () => {
+    const foreignTokenAccount0 = await createForeignTokenAccount(tokenMint0);
+    await expectAnchorError(openPositionRequest({ userToken0: foreignTokenAccount0 }), "ConstraintTokenOwner");
+  })
+
+  it('Rejects increasing liquidity into a substituted pool vault', async () => {
+    await expectAnchorError(increaseLiquidityRequest({ poolToken1: userTokenAccount1 }), "InvalidVault");
+  })
+
+  it('Rejects increasing liquidity with swapped tick arrays', async () => {
+    await expectAnchorError(
+      increaseLiquidityRequest({ lowerTickArray: upperTickArrayPda, upperTickArray: lowerTickArrayPda }),
+      "InvalidTickArray"
+    );
+  })
+
+  it('Rejects withdrawing from a substituted pool vault', async () => {
+    await expectAnchorError(decreaseLiquidityRequest({ poolToken0: userTokenAccount0 }), "InvalidVault");
+  })
+
+  it('Rejects withdrawing into a user token account of the wrong mint', async () => {
+    await expectAnchorError(decreaseLiquidityRequest({ userToken1: userTokenAccount0 }), "ConstraintTokenMint");
+  })
+
+  it('Rejects decreasing liquidity with swapped tick arrays', async () => {
+    await expectAnchorError(
+      decreaseLiquidityRequest({ lowerTickArray: upperTickArrayPda, upperTickArray: lowerTickArrayPda }),
+      "InvalidTickArray"
+    );
+  })
+
+  it('Rejects swaps and liquidity changes past their deadline or off the expected price', async () => {
+    const now = Math.floor(Date.now() / 1000);
+    const expired = new BN(now - 3600);
+    const pool = await program.account.pool.fetch(poolPDA);
+    const stalePrice = {
+      sqrtPriceX96: pool.sqrtPriceX96.add(new BN(10_000_000_000)),
+      toleranceX96: new BN(1_000_000_000),
+    };
+    const swapAccounts = {
+      globalConfig: globalConfigPda,
+      ...mainPoolTokenAccounts(),
+      wsolAccount: null,
+      ...eventCpiAccounts,
+    };
+    const liquidityAccounts = {
+      globalConfig: globalConfigPda,
+      ...mainPoolTokenAccounts(),
+      lowerTickArray: lowerTickArrayPda,
+      upperTickArray: upperTickArrayPda,
+      position: positionPda,
+      ...eventCpiAccounts,
+    };
+
+    await expectAnchorError(
+      program.methods.swap(new BN(50), true, new BN(0), expired, null).accountsStrict(swapAccounts).rpc(),
+      "DeadlineExceeded"
+    );
+    await expectAnchorError(
+      program.methods.swap(new BN(50), true, new BN(0), null, stalePrice).accountsStrict(swapAccounts).rpc(),
+      "SqrtPriceOutOfTolerance"
+    );
+    await expectAnchorError(
+      program.methods
+        .openPosition(
+          payer.publicKey,
+          LOWER_TICK,
+          UPPER_TICK,
+          LIQUIDITY_AMOUNT,
+          getTickArrayStartIndex(LOWER_TICK, TICK_SPACING),
+          getTickArrayStartIndex(UPPER_TICK, TICK_SPACING),
+          U64_MAX,
+          U64_MAX,
+          null,
+          stalePrice
+        )
+        .accountsStrict({ ...liquidityAccounts, wsolAccount: null })
+        .rpc(),
+      "SqrtPriceOutOfTolerance"
+    );
+    await expectAnchorError(
+      program.methods.decreaseLiquidity(new BN(1000), new BN(0), new BN(0), expired, null).accountsStrict(liquidityAccounts).rpc(),
+      "DeadlineExceeded"
+    );
+
+    // Guards that still hold let the swap through. Token 1 goes in so the price stays inside
+    // the position's range; the quote test's token 0 swap later moves it back.
+    const freshPrice = { sqrtPriceX96: pool.sqrtPriceX96, toleranceX96: new BN(1_000_000_000) };
+    await program.methods
+      .swap(new BN(50), false, new BN(0), new BN(now + 3600), freshPrice)
+      .accountsStrict(swapAccounts)
+      .rpc()
+  })
+
+  it('Enforces deposit maximums and withdrawal minimums before moving tokens', async () => {
+    const liquidityAccounts = {
+      globalConfig: globalConfigPda,
+      ...mainPoolTokenAccounts(),
+      lowerTickArray: lowerTickArrayPda,
+      upperTickArray: upperTickArrayPda,
+      position: positionPda,
+      ...eventCpiAccounts,
+    };
+    // 1000 liquidity moves 1 token of each side while the price is in range
+    const liquidity = new BN(1000);
+    const userToken0Before = await getAccount(provider.connection, userTokenAccount0);
+
+    await expectAnchorError(
+      program.methods
+        .openPosition(
+          payer.publicKey,
+          LOWER_TICK,
+          UPPER_TICK,
+          liquidity,
+          getTickArrayStartIndex(LOWER_TICK, TICK_SPACING),
+          getTickArrayStartIndex(UPPER_TICK, TICK_SPACING),
+          new BN(0),
+          U64_MAX,
+          null,
+          null
+        )
+        .accountsStrict({ ...liquidityAccounts, wsolAccount: null })
+        .rpc(),
+      "SlippageExceeded"
+    );
+    await expectAnchorError(
+      program.methods.increaseLiquidity(liquidity, U64_MAX, new BN(0)).accountsStrict(liquidityAccounts).rpc(),
+      "SlippageExceeded"
+    );
+    await expectAnchorError(
+      program.methods
+        .decreaseLiquidity(liquidity, new BN(2), new BN(0), null, null)
+        .accountsStrict(liquidityAccounts)
+        .rpc(),
+      "SlippageExceeded"
+    );
+
+    const userToken0After = await getAccount(provider.connection, userTokenAccount0);
+    expect(userToken0After.amount).toEqual(userToken0Before.amount);
+
+    // Exact bounds are accepted
+    await program.methods
+      .increaseLiquidity(liquidity, new BN(1), new BN(1))
+      .accountsStrict(liquidityAccounts)
+      .rpc()
+    await program.methods
+      .decreaseLiquidity(liquidity, new BN(1), new BN(1), null, null)
+      .accountsStrict(liquidityAccounts)
+      .rpc()
+  })
+
   it('Fails to swap with insufficient liquidity', async () => {
     const amountIn = new BN(100000000000); // Very large amount
     const amountOutMinimum = new BN(1);
@@ -428,17 +1105,23 @@ describe('Clmm', () => {
 
     try {
       await program.methods
-        .swap(amountIn, swapToken0For1, amountOutMinimum)
+        .swap(amountIn, swapToken0For1, amountOutMinimum, null, null)
         .accountsStrict({
+          globalConfig: globalConfigPda,
           pool: poolPDA,
           userToken0: userTokenAccount0,
           userToken1: userTokenAccount1,
-          poolToken0: tokenVault0Keypair.publicKey,
-          poolToken1: tokenVault1Keypair.publicKey,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
           payer: payer.publicKey,
+          wsolAccount: null,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
           systemProgram: SystemProgram.programId,
-          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
           rent: SYSVAR_RENT_PUBKEY,
+          ...eventCpiAccounts,
         })
         .rpc()
       
@@ -495,22 +1178,29 @@ describe('Clmm', () => {
           invalidUpperTick,
           LIQUIDITY_AMOUNT,
           lowerTickArrayStartIndex,
-          upperTickArrayStartIndex 
+          upperTickArrayStartIndex,
+          U64_MAX,
+          U64_MAX,
+          null,
+          null
         )
         .accountsStrict({ 
+          globalConfig: globalConfigPda,
           pool: poolPDA,
           lowerTickArray: invalidLowerTickArrayPda,
           upperTickArray: invalidUpperTickArrayPda,
           position: invalidPositionPda,
           userToken0: userTokenAccount0,
           userToken1: userTokenAccount1,
-          poolToken0: tokenVault0Keypair.publicKey,
-          poolToken1: tokenVault1Keypair.publicKey,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
           payer: payer.publicKey,
+          wsolAccount: null,
           tokenMint0: tokenMint0,
           tokenMint1: tokenMint1,
           systemProgram: SystemProgram.programId,
-          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
           rent: SYSVAR_RENT_PUBKEY,
         })
         .rpc()
@@ -523,4 +1213,1711 @@ describe('Clmm', () => {
       expect(error).toBeDefined();
     }
   })
-})
\ No newline at end of file
+
+  it('Collect Fees', async () => {
+    const tx = await program.methods
+      .collectFees()
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        pool: poolPDA,
+        position: positionPda,
+        userToken0: userTokenAccount0,
+        userToken1: userTokenAccount1,
+        poolToken0: tokenVault0,
+        poolToken1: tokenVault1,
+        payer: payer.publicKey,
+        tokenMint0: tokenMint0,
+        tokenMint1: tokenMint1,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
+        ...eventCpiAccounts,
+      })
+      .rpc({ skipPreflight: true })
+
+      console.log("collect fees tx:", tx);
+
+    const positionAfter = await program.account.position.fetch(positionPda);
+    expect(positionAfter.tokensOwed0.toNumber()).toEqual(0);
+    expect(positionAfter.tokensOwed1.toNumber()).toEqual(0);
+
+    const events = await getEvents(tx);
+    expect(events.map((event) => event.name)).toEqual(["feesCollected"]);
+    expect(events[0].data.position.toBase58()).toEqual(positionPda.toBase58());
+    expect(events[0].data.bundleIndex).toBeNull();
+  })
+
+  it('Rejects collecting fees from a substituted pool vault', async () => {
+    await expectAnchorError(
+      program.methods
+        .collectFees()
+        .accountsStrict({
+          globalConfig: globalConfigPda,
+          pool: poolPDA,
+          position: positionPda,
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: userTokenAccount0,
+          poolToken1: tokenVault1,
+          payer: payer.publicKey,
+          tokenMint0,
+          tokenMint1,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventCpiAccounts,
+        })
+        .rpc(),
+      "InvalidVault"
+    );
+  })
+
+  it('Rejects collecting protocol fees from a substituted pool vault', async () => {
+    await expectAnchorError(
+      program.methods
+        .collectProtocolFees()
+        .accountsStrict({
+          feeManager: payer.publicKey,
+          globalConfig: globalConfigPda,
+          pool: poolPDA,
+          poolToken0: tokenVault0,
+          poolToken1: userTokenAccount1,
+          recipientToken0: userTokenAccount0,
+          recipientToken1: userTokenAccount1,
+          tokenMint0,
+          tokenMint1,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventCpiAccounts,
+        })
+        .rpc(),
+      "InvalidVault"
+    );
+  })
+
+  it('Get Position Value', async () => {
+    const positionAccount = await program.account.position.fetch(positionPda);
+
+    const value = await program.methods
+      .getPositionValue()
+      .accountsStrict({
+        pool: poolPDA,
+        position: positionPda,
+      })
+      .view()
+
+    // Price sits inside [LOWER_TICK, UPPER_TICK), so both sides are held (simplified: liquidity/1000)
+    const expectedAmount = positionAccount.liquidity.toNumber() / 1000;
+
+    expect(value.inRange).toEqual(true);
+    expect(value.amount0.toNumber()).toEqual(expectedAmount);
+    expect(value.amount1.toNumber()).toEqual(expectedAmount);
+    expect(value.feesOwed0.toNumber()).toEqual(positionAccount.tokensOwed0.toNumber());
+    expect(value.feesOwed1.toNumber()).toEqual(positionAccount.tokensOwed1.toNumber());
+  })
+
+  it('Quotes a swap without changing the pool and matches its execution', async () => {
+    const amountIn = new BN(50);
+    const poolBefore = await program.account.pool.fetch(poolPDA);
+    const crossedTickArrays = await getCrossedTickArrays(poolPDA, true);
+
+    // Quoting without the tick arrays the swap crosses is rejected
+    if (crossedTickArrays.length > 0) {
+      await expectAnchorError(
+        program.methods
+          .quoteSwap(amountIn, true)
+          .accountsStrict({ pool: poolPDA, tokenMint0, tokenMint1 })
+          .remainingAccounts(crossedTickArrays.slice(1))
+          .view(),
+        "MissingTickArray"
+      );
+    }
+
+    const quote = await program.methods
+      .quoteSwap(amountIn, true)
+      .accountsStrict({
+        pool: poolPDA,
+        tokenMint0,
+        tokenMint1,
+      })
+      .remainingAccounts(crossedTickArrays)
+      .view()
+
+    const poolAfterQuote = await program.account.pool.fetch(poolPDA);
+    expect(poolAfterQuote.sqrtPriceX96.toString()).toEqual(poolBefore.sqrtPriceX96.toString());
+    expect(poolAfterQuote.feeGrowthGlobal0X64.toString()).toEqual(poolBefore.feeGrowthGlobal0X64.toString());
+
+    const userToken0Before = await getAccount(provider.connection, userTokenAccount0);
+    const userToken1Before = await getAccount(provider.connection, userTokenAccount1);
+    await program.methods
+      .swap(amountIn, true, quote.amountOut, null, null)
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        ...mainPoolTokenAccounts(),
+        wsolAccount: null,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+    const userToken0After = await getAccount(provider.connection, userTokenAccount0);
+    const userToken1After = await getAccount(provider.connection, userTokenAccount1);
+    const poolAfterSwap = await program.account.pool.fetch(poolPDA);
+
+    expect(Number(userToken0Before.amount) - Number(userToken0After.amount)).toEqual(quote.amountIn.toNumber());
+    expect(Number(userToken1After.amount) - Number(userToken1Before.amount)).toEqual(quote.amountOut.toNumber());
+    expect(quote.feeAmount.toNumber()).toEqual(Math.floor(amountIn.toNumber() / 1000));
+    expect(quote.sqrtPriceX96.toString()).toEqual(poolAfterSwap.sqrtPriceX96.toString());
+    expect(quote.tick).toEqual(poolAfterSwap.currentTick);
+
+    // Count the initialized ticks in (end tick, start tick] of the arrays that exist
+    let expectedTicksCrossed = 0;
+    for (const { pubkey } of crossedTickArrays) {
+      const tickArray = await program.account.tickArray.fetchNullable(pubkey);
+      tickArray?.ticks.forEach((tick, offset) => {
+        const tickIndex = tickArray.startingTick + offset * TICK_SPACING;
+        if (
+          tick.initialized.toNumber() !== 0 &&
+          tickIndex > poolAfterSwap.currentTick &&
+          tickIndex <= poolBefore.currentTick
+        ) {
+          expectedTicksCrossed += 1;
+        }
+      });
+    }
+    expect(quote.ticksCrossed).toEqual(expectedTicksCrossed);
+  })
+
+  it('Initialize Position Bundle', async () => {
+    const tx = await program.methods
+      .initializePositionBundle()
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        payer: payer.publicKey,
+        pool: poolPDA,
+        positionBundle: positionBundlePda,
+        systemProgram: SystemProgram.programId,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+      console.log("initialize position bundle tx:", tx);
+
+    const bundleAccount = await program.account.positionBundle.fetch(positionBundlePda);
+    expect(bundleAccount.owner.toString()).toEqual(payer.publicKey.toString());
+    expect(bundleAccount.pool.toString()).toEqual(poolPDA.toString());
+    expect(bundleAccount.positionBitmap.toNumber()).toEqual(0);
+  })
+
+  it('Open, increase and decrease a bundled position', async () => {
+    const bundleIndex = 3;
+    const lowerTickArrayStartIndex = getTickArrayStartIndex(LOWER_TICK, TICK_SPACING);
+    const upperTickArrayStartIndex = getTickArrayStartIndex(UPPER_TICK, TICK_SPACING);
+    const poolBefore = await program.account.pool.fetch(poolPDA);
+
+    const tx = await program.methods
+      .openBundledPosition(
+        bundleIndex,
+        LOWER_TICK,
+        UPPER_TICK,
+        LIQUIDITY_AMOUNT,
+        lowerTickArrayStartIndex,
+        upperTickArrayStartIndex,
+        U64_MAX,
+        U64_MAX
+      )
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        pool: poolPDA,
+        lowerTickArray: lowerTickArrayPda,
+        upperTickArray: upperTickArrayPda,
+        positionBundle: positionBundlePda,
+        userToken0: userTokenAccount0,
+        userToken1: userTokenAccount1,
+        poolToken0: tokenVault0,
+        poolToken1: tokenVault1,
+        payer: payer.publicKey,
+        tokenMint0: tokenMint0,
+        tokenMint1: tokenMint1,
+        systemProgram: SystemProgram.programId,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+        ...eventCpiAccounts,
+      })
+      .rpc({ skipPreflight: true })
+
+      console.log("open bundled position tx:", tx);
+
+    let bundleAccount = await program.account.positionBundle.fetch(positionBundlePda);
+    expect(bundleAccount.positionBitmap.toNumber()).toEqual(1 << bundleIndex);
+    expect(bundleAccount.positions[bundleIndex].tickLower).toEqual(LOWER_TICK);
+    expect(bundleAccount.positions[bundleIndex].tickUpper).toEqual(UPPER_TICK);
+    expect(bundleAccount.positions[bundleIndex].liquidityLower.toString()).toEqual(LIQUIDITY_AMOUNT.toString());
+
+    await program.methods
+      .increaseBundledLiquidity(bundleIndex, LIQUIDITY_AMOUNT, U64_MAX, U64_MAX)
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        pool: poolPDA,
+        lowerTickArray: lowerTickArrayPda,
+        upperTickArray: upperTickArrayPda,
+        positionBundle: positionBundlePda,
+        userToken0: userTokenAccount0,
+        userToken1: userTokenAccount1,
+        poolToken0: tokenVault0,
+        poolToken1: tokenVault1,
+        payer: payer.publicKey,
+        tokenMint0: tokenMint0,
+        tokenMint1: tokenMint1,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
+        ...eventCpiAccounts,
+      })
+      .rpc({ skipPreflight: true })
+
+    bundleAccount = await program.account.positionBundle.fetch(positionBundlePda);
+    expect(bundleAccount.positions[bundleIndex].liquidityLower.toString()).toEqual(
+      LIQUIDITY_AMOUNT.muln(2).toString()
+    );
+
+    await program.methods
+      .decreaseBundledLiquidity(bundleIndex, LIQUIDITY_AMOUNT.muln(2), new BN(0), new BN(0))
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        payer: payer.publicKey,
+        pool: poolPDA,
+        lowerTickArray: lowerTickArrayPda,
+        upperTickArray: upperTickArrayPda,
+        positionBundle: positionBundlePda,
+        userToken0: userTokenAccount0,
+        userToken1: userTokenAccount1,
+        poolToken0: tokenVault0,
+        poolToken1: tokenVault1,
+        tokenMint0: tokenMint0,
+        tokenMint1: tokenMint1,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
+        ...eventCpiAccounts,
+      })
+      .rpc({ skipPreflight: true })
+
+    // A fully withdrawn position with nothing owed frees its slot
+    bundleAccount = await program.account.positionBundle.fetch(positionBundlePda);
+    expect(bundleAccount.positionBitmap.toNumber()).toEqual(0);
+
+    const poolAfter = await program.account.pool.fetch(poolPDA);
+    expect(poolAfter.globalLiquidity.toString()).toEqual(poolBefore.globalLiquidity.toString());
+  })
+
+  it('Rejects bundled liquidity changes through substituted vaults, foreign tick arrays or past the amount bounds', async () => {
+    const bundleIndex = 5;
+    const lowerTickArrayStartIndex = getTickArrayStartIndex(LOWER_TICK, TICK_SPACING);
+    const upperTickArrayStartIndex = getTickArrayStartIndex(UPPER_TICK, TICK_SPACING);
+    const liquidityAccounts = {
+      globalConfig: globalConfigPda,
+      pool: poolPDA,
+      lowerTickArray: lowerTickArrayPda,
+      upperTickArray: upperTickArrayPda,
+      positionBundle: positionBundlePda,
+      userToken0: userTokenAccount0,
+      userToken1: userTokenAccount1,
+      poolToken0: tokenVault0,
+      poolToken1: tokenVault1,
+      payer: payer.publicKey,
+      tokenMint0,
+      tokenMint1,
+      tokenProgram0: TOKEN_PROGRAM_ID,
+      tokenProgram1: TOKEN_PROGRAM_ID,
+      ...eventCpiAccounts,
+    };
+    const openAccounts = {
+      ...liquidityAccounts,
+      systemProgram: SystemProgram.programId,
+      rent: SYSVAR_RENT_PUBKEY,
+    };
+    const openRequest = (
+      index: number,
+      lowerStartIndex: number,
+      overrides: Record<string, PublicKey>,
+      amount0Max = U64_MAX
+    ) =>
+      program.methods
+        .openBundledPosition(
+          index,
+          LOWER_TICK,
+          UPPER_TICK,
+          LIQUIDITY_AMOUNT,
+          lowerStartIndex,
+          upperTickArrayStartIndex,
+          amount0Max,
+          U64_MAX
+        )
+        .accountsStrict({ ...openAccounts, ...overrides })
+        .rpc();
+    const increaseRequest = (overrides: Record<string, PublicKey>, amount1Max = U64_MAX) =>
+      program.methods
+        .increaseBundledLiquidity(bundleIndex, LIQUIDITY_AMOUNT, U64_MAX, amount1Max)
+        .accountsStrict({ ...liquidityAccounts, ...overrides })
+        .rpc();
+    const decreaseRequest = (overrides: Record<string, PublicKey>, amount0Min = new BN(0)) =>
+      program.methods
+        .decreaseBundledLiquidity(bundleIndex, LIQUIDITY_AMOUNT, amount0Min, new BN(0))
+        .accountsStrict({ ...liquidityAccounts, ...overrides })
+        .rpc();
+
+    await expectAnchorError(openRequest(bundleIndex, lowerTickArrayStartIndex, { poolToken0: userTokenAccount0 }), "InvalidVault");
+    const foreignTokenAccount0 = await createForeignTokenAccount(tokenMint0);
+    await expectAnchorError(
+      openRequest(bundleIndex, lowerTickArrayStartIndex, { userToken0: foreignTokenAccount0 }),
+      "ConstraintTokenOwner"
+    );
+    // The upper array doesn't hold the lower tick
+    await expectAnchorError(
+      openRequest(bundleIndex, upperTickArrayStartIndex, { lowerTickArray: upperTickArrayPda }),
+      "InvalidTickArray"
+    );
+
+    await expectAnchorError(openRequest(bundleIndex, lowerTickArrayStartIndex, {}, new BN(0)), "SlippageExceeded");
+
+    await openRequest(bundleIndex, lowerTickArrayStartIndex, {});
+
+    await expectAnchorError(increaseRequest({}, new BN(0)), "SlippageExceeded");
+    await expectAnchorError(decreaseRequest({}, U64_MAX), "SlippageExceeded");
+
+    await expectAnchorError(increaseRequest({ poolToken1: userTokenAccount1 }), "InvalidVault");
+    await expectAnchorError(
+      increaseRequest({ lowerTickArray: upperTickArrayPda, upperTickArray: lowerTickArrayPda }),
+      "InvalidTickArray"
+    );
+    await expectAnchorError(decreaseRequest({ poolToken0: userTokenAccount0 }), "InvalidVault");
+    await expectAnchorError(
+      decreaseRequest({ lowerTickArray: upperTickArrayPda, upperTickArray: lowerTickArrayPda }),
+      "InvalidTickArray"
+    );
+    await expectAnchorError(
+      program.methods
+        .collectBundledFees(bundleIndex)
+        .accountsStrict({
+          globalConfig: globalConfigPda,
+          payer: payer.publicKey,
+          pool: poolPDA,
+          positionBundle: positionBundlePda,
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: userTokenAccount1,
+          tokenMint0,
+          tokenMint1,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventCpiAccounts,
+        })
+        .rpc(),
+      "InvalidVault"
+    );
+
+    // Leave the pool as it was for the tests that follow
+    await decreaseRequest({});
+    const bundleAccount = await program.account.positionBundle.fetch(positionBundlePda);
+    expect(bundleAccount.positionBitmap.toNumber()).toEqual(0);
+  })
+
+  it('Routes a swap across two pools with a single slippage check', async () => {
+    // Second pool pairing token 1 with a fresh mint, so the route is token 0 -> token 1 -> token 2
+    const tokenMint2 = await createMint(provider.connection, payer.payer, payer.publicKey, null, 9);
+    const userTokenAccount2 = await createAssociatedTokenAccount(
+      provider.connection,
+      payer.payer,
+      tokenMint2,
+      payer.publicKey
+    );
+    await mintTo(provider.connection, payer.payer, tokenMint2, userTokenAccount2, payer.publicKey, 1000000000);
+
+    const [side0, side1] = sortMintsWithPrograms([tokenMint1, TOKEN_PROGRAM_ID], [tokenMint2, TOKEN_PROGRAM_ID]);
+    const { pool, vault0, vault1 } = await initializePoolFor(side0, side1);
+    const mint1IsToken0 = side0[0].equals(tokenMint1);
+    const { lowerTickArrayStartIndex, upperTickArrayStartIndex, lowerTickArray, upperTickArray, position } =
+      derivePositionAccounts(pool);
+
+    await program.methods
+      .openPosition(
+        payer.publicKey,
+        LOWER_TICK,
+        UPPER_TICK,
+        LIQUIDITY_AMOUNT,
+        lowerTickArrayStartIndex,
+        upperTickArrayStartIndex,
+        U64_MAX,
+        U64_MAX,
+        null,
+        null
+      )
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        pool,
+        lowerTickArray,
+        upperTickArray,
+        position,
+        userToken0: mint1IsToken0 ? userTokenAccount1 : userTokenAccount2,
+        userToken1: mint1IsToken0 ? userTokenAccount2 : userTokenAccount1,
+        poolToken0: vault0,
+        poolToken1: vault1,
+        payer: payer.publicKey,
+        wsolAccount: null,
+        tokenMint0: side0[0],
+        tokenMint1: side1[0],
+        systemProgram: SystemProgram.programId,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    const hopAccounts = (hopPool: PublicKey, hopVault0: PublicKey, hopVault1: PublicKey, hopMint0: PublicKey, hopMint1: PublicKey) => [
+      { pubkey: hopPool, isWritable: true, isSigner: false },
+      { pubkey: hopVault0, isWritable: true, isSigner: false },
+      { pubkey: hopVault1, isWritable: true, isSigner: false },
+      { pubkey: hopMint0, isWritable: false, isSigner: false },
+      { pubkey: hopMint1, isWritable: false, isSigner: false },
+      { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+      { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+    ];
+    const firstHop = hopAccounts(poolPDA, tokenVault0, tokenVault1, tokenMint0, tokenMint1);
+    const secondHop = hopAccounts(pool, vault0, vault1, side0[0], side1[0]);
+    const routeAccounts = {
+      globalConfig: globalConfigPda,
+      userTokenIn: userTokenAccount0,
+      userTokenOut: userTokenAccount2,
+      payer: payer.publicKey,
+      ...eventCpiAccounts,
+    };
+
+    const amountIn = 50;
+    const firstHopOut = amountIn - Math.floor(amountIn / 1000);
+    const expectedOut = firstHopOut - Math.floor(firstHopOut / 1000);
+
+    // A minimum above what the route yields fails the whole route
+    await expectAnchorError(
+      program.methods
+        .swapRoute(new BN(amountIn), new BN(expectedOut + 1), 2)
+        .accountsStrict(routeAccounts)
+        .remainingAccounts([...firstHop, ...secondHop])
+        .rpc(),
+      "SlippageExceeded"
+    );
+
+    // Hops that don't chain into the output account are rejected
+    await expectAnchorError(
+      program.methods
+        .swapRoute(new BN(amountIn), new BN(0), 1)
+        .accountsStrict(routeAccounts)
+        .remainingAccounts(firstHop)
+        .rpc(),
+      "InvalidRoute"
+    );
+
+    const user0Before = await getAccount(provider.connection, userTokenAccount0);
+    const user1Before = await getAccount(provider.connection, userTokenAccount1);
+    const user2Before = await getAccount(provider.connection, userTokenAccount2);
+    const middleVaultBefore = await getAccount(provider.connection, mint1IsToken0 ? vault0 : vault1);
+
+    await program.methods
+      .swapRoute(new BN(amountIn), new BN(expectedOut), 2)
+      .accountsStrict(routeAccounts)
+      .remainingAccounts([...firstHop, ...secondHop])
+      .rpc()
+
+    const user0After = await getAccount(provider.connection, userTokenAccount0);
+    const user1After = await getAccount(provider.connection, userTokenAccount1);
+    const user2After = await getAccount(provider.connection, userTokenAccount2);
+    const middleVaultAfter = await getAccount(provider.connection, mint1IsToken0 ? vault0 : vault1);
+
+    expect(Number(user0Before.amount) - Number(user0After.amount)).toEqual(amountIn);
+    // The intermediate token goes pool to pool without touching the user's account
+    expect(Number(user1After.amount)).toEqual(Number(user1Before.amount));
+    expect(Number(middleVaultAfter.amount) - Number(middleVaultBefore.amount)).toEqual(firstHopOut);
+    expect(Number(user2After.amount) - Number(user2Before.amount)).toEqual(expectedOut);
+  })
+
+  it('Lends vault inventory within a transaction and credits the fee to LPs', async () => {
+    const flashMint = await createMint(provider.connection, payer.payer, payer.publicKey, null, 9);
+    const flashUserAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      payer.payer,
+      flashMint,
+      payer.publicKey
+    );
+    await mintTo(provider.connection, payer.payer, flashMint, flashUserAccount, payer.publicKey, 1000000000);
+
+    const [side0, side1] = sortMintsWithPrograms([tokenMint0, TOKEN_PROGRAM_ID], [flashMint, TOKEN_PROGRAM_ID]);
+    const { pool, vault0, vault1 } = await initializePoolFor(side0, side1);
+    const flashIsToken0 = side0[0].equals(flashMint);
+    const [userToken0, userToken1] = flashIsToken0
+      ? [flashUserAccount, userTokenAccount0]
+      : [userTokenAccount0, flashUserAccount];
+    const { lowerTickArrayStartIndex, upperTickArrayStartIndex, lowerTickArray, upperTickArray, position } =
+      derivePositionAccounts(pool);
+
+    const tokenAccounts = {
+      pool,
+      userToken0,
+      userToken1,
+      poolToken0: vault0,
+      poolToken1: vault1,
+      payer: payer.publicKey,
+      tokenMint0: side0[0],
+      tokenMint1: side1[0],
+      tokenProgram0: TOKEN_PROGRAM_ID,
+      tokenProgram1: TOKEN_PROGRAM_ID,
+    };
+
+    const liquidity = new BN(10_000_000);
+    await program.methods
+      .openPosition(
+        payer.publicKey,
+        LOWER_TICK,
+        UPPER_TICK,
+        liquidity,
+        lowerTickArrayStartIndex,
+        upperTickArrayStartIndex,
+        U64_MAX,
+        U64_MAX,
+        null,
+        null
+      )
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        lowerTickArray,
+        upperTickArray,
+        position,
+        wsolAccount: null,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+        ...tokenAccounts,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    const borrowAmount = 5000;
+    const flashFee = Math.ceil((borrowAmount * 1000) / 1_000_000);
+    const [amount0, amount1] = flashIsToken0 ? [new BN(borrowAmount), new BN(0)] : [new BN(0), new BN(borrowAmount)];
+    const flashLoanIx = await program.methods
+      .flashLoan(amount0, amount1)
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        ...tokenAccounts,
+        ...eventCpiAccounts,
+      })
+      .instruction()
+    const flashRepayIx = await program.methods
+      .flashRepay()
+      .accountsStrict({ ...tokenAccounts, ...eventCpiAccounts })
+      .instruction()
+
+    // Borrowing without repaying in the same transaction is rejected up front
+    await expectAnchorError(
+      program.methods
+        .flashLoan(amount0, amount1)
+        .accountsStrict({
+          globalConfig: globalConfigPda,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          ...tokenAccounts,
+          ...eventCpiAccounts,
+        })
+        .rpc(),
+      "FlashRepayMissing"
+    );
+
+    const flashVault = flashIsToken0 ? vault0 : vault1;
+    const vaultBefore = await getAccount(provider.connection, flashVault);
+    const userBefore = await getAccount(provider.connection, flashUserAccount);
+    const poolBefore = await program.account.pool.fetch(pool);
+
+    const flashTx = await provider.sendAndConfirm(new Transaction().add(flashLoanIx, flashRepayIx));
+
+    const vaultAfter = await getAccount(provider.connection, flashVault);
+    const userAfter = await getAccount(provider.connection, flashUserAccount);
+    const poolAfter = await program.account.pool.fetch(pool);
+
+    expect(Number(vaultAfter.amount) - Number(vaultBefore.amount)).toEqual(flashFee);
+    expect(Number(userBefore.amount) - Number(userAfter.amount)).toEqual(flashFee);
+    expect(poolAfter.flashLoan).toBeNull();
+
+    const events = await getEvents(flashTx);
+    expect(events.map((event) => event.name)).toEqual(["flashLoanTaken", "flashLoanRepaid"]);
+    expect(events[1].data.pool.toBase58()).toEqual(pool.toBase58());
+
+    const feeGrowthBefore = flashIsToken0 ? poolBefore.feeGrowthGlobal0X64 : poolBefore.feeGrowthGlobal1X64;
+    const feeGrowthAfter = flashIsToken0 ? poolAfter.feeGrowthGlobal0X64 : poolAfter.feeGrowthGlobal1X64;
+    const expectedFeeGrowth = new BN(flashFee).shln(64).div(liquidity);
+    expect(feeGrowthAfter.sub(feeGrowthBefore).toString()).toEqual(expectedFeeGrowth.toString());
+
+    // The fee rounds up, so a single unit still costs one
+    const [unit0, unit1] = flashIsToken0 ? [new BN(1), new BN(0)] : [new BN(0), new BN(1)];
+    const unitLoanIx = await program.methods
+      .flashLoan(unit0, unit1)
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        ...tokenAccounts,
+        ...eventCpiAccounts,
+      })
+      .instruction()
+    await provider.sendAndConfirm(new Transaction().add(unitLoanIx, flashRepayIx));
+    const vaultAfterUnit = await getAccount(provider.connection, flashVault);
+    const userAfterUnit = await getAccount(provider.connection, flashUserAccount);
+    expect(Number(vaultAfterUnit.amount) - Number(vaultAfter.amount)).toEqual(1);
+    expect(Number(userAfter.amount) - Number(userAfterUnit.amount)).toEqual(1);
+
+    // Nothing may move the vaults or the liquidity while the loan is outstanding
+    const swapIx = await program.methods
+      .swap(new BN(50), flashIsToken0, new BN(0), null, null)
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        wsolAccount: null,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+        ...tokenAccounts,
+        ...eventCpiAccounts,
+      })
+      .instruction()
+    const decreaseIx = await program.methods
+      .decreaseLiquidity(new BN(1000), new BN(0), new BN(0), null, null)
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        lowerTickArray,
+        upperTickArray,
+        position,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+        ...tokenAccounts,
+        ...eventCpiAccounts,
+      })
+      .instruction()
+    for (const innerIx of [swapIx, decreaseIx]) {
+      await expectAnchorError(
+        program.methods
+          .flashLoan(amount0, amount1)
+          .accountsStrict({
+            globalConfig: globalConfigPda,
+            instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+            ...tokenAccounts,
+            ...eventCpiAccounts,
+          })
+          .postInstructions([innerIx, flashRepayIx])
+          .rpc(),
+        "FlashLoanInProgress"
+      );
+    }
+  })
+
+  it('Flash swaps through a callback program that pays the input afterwards', async () => {
+    const callbackProgramId = anchor.workspace.FlashSwapReceiver.programId as PublicKey;
+    const flashSwapAccounts = {
+      globalConfig: globalConfigPda,
+      pool: poolPDA,
+      userToken0: userTokenAccount0,
+      userToken1: userTokenAccount1,
+      poolToken0: tokenVault0,
+      poolToken1: tokenVault1,
+      payer: payer.publicKey,
+      tokenMint0,
+      tokenMint1,
+      tokenProgram0: TOKEN_PROGRAM_ID,
+      tokenProgram1: TOKEN_PROGRAM_ID,
+      callbackProgram: callbackProgramId,
+      ...eventCpiAccounts,
+    };
+    // Accounts of the receiver's flash_swap_callback, forwarded by the pool
+    const callbackAccounts = [
+      { pubkey: payer.publicKey, isWritable: false, isSigner: true },
+      { pubkey: userTokenAccount0, isWritable: true, isSigner: false },
+      { pubkey: userTokenAccount1, isWritable: true, isSigner: false },
+      { pubkey: tokenVault0, isWritable: true, isSigner: false },
+      { pubkey: tokenVault1, isWritable: true, isSigner: false },
+      { pubkey: tokenMint0, isWritable: false, isSigner: false },
+      { pubkey: tokenMint1, isWritable: false, isSigner: false },
+      { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+      { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+    ];
+    const shortfall = (amount: number) => {
+      const data = Buffer.alloc(8);
+      data.writeBigUInt64LE(BigInt(amount));
+      return data;
+    };
+
+    const amountIn = 50;
+    const expectedOut = amountIn - Math.floor(amountIn / 1000);
+
+    // Paying one token short unwinds the whole swap, including the output already sent
+    await expectAnchorError(
+      program.methods
+        .flashSwap(new BN(amountIn), true, new BN(0), shortfall(1))
+        .accountsStrict(flashSwapAccounts)
+        .remainingAccounts(callbackAccounts)
+        .rpc(),
+      "FlashSwapUnderpaid"
+    );
+
+    await expectAnchorError(
+      program.methods
+        .flashSwap(new BN(amountIn), true, new BN(0), Buffer.alloc(0))
+        .accountsStrict({ ...flashSwapAccounts, callbackProgram: program.programId })
+        .remainingAccounts(callbackAccounts)
+        .rpc(),
+      "InvalidCallbackProgram"
+    );
+
+    const userToken0Before = await getAccount(provider.connection, userTokenAccount0);
+    const userToken1Before = await getAccount(provider.connection, userTokenAccount1);
+    const poolBefore = await program.account.pool.fetch(poolPDA);
+
+    await program.methods
+      .flashSwap(new BN(amountIn), true, new BN(expectedOut), Buffer.alloc(0))
+      .accountsStrict(flashSwapAccounts)
+      .remainingAccounts(callbackAccounts)
+      .rpc()
+
+    const userToken0After = await getAccount(provider.connection, userTokenAccount0);
+    const userToken1After = await getAccount(provider.connection, userTokenAccount1);
+    const poolAfter = await program.account.pool.fetch(poolPDA);
+
+    expect(Number(userToken0Before.amount) - Number(userToken0After.amount)).toEqual(amountIn);
+    expect(Number(userToken1After.amount) - Number(userToken1Before.amount)).toEqual(expectedOut);
+    expect(poolAfter.sqrtPriceX96.lt(poolBefore.sqrtPriceX96)).toBe(true);
+  })
+
+  it('Rejects swaps while swaps are paused', async () => {
+    const PAUSE_SWAP = 1 << 0;
+
+    await program.methods
+      .setPauseFlags(PAUSE_SWAP)
+      .accountsStrict({
+        pauser: payer.publicKey,
+        globalConfig: globalConfigPda,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    try {
+      await program.methods
+        .swap(new BN(50), true, new BN(0), null, null)
+        .accountsStrict({
+          globalConfig: globalConfigPda,
+          pool: poolPDA,
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          payer: payer.publicKey,
+          wsolAccount: null,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+          ...eventCpiAccounts,
+        })
+        .rpc()
+
+      expect(true).toBe(false);
+    } catch (error) {
+      expect(error.error?.errorCode?.code).toEqual("SwapPaused");
+    }
+
+    await program.methods
+      .setPauseFlags(0)
+      .accountsStrict({
+        pauser: payer.publicKey,
+        globalConfig: globalConfigPda,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    const globalConfig = await program.account.globalConfig.fetch(globalConfigPda);
+    expect(globalConfig.pauseFlags).toEqual(0);
+  })
+
+  it('Rejects collecting protocol fees while withdrawals are paused', async () => {
+    const PAUSE_WITHDRAW = 1 << 2;
+
+    await program.methods
+      .setPauseFlags(PAUSE_WITHDRAW)
+      .accountsStrict({
+        pauser: payer.publicKey,
+        globalConfig: globalConfigPda,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    await expectAnchorError(
+      program.methods
+        .collectProtocolFees()
+        .accountsStrict({
+          feeManager: payer.publicKey,
+          globalConfig: globalConfigPda,
+          pool: poolPDA,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          recipientToken0: userTokenAccount0,
+          recipientToken1: userTokenAccount1,
+          tokenMint0,
+          tokenMint1,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventCpiAccounts,
+        })
+        .rpc(),
+      "WithdrawPaused"
+    );
+
+    await program.methods
+      .setPauseFlags(0)
+      .accountsStrict({
+        pauser: payer.publicKey,
+        globalConfig: globalConfigPda,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+  })
+
+  it('Transfers authority in two steps', async () => {
+    const newAuthority = Keypair.generate();
+
+    await program.methods
+      .transferAuthority(newAuthority.publicKey)
+      .accountsStrict({
+        authority: payer.publicKey,
+        globalConfig: globalConfigPda,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    let globalConfig = await program.account.globalConfig.fetch(globalConfigPda);
+    expect(globalConfig.authority.toString()).toEqual(payer.publicKey.toString());
+    expect(globalConfig.pendingAuthority.toString()).toEqual(newAuthority.publicKey.toString());
+
+    await program.methods
+      .acceptAuthority()
+      .accountsStrict({
+        pendingAuthority: newAuthority.publicKey,
+        globalConfig: globalConfigPda,
+        ...eventCpiAccounts,
+      })
+      .signers([newAuthority])
+      .rpc()
+
+    globalConfig = await program.account.globalConfig.fetch(globalConfigPda);
+    expect(globalConfig.authority.toString()).toEqual(newAuthority.publicKey.toString());
+
+    // Hand authority back so the remaining admin calls keep using the provider wallet
+    await program.methods
+      .transferAuthority(payer.publicKey)
+      .accountsStrict({
+        authority: newAuthority.publicKey,
+        globalConfig: globalConfigPda,
+        ...eventCpiAccounts,
+      })
+      .signers([newAuthority])
+      .rpc()
+
+    await program.methods
+      .acceptAuthority()
+      .accountsStrict({
+        pendingAuthority: payer.publicKey,
+        globalConfig: globalConfigPda,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    globalConfig = await program.account.globalConfig.fetch(globalConfigPda);
+    expect(globalConfig.authority.toString()).toEqual(payer.publicKey.toString());
+  })
+
+  it('Applies a queued fee rate change only after the timelock', async () => {
+    await program.methods
+      .queueFeeRateChange(3000)
+      .accountsStrict({
+        feeManager: payer.publicKey,
+        globalConfig: globalConfigPda,
+        pool: poolPDA,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    let poolAccount = await program.account.pool.fetch(poolPDA);
+    expect(poolAccount.pendingChange.change).toEqual({ feeRate: { 0: 3000 } });
+    expect(poolAccount.feeRate).toEqual(1000);
+
+    try {
+      await program.methods
+        .executePendingChange()
+        .accountsStrict({
+          globalConfig: globalConfigPda,
+          pool: poolPDA,
+          ...eventCpiAccounts,
+        })
+        .rpc()
+
+      expect(true).toBe(false);
+    } catch (error) {
+      expect(error.error?.errorCode?.code).toEqual("PendingChangeNotReady");
+    }
+
+    await new Promise((resolve) => setTimeout(resolve, (TIMELOCK_DELAY + 2) * 1000));
+
+    await program.methods
+      .executePendingChange()
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        pool: poolPDA,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    poolAccount = await program.account.pool.fetch(poolPDA);
+    expect(poolAccount.feeRate).toEqual(3000);
+    expect(poolAccount.pendingChange).toBeNull();
+  })
+
+  it('Cancels a queued protocol fee change', async () => {
+    await program.methods
+      .queueProtocolFeeRateChange(1000)
+      .accountsStrict({
+        feeManager: payer.publicKey,
+        globalConfig: globalConfigPda,
+        pool: poolPDA,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    await program.methods
+      .cancelPendingChange()
+      .accountsStrict({
+        admin: payer.publicKey,
+        globalConfig: globalConfigPda,
+        pool: poolPDA,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    const poolAccount = await program.account.pool.fetch(poolPDA);
+    expect(poolAccount.pendingChange).toBeNull();
+    expect(poolAccount.protocolFeeRate).toEqual(0);
+  })
+
+  it('Rejects fee changes from accounts without the fee manager role', async () => {
+    const outsider = Keypair.generate();
+    try {
+      await program.methods
+        .queueFeeRateChange(1000)
+        .accountsStrict({
+          feeManager: outsider.publicKey,
+          globalConfig: globalConfigPda,
+          pool: poolPDA,
+          ...eventCpiAccounts,
+        })
+        .signers([outsider])
+        .rpc()
+
+      expect(true).toBe(false);
+    } catch (error) {
+      expect(error.error?.errorCode?.code).toEqual("Unauthorized");
+    }
+  })
+
+  it('Rejects rewards funded into a substituted pool vault', async () => {
+    await expectAnchorError(
+      program.methods
+        .fundRewards(new BN(1000), new BN(0))
+        .accountsStrict({
+          rewardFunder: payer.publicKey,
+          globalConfig: globalConfigPda,
+          pool: poolPDA,
+          funderToken0: userTokenAccount0,
+          funderToken1: userTokenAccount1,
+          poolToken0: userTokenAccount0,
+          poolToken1: tokenVault1,
+          tokenMint0,
+          tokenMint1,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventCpiAccounts,
+        })
+        .rpc(),
+      "InvalidVault"
+    );
+  })
+
+  it('Funds rewards into fee growth', async () => {
+    const poolBefore = await program.account.pool.fetch(poolPDA);
+
+    await program.methods
+      .fundRewards(new BN(1000), new BN(0))
+      .accountsStrict({
+        rewardFunder: payer.publicKey,
+        globalConfig: globalConfigPda,
+        pool: poolPDA,
+        funderToken0: userTokenAccount0,
+        funderToken1: userTokenAccount1,
+        poolToken0: tokenVault0,
+        poolToken1: tokenVault1,
+        tokenMint0: tokenMint0,
+        tokenMint1: tokenMint1,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    const poolAfter = await program.account.pool.fetch(poolPDA);
+    expect(poolAfter.feeGrowthGlobal0X64.gt(poolBefore.feeGrowthGlobal0X64)).toBe(true);
+    expect(poolAfter.feeGrowthGlobal1X64.toString()).toEqual(poolBefore.feeGrowthGlobal1X64.toString());
+  })
+
+  it('Revokes a role', async () => {
+    await program.methods
+      .revokeRole({ rewardFunder: {} } as any)
+      .accountsStrict({
+        authority: payer.publicKey,
+        globalConfig: globalConfigPda,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    const globalConfig = await program.account.globalConfig.fetch(globalConfigPda);
+    expect(globalConfig.rewardFunder.toString()).toEqual(PublicKey.default.toString());
+  })
+
+  it('Emergency withdraw-only mode stops swaps but lets LPs exit', async () => {
+    const bundleIndex = 7;
+    const bundledAccounts = {
+      globalConfig: globalConfigPda,
+      payer: payer.publicKey,
+      pool: poolPDA,
+      lowerTickArray: lowerTickArrayPda,
+      upperTickArray: upperTickArrayPda,
+      positionBundle: positionBundlePda,
+      userToken0: userTokenAccount0,
+      userToken1: userTokenAccount1,
+      poolToken0: tokenVault0,
+      poolToken1: tokenVault1,
+      tokenMint0: tokenMint0,
+      tokenMint1: tokenMint1,
+      tokenProgram0: TOKEN_PROGRAM_ID,
+      tokenProgram1: TOKEN_PROGRAM_ID,
+      ...eventCpiAccounts,
+    };
+    await program.methods
+      .openBundledPosition(
+        bundleIndex,
+        LOWER_TICK,
+        UPPER_TICK,
+        LIQUIDITY_AMOUNT,
+        getTickArrayStartIndex(LOWER_TICK, TICK_SPACING),
+        getTickArrayStartIndex(UPPER_TICK, TICK_SPACING),
+        U64_MAX,
+        U64_MAX
+      )
+      .accountsStrict({ ...bundledAccounts, systemProgram: SystemProgram.programId, rent: SYSVAR_RENT_PUBKEY })
+      .rpc()
+
+    const statusTx = await program.methods
+      .setPoolStatus({ withdrawOnly: {} } as any)
+      .accountsStrict({
+        pauser: payer.publicKey,
+        globalConfig: globalConfigPda,
+        pool: poolPDA,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+    const statusEvents = await getEvents(statusTx);
+    expect(statusEvents.map((event) => event.name)).toEqual(["poolStatusChanged"]);
+    expect(statusEvents[0].data.status).toEqual({ withdrawOnly: {} });
+
+    try {
+      await program.methods
+        .swap(new BN(50), true, new BN(0), null, null)
+        .accountsStrict({
+          globalConfig: globalConfigPda,
+          pool: poolPDA,
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          payer: payer.publicKey,
+          wsolAccount: null,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+          ...eventCpiAccounts,
+        })
+        .rpc()
+
+      expect(true).toBe(false);
+    } catch (error) {
+      expect(error.error?.errorCode?.code).toEqual("PoolWithdrawOnly");
+    }
+
+    const emergencyWithdrawAccounts = {
+      globalConfig: globalConfigPda,
+      payer: payer.publicKey,
+      pool: poolPDA,
+      lowerTickArray: lowerTickArrayPda,
+      upperTickArray: upperTickArrayPda,
+      position: positionPda,
+      userToken0: userTokenAccount0,
+      userToken1: userTokenAccount1,
+      poolToken0: tokenVault0,
+      poolToken1: tokenVault1,
+      tokenMint0: tokenMint0,
+      tokenMint1: tokenMint1,
+      tokenProgram0: TOKEN_PROGRAM_ID,
+      tokenProgram1: TOKEN_PROGRAM_ID,
+      ...eventCpiAccounts,
+    };
+
+    // The payout is sized from the vault balances, so a substituted vault must not be accepted
+    await expectAnchorError(
+      program.methods
+        .emergencyWithdraw()
+        .accountsStrict({ ...emergencyWithdrawAccounts, poolToken0: userTokenAccount0 })
+        .rpc(),
+      "InvalidVault"
+    );
+
+    const poolBefore = await program.account.pool.fetch(poolPDA);
+    const positionBefore = await program.account.position.fetch(positionPda);
+    const vault0Before = await getAccount(provider.connection, tokenVault0);
+    const userToken0Before = await getAccount(provider.connection, userTokenAccount0);
+    const lowerGrossBefore = await fetchTickLiquidityGross(lowerTickArrayPda, LOWER_TICK);
+    const upperGrossBefore = await fetchTickLiquidityGross(upperTickArrayPda, UPPER_TICK);
+
+    await program.methods
+      .emergencyWithdraw()
+      .accountsStrict(emergencyWithdrawAccounts)
+      .rpc()
+
+    // Pro rata share of the vault, net of protocol fees
+    const expectedAmount0 = new BN(vault0Before.amount.toString())
+      .sub(poolBefore.protocolFeesOwed0)
+      .mul(positionBefore.liquidity)
+      .div(poolBefore.globalLiquidity);
+
+    const userToken0After = await getAccount(provider.connection, userTokenAccount0);
+    expect(
+      new BN(userToken0After.amount.toString()).sub(new BN(userToken0Before.amount.toString())).toString()
+    ).toEqual(expectedAmount0.toString());
+
+    const positionAfter = await program.account.position.fetch(positionPda);
+    expect(positionAfter.liquidity.toNumber()).toEqual(0);
+
+    const poolAfter = await program.account.pool.fetch(poolPDA);
+    expect(poolAfter.globalLiquidity.toString()).toEqual(
+      poolBefore.globalLiquidity.sub(positionBefore.liquidity).toString()
+    );
+    // The withdrawn liquidity leaves its ticks too
+    expect((await fetchTickLiquidityGross(lowerTickArrayPda, LOWER_TICK)).toString()).toEqual(
+      lowerGrossBefore.sub(positionBefore.liquidity).toString()
+    );
+    expect((await fetchTickLiquidityGross(upperTickArrayPda, UPPER_TICK)).toString()).toEqual(
+      upperGrossBefore.sub(positionBefore.liquidity).toString()
+    );
+
+    // Bundled positions exit the same way
+    await expectAnchorError(
+      program.methods
+        .emergencyWithdrawBundled(bundleIndex)
+        .accountsStrict({ ...bundledAccounts, poolToken1: userTokenAccount1 })
+        .rpc(),
+      "InvalidVault"
+    );
+
+    // Swapped tick arrays make the tick update fail; the exit must still go through and
+    // leave the ticks untouched
+    const lowerGrossBeforeBundled = await fetchTickLiquidityGross(lowerTickArrayPda, LOWER_TICK);
+    const upperGrossBeforeBundled = await fetchTickLiquidityGross(upperTickArrayPda, UPPER_TICK);
+    const userToken1BeforeBundled = await getAccount(provider.connection, userTokenAccount1);
+    await program.methods
+      .emergencyWithdrawBundled(bundleIndex)
+      .accountsStrict({ ...bundledAccounts, lowerTickArray: upperTickArrayPda, upperTickArray: lowerTickArrayPda })
+      .rpc()
+
+    const bundleAccount = await program.account.positionBundle.fetch(positionBundlePda);
+    expect(bundleAccount.positionBitmap.toNumber() & (1 << bundleIndex)).toEqual(0);
+    const poolAfterBundled = await program.account.pool.fetch(poolPDA);
+    expect(poolAfterBundled.globalLiquidity.toString()).toEqual(
+      poolAfter.globalLiquidity.sub(LIQUIDITY_AMOUNT).toString()
+    );
+    const userToken1AfterBundled = await getAccount(provider.connection, userTokenAccount1);
+    expect(userToken1AfterBundled.amount > userToken1BeforeBundled.amount).toBe(true);
+    expect((await fetchTickLiquidityGross(lowerTickArrayPda, LOWER_TICK)).toString()).toEqual(
+      lowerGrossBeforeBundled.toString()
+    );
+    expect((await fetchTickLiquidityGross(upperTickArrayPda, UPPER_TICK)).toString()).toEqual(
+      upperGrossBeforeBundled.toString()
+    );
+  })
+
+  it('Accounts for transfer fees on deposits and swaps', async () => {
+    const TRANSFER_FEE_BPS = 100;
+    const MAX_TRANSFER_FEE = BigInt(1_000_000_000);
+    const transferFee = (amount: number) =>
+      Math.min(Math.ceil((amount * TRANSFER_FEE_BPS) / 10_000), Number(MAX_TRANSFER_FEE));
+
+    const feeMint = await createToken2022Mint(
+      [ExtensionType.TransferFeeConfig],
+      (mint) => [
+        createInitializeTransferFeeConfigInstruction(
+          mint,
+          payer.publicKey,
+          payer.publicKey,
+          TRANSFER_FEE_BPS,
+          MAX_TRANSFER_FEE,
+          TOKEN_2022_PROGRAM_ID
+        ),
+      ]
+    );
+    const feeUserAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      payer.payer,
+      feeMint,
+      payer.publicKey,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await mintTo(
+      provider.connection,
+      payer.payer,
+      feeMint,
+      feeUserAccount,
+      payer.publicKey,
+      1000000000,
+      [],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    const [side0, side1] = sortMintsWithPrograms(
+      [tokenMint0, TOKEN_PROGRAM_ID],
+      [feeMint, TOKEN_2022_PROGRAM_ID]
+    );
+    const feeIsToken0 = side0[0].equals(feeMint);
+    const { pool, vault0, vault1 } = await initializePoolFor(side0, side1);
+    const [userToken0, userToken1] = feeIsToken0
+      ? [feeUserAccount, userTokenAccount0]
+      : [userTokenAccount0, feeUserAccount];
+    const feeVault = feeIsToken0 ? vault0 : vault1;
+
+    const { lowerTickArrayStartIndex, upperTickArrayStartIndex, lowerTickArray, upperTickArray, position } =
+      derivePositionAccounts(pool);
+
+    const tokenAccounts = {
+      userToken0,
+      userToken1,
+      poolToken0: vault0,
+      poolToken1: vault1,
+      payer: payer.publicKey,
+      wsolAccount: null,
+      tokenMint0: side0[0],
+      tokenMint1: side1[0],
+      systemProgram: SystemProgram.programId,
+      tokenProgram0: side0[1],
+      tokenProgram1: side1[1],
+      rent: SYSVAR_RENT_PUBKEY,
+    };
+
+    await program.methods
+      .openPosition(
+        payer.publicKey,
+        LOWER_TICK,
+        UPPER_TICK,
+        LIQUIDITY_AMOUNT,
+        lowerTickArrayStartIndex,
+        upperTickArrayStartIndex,
+        U64_MAX,
+        U64_MAX,
+        null,
+        null
+      )
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        pool,
+        lowerTickArray,
+        upperTickArray,
+        position,
+        ...tokenAccounts,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    // The vault receives the full deposit even though the user paid the transfer fee on top
+    const depositAmount = LIQUIDITY_AMOUNT.toNumber() / 1000;
+    const feeVaultAfterDeposit = await getAccount(provider.connection, feeVault, undefined, TOKEN_2022_PROGRAM_ID);
+    expect(Number(feeVaultAfterDeposit.amount)).toEqual(depositAmount);
+
+    const swapAccounts = {
+      globalConfig: globalConfigPda,
+      pool,
+      ...tokenAccounts,
+      ...eventCpiAccounts,
+    };
+
+    // Swapping into the fee mint delivers exactly the quoted output
+    const amountIn = 50;
+    const quotedOut = amountIn - Math.floor(amountIn / 1000);
+    const feeUserBefore = await getAccount(provider.connection, feeUserAccount, undefined, TOKEN_2022_PROGRAM_ID);
+    await program.methods
+      .swap(new BN(amountIn), !feeIsToken0, new BN(quotedOut), null, null)
+      .accountsStrict(swapAccounts)
+      .rpc()
+    const feeUserAfter = await getAccount(provider.connection, feeUserAccount, undefined, TOKEN_2022_PROGRAM_ID);
+    expect(Number(feeUserAfter.amount) - Number(feeUserBefore.amount)).toEqual(quotedOut);
+
+    // Swapping out of the fee mint prices the swap on what reaches the vault
+    const netIn = amountIn - transferFee(amountIn);
+    const expectedOut = netIn - Math.floor(netIn / 1000);
+    const quote = await program.methods
+      .quoteSwap(new BN(amountIn), feeIsToken0)
+      .accountsStrict({ pool, tokenMint0: side0[0], tokenMint1: side1[0] })
+      .remainingAccounts(await getCrossedTickArrays(pool, feeIsToken0))
+      .view()
+    expect(quote.amountOut.toNumber()).toEqual(expectedOut);
+    const legacyUserBefore = await getAccount(provider.connection, userTokenAccount0);
+    await program.methods
+      .swap(new BN(amountIn), feeIsToken0, new BN(expectedOut), null, null)
+      .accountsStrict(swapAccounts)
+      .rpc()
+    const legacyUserAfter = await getAccount(provider.connection, userTokenAccount0);
+    expect(Number(legacyUserAfter.amount) - Number(legacyUserBefore.amount)).toEqual(expectedOut);
+  })
+
+  it('Charges the full swap input when the transfer fee is at its cap', async () => {
+    const TRANSFER_FEE_BPS = 100;
+    const MAX_TRANSFER_FEE = BigInt(2);
+
+    const cappedMint = await createToken2022Mint(
+      [ExtensionType.TransferFeeConfig],
+      (mint) => [
+        createInitializeTransferFeeConfigInstruction(
+          mint,
+          payer.publicKey,
+          payer.publicKey,
+          TRANSFER_FEE_BPS,
+          MAX_TRANSFER_FEE,
+          TOKEN_2022_PROGRAM_ID
+        ),
+      ]
+    );
+    const cappedUserAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      payer.payer,
+      cappedMint,
+      payer.publicKey,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await mintTo(
+      provider.connection,
+      payer.payer,
+      cappedMint,
+      cappedUserAccount,
+      payer.publicKey,
+      1000000000,
+      [],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    const [side0, side1] = sortMintsWithPrograms(
+      [tokenMint0, TOKEN_PROGRAM_ID],
+      [cappedMint, TOKEN_2022_PROGRAM_ID]
+    );
+    const cappedIsToken0 = side0[0].equals(cappedMint);
+    const { pool, vault0, vault1 } = await initializePoolFor(side0, side1);
+    const [userToken0, userToken1] = cappedIsToken0
+      ? [cappedUserAccount, userTokenAccount0]
+      : [userTokenAccount0, cappedUserAccount];
+    const cappedVault = cappedIsToken0 ? vault0 : vault1;
+
+    const { lowerTickArrayStartIndex, upperTickArrayStartIndex, lowerTickArray, upperTickArray, position } =
+      derivePositionAccounts(pool);
+
+    const tokenAccounts = {
+      userToken0,
+      userToken1,
+      poolToken0: vault0,
+      poolToken1: vault1,
+      payer: payer.publicKey,
+      wsolAccount: null,
+      tokenMint0: side0[0],
+      tokenMint1: side1[0],
+      systemProgram: SystemProgram.programId,
+      tokenProgram0: side0[1],
+      tokenProgram1: side1[1],
+      rent: SYSVAR_RENT_PUBKEY,
+    };
+
+    await program.methods
+      .openPosition(
+        payer.publicKey,
+        LOWER_TICK,
+        UPPER_TICK,
+        LIQUIDITY_AMOUNT,
+        lowerTickArrayStartIndex,
+        upperTickArrayStartIndex,
+        U64_MAX,
+        U64_MAX,
+        null,
+        null
+      )
+      .accountsStrict({
+        globalConfig: globalConfigPda,
+        pool,
+        lowerTickArray,
+        upperTickArray,
+        position,
+        ...tokenAccounts,
+        ...eventCpiAccounts,
+      })
+      .rpc()
+
+    // The capped fee of 2 leaves 99 of 101, which 100 would also deliver; the user still pays 101
+    const amountIn = 101;
+    const netIn = amountIn - Number(MAX_TRANSFER_FEE);
+    const quote = await program.methods
+      .quoteSwap(new BN(amountIn), cappedIsToken0)
+      .accountsStrict({ pool, tokenMint0: side0[0], tokenMint1: side1[0] })
+      .remainingAccounts(await getCrossedTickArrays(pool, cappedIsToken0))
+      .view()
+    expect(quote.amountIn.toNumber()).toEqual(amountIn);
+
+    const userBefore = await getAccount(provider.connection, cappedUserAccount, undefined, TOKEN_2022_PROGRAM_ID);
+    const vaultBefore = await getAccount(provider.connection, cappedVault, undefined, TOKEN_2022_PROGRAM_ID);
+    await program.methods
+      .swap(new BN(amountIn), cappedIsToken0, quote.amountOut, null, null)
+      .accountsStrict({ globalConfig: globalConfigPda, pool, ...tokenAccounts, ...eventCpiAccounts })
+      .rpc()
+    const userAfter = await getAccount(provider.connection, cappedUserAccount, undefined, TOKEN_2022_PROGRAM_ID);
+    const vaultAfter = await getAccount(provider.connection, cappedVault, undefined, TOKEN_2022_PROGRAM_ID);
+    expect(Number(userBefore.amount) - Number(userAfter.amount)).toEqual(amountIn);
+    expect(Number(vaultAfter.amount) - Number(vaultBefore.amount)).toEqual(netIn);
+  })
+
+  it('Runs the transfer hook of a hooked mint on deposits and swaps', async () => {
+    const hookProgram = anchor.workspace.TransferHookCounter as Program<TransferHookCounter>;
+    const hookMint = await createToken2022Mint(
+      [ExtensionType.TransferHook],
+      (mint) => [
+        createInitializeTransferHookInstruction(mint, payer.publicKey, hookProgram.programId, TOKEN_2022_PROGRAM_ID),
+      ]
+    );
+    const [extraAccountMetaList] = PublicKey.findProgramAddressSync(
+      [Buffer.from("extra-account-metas"), hookMint.toBuffer()],
+      hookProgram.programId
+    );
+    const [counter] = PublicKey.findProgramAddressSync(
+      [Buffer.from("counter"), hookMint.toBuffer()],
+      hookProgram.programId
+    );
+    await hookProgram.methods
+      .initializeExtraAccountMetaList()
+      .accountsStrict({
+        payer: payer.publicKey,
+        extraAccountMetaList,
+        counter,
+        mint: hookMint,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc()
+
+    const hookUserAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      payer.payer,
+      hookMint,
+      payer.publicKey,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await mintTo(
+      provider.connection,
+      payer.payer,
+      hookMint,
+      hookUserAccount,
+      payer.publicKey,
+      1000000000,
+      [],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    const [side0, side1] = sortMintsWithPrograms(
+      [tokenMint0, TOKEN_PROGRAM_ID],
+      [hookMint, TOKEN_2022_PROGRAM_ID]
+    );
+    const hookIsToken0 = side0[0].equals(hookMint);
+    const { pool, vault0, vault1 } = await initializePoolFor(side0, side1);
+    const { lowerTickArrayStartIndex, upperTickArrayStartIndex, lowerTickArray, upperTickArray, position } =
+      derivePositionAccounts(pool);
+
+    const tokenAccounts = {
+      userToken0: hookIsToken0 ? hookUserAccount : userTokenAccount0,
+      userToken1: hookIsToken0 ? userTokenAccount0 : hookUserAccount,
+      poolToken0: vault0,
+      poolToken1: vault1,
+      payer: payer.publicKey,
+      wsolAccount: null,
+      tokenMint0: side0[0],
+      tokenMint1: side1[0],
+      systemProgram: SystemProgram.programId,
+      tokenProgram0: side0[1],
+      tokenProgram1: side1[1],
+      rent: SYSVAR_RENT_PUBKEY,
+      ...eventCpiAccounts,
+    };
+    // The token program resolves the counter from the extra account metas, so the pool has to
+    // forward the hook program, its meta list and the counter.
+    const hookAccounts = [
+      { pubkey: hookProgram.programId, isSigner: false, isWritable: false },
+      { pubkey: extraAccountMetaList, isSigner: false, isWritable: false },
+      { pubkey: counter, isSigner: false, isWritable: true },
+    ];
+
+    await program.methods
+      .openPosition(
+        payer.publicKey,
+        LOWER_TICK,
+        UPPER_TICK,
+        LIQUIDITY_AMOUNT,
+        lowerTickArrayStartIndex,
+        upperTickArrayStartIndex,
+        U64_MAX,
+        U64_MAX,
+        null,
+        null
+      )
+      .accountsStrict({ ...tokenAccounts, globalConfig: globalConfigPda, pool, lowerTickArray, upperTickArray, position })
+      .remainingAccounts(hookAccounts)
+      .rpc()
+
+    const depositAmount = LIQUIDITY_AMOUNT.toNumber() / 1000;
+    let counterAccount = await hookProgram.account.transferCounter.fetch(counter);
+    expect(counterAccount.transfers.toNumber()).toEqual(1);
+    expect(counterAccount.amount.toNumber()).toEqual(depositAmount);
+
+    const swapAccounts = { ...tokenAccounts, globalConfig: globalConfigPda, pool };
+    const amountIn = 50;
+    const quotedOut = amountIn - Math.floor(amountIn / 1000);
+
+    // Into the hooked mint: the pool-signed payout from the vault runs the hook
+    await program.methods
+      .swap(new BN(amountIn), !hookIsToken0, new BN(quotedOut), null, null)
+      .accountsStrict(swapAccounts)
+      .remainingAccounts(hookAccounts)
+      .rpc()
+    counterAccount = await hookProgram.account.transferCounter.fetch(counter);
+    expect(counterAccount.transfers.toNumber()).toEqual(2);
+    expect(counterAccount.amount.toNumber()).toEqual(depositAmount + quotedOut);
+
+    // Out of the hooked mint: the user's deposit into the vault runs it too
+    await program.methods
+      .swap(new BN(amountIn), hookIsToken0, new BN(0), null, null)
+      .accountsStrict(swapAccounts)
+      .remainingAccounts(hookAccounts)
+      .rpc()
+    counterAccount = await hookProgram.account.transferCounter.fetch(counter);
+    expect(counterAccount.transfers.toNumber()).toEqual(3);
+    expect(counterAccount.amount.toNumber()).toEqual(depositAmount + quotedOut + amountIn);
+
+    // Without the hook's accounts the token program can't run it, so the transfer fails
+    await expect(
+      program.methods
+        .swap(new BN(amountIn), hookIsToken0, new BN(0), null, null)
+        .accountsStrict(swapAccounts)
+        .rpc()
+    ).rejects.toThrow();
+  })
+
+  it('Deposits and swaps native SOL without a wSOL account', async () => {
+    const [side0, side1] = sortMintsWithPrograms(
+      [NATIVE_MINT, TOKEN_PROGRAM_ID],
+      [tokenMint0, TOKEN_PROGRAM_ID]
+    );
+    const solIsToken0 = side0[0].equals(NATIVE_MINT);
+    const { pool, vault0, vault1 } = await initializePoolFor(side0, side1);
+    const { lowerTickArrayStartIndex, upperTickArrayStartIndex, lowerTickArray, upperTickArray, position } =
+      derivePositionAccounts(pool);
+    const [wsolAccount] = PublicKey.findProgramAddressSync(
+      [Buffer.from("wsol"), payer.publicKey.toBuffer()],
+      program.programId
+    );
+    const solVault = solIsToken0 ? vault0 : vault1;
+
+    const accounts = {
+      globalConfig: globalConfigPda,
+      pool,
+      userToken0: solIsToken0 ? null : userTokenAccount0,
+      userToken1: solIsToken0 ? userTokenAccount0 : null,
+      poolToken0: vault0,
+      poolToken1: vault1,
+      payer: payer.publicKey,
+      wsolAccount,
+      tokenMint0: side0[0],
+      tokenMint1: side1[0],
+      systemProgram: SystemProgram.programId,
+      tokenProgram0: side0[1],
+      tokenProgram1: side1[1],
+      rent: SYSVAR_RENT_PUBKEY,
+      ...eventCpiAccounts,
+    };
+
+    await program.methods
+      .openPosition(
+        payer.publicKey,
+        LOWER_TICK,
+        UPPER_TICK,
+        LIQUIDITY_AMOUNT,
+        lowerTickArrayStartIndex,
+        upperTickArrayStartIndex,
+        U64_MAX,
+        U64_MAX,
+        null,
+        null
+      )
+      .accountsStrict({ ...accounts, lowerTickArray, upperTickArray, position })
+      .rpc()
+
+    const depositAmount = LIQUIDITY_AMOUNT.toNumber() / 1000;
+    const solVaultAfterDeposit = await getAccount(provider.connection, solVault);
+    expect(Number(solVaultAfterDeposit.amount)).toEqual(depositAmount);
+    expect(await provider.connection.getAccountInfo(wsolAccount)).toBeNull();
+
+    // Token in, SOL out: the output is unwrapped straight to the payer
+    const amountIn = 50;
+    const quotedOut = amountIn - Math.floor(amountIn / 1000);
+    await program.methods
+      .swap(new BN(amountIn), !solIsToken0, new BN(quotedOut), null, null)
+      .accountsStrict(accounts)
+      .rpc()
+
+    const solVaultAfterSell = await getAccount(provider.connection, solVault);
+    expect(Number(solVaultAfterSell.amount)).toEqual(depositAmount - quotedOut);
+    expect(await provider.connection.getAccountInfo(wsolAccount)).toBeNull();
+
+    // SOL in, token out: the input is wrapped from the payer's lamports
+    const userTokenBefore = await getAccount(provider.connection, userTokenAccount0);
+    await program.methods
+      .swap(new BN(amountIn), solIsToken0, new BN(quotedOut), null, null)
+      .accountsStrict(accounts)
+      .rpc()
+
+    const userTokenAfter = await getAccount(provider.connection, userTokenAccount0);
+    expect(Number(userTokenAfter.amount) - Number(userTokenBefore.amount)).toEqual(quotedOut);
+    const solVaultAfterBuy = await getAccount(provider.connection, solVault);
+    expect(Number(solVaultAfterBuy.amount)).toEqual(depositAmount - quotedOut + amountIn);
+    expect(await provider.connection.getAccountInfo(wsolAccount)).toBeNull();
+  })
+})
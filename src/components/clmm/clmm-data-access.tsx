@@ -1,8 +1,8 @@
 'use client'
 
-import { getClmmProgram, getClmmProgramId } from '@project/anchor'
+import { findEventAuthorityAddress, findPoolAddress, findTokenVaultAddress, getClmmProgram, getClmmProgramId } from '@project/anchor'
 import { useConnection } from '@solana/wallet-adapter-react'
-import { Cluster, Keypair, PublicKey, SystemProgram, SYSVAR_RENT_PUBKEY } from '@solana/web3.js'
+import { Cluster, PublicKey, SystemProgram, SYSVAR_RENT_PUBKEY } from '@solana/web3.js'
 import { useMutation, useQuery } from '@tanstack/react-query'
 import { useMemo } from 'react'
 import { useCluster } from '../cluster/cluster-data-access'
@@ -69,6 +69,8 @@ interface SwapArgs {
 }
 
 const TICKS_PER_ARRAY = 30;
+// No slippage bound on deposits until the UI exposes one
+const U64_MAX = new BN('18446744073709551615');
 
 function i32ToLeBytes(value: number): Buffer {
   const buffer = Buffer.allocUnsafe(4);
@@ -89,6 +91,11 @@ export function useClmmProgram() {
   const provider = useAnchorProvider()
   const programId = useMemo(() => getClmmProgramId(cluster.network as Cluster), [cluster])
   const program = useMemo(() => getClmmProgram(provider, programId), [provider, programId])
+  const [globalConfigPda] = useMemo(
+    () => PublicKey.findProgramAddressSync([Buffer.from("global_config")], programId),
+    [programId]
+  )
+  const eventAuthorityPda = useMemo(() => findEventAuthorityAddress(programId), [programId])
 
   const poolAccounts = useQuery({
     queryKey: ['pool', 'all', { cluster }],
@@ -112,28 +119,28 @@ export function useClmmProgram() {
 
   const initializePoolHandler = useMutation<string, Error, InitializePoolArgs>({
     mutationKey: ['pool', 'initialize', { cluster }],
-    mutationFn: async({ payerPubkey, TICK_SPACING, INITIAL_SQRT_PRICE, tokenMint0, tokenMint1 }) => {
-      const [poolPDA] = PublicKey.findProgramAddressSync(
-        [Buffer.from("pool"), tokenMint0.toBuffer(), tokenMint1.toBuffer(), i32ToLeBytes(TICK_SPACING)], 
-        program.programId
-      );
-      const tokenVault0Keypair = Keypair.generate();
-      const tokenVault1Keypair = Keypair.generate();
+    mutationFn: async({ payerPubkey, TICK_SPACING, INITIAL_SQRT_PRICE, tokenMint0: mintA, tokenMint1: mintB }) => {
+      // The program only accepts mints in canonical order; invert the price if the pair was flipped
+      const { pool: poolPDA, tokenMint0, tokenMint1, isInverted } = findPoolAddress(mintA, mintB, TICK_SPACING, program.programId);
+      const initialSqrtPrice = isInverted ? new BN(1).shln(192).div(INITIAL_SQRT_PRICE) : INITIAL_SQRT_PRICE;
 
       return await program.methods
-        .initializePool(TICK_SPACING, INITIAL_SQRT_PRICE)
+        .initializePool(TICK_SPACING, initialSqrtPrice)
         .accountsStrict({ 
+          globalConfig: globalConfigPda,
           payer: payerPubkey,
           pool: poolPDA,
           tokenMint0,
           tokenMint1,
-          tokenVault0: tokenVault0Keypair.publicKey,
-          tokenVault1: tokenVault1Keypair.publicKey,
+          tokenVault0: findTokenVaultAddress(poolPDA, tokenMint0, program.programId),
+          tokenVault1: findTokenVaultAddress(poolPDA, tokenMint1, program.programId),
           systemProgram: SystemProgram.programId,
-          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
           rent: SYSVAR_RENT_PUBKEY,
+          eventAuthority: eventAuthorityPda,
+          program: programId,
         })
-        .signers([tokenVault0Keypair, tokenVault1Keypair])
         .rpc()
       },
     onSuccess: async (signature) => {
@@ -201,9 +208,14 @@ export function useClmmProgram() {
           UPPER_TICK,
           LIQUIDITY_AMOUNT,
           lowerTickArrayStartIndex,
-          upperTickArrayStartIndex 
+          upperTickArrayStartIndex,
+          U64_MAX,
+          U64_MAX,
+          null,
+          null
         )
         .accountsStrict({ 
+          globalConfig: globalConfigPda,
           pool: poolPDA,
           lowerTickArray: lowerTickArrayPda,
           upperTickArray: upperTickArrayPda,
@@ -213,11 +225,15 @@ export function useClmmProgram() {
           poolToken0: tokenVault0Pubkey,
           poolToken1: tokenVault1Pubkey,
           payer: payerPubkey,
+          wsolAccount: null,
           tokenMint0: tokenMint0,
           tokenMint1: tokenMint1,
           systemProgram: SystemProgram.programId,
-          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
           rent: SYSVAR_RENT_PUBKEY,
+          eventAuthority: eventAuthorityPda,
+          program: programId,
         })
         .rpc()
       },
@@ -282,8 +298,9 @@ export function useClmmProgram() {
       );
 
       return await program.methods
-        .increaseLiquidity(liquidityAmount)
+        .increaseLiquidity(liquidityAmount, U64_MAX, U64_MAX)
         .accountsStrict({ 
+          globalConfig: globalConfigPda,
           pool: poolPDA,
           lowerTickArray: lowerTickArrayPda,
           upperTickArray: upperTickArrayPda,
@@ -295,9 +312,12 @@ export function useClmmProgram() {
           payer: payerPubkey,
           tokenMint0: tokenMint0,
           tokenMint1: tokenMint1,
-          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
           rent: SYSVAR_RENT_PUBKEY,
+          eventAuthority: eventAuthorityPda,
+          program: programId,
         })
         .rpc()
       },
@@ -362,8 +382,9 @@ export function useClmmProgram() {
       );
 
       return await program.methods
-        .decreaseLiquidity(liquidityToRemove)
+        .decreaseLiquidity(liquidityToRemove, new BN(0), new BN(0), null, null)
         .accountsStrict({ 
+          globalConfig: globalConfigPda,
           payer: payerPubkey,
           pool: poolPDA,
           lowerTickArray: lowerTickArrayPda,
@@ -376,8 +397,11 @@ export function useClmmProgram() {
           tokenMint0: tokenMint0,
           tokenMint1: tokenMint1,
           systemProgram: SystemProgram.programId,
-          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
           rent: SYSVAR_RENT_PUBKEY,
+          eventAuthority: eventAuthorityPda,
+          program: programId,
         })
         // .signers([keypair])
         .rpc()
@@ -412,17 +436,24 @@ export function useClmmProgram() {
       )
 
       return await program.methods
-        .swap(amountIn, swapToken0For1, amountOutMinimum)
+        .swap(amountIn, swapToken0For1, amountOutMinimum, null, null)
         .accountsStrict({ 
+          globalConfig: globalConfigPda,
           pool: poolPDA,
           userToken0: userTokenAccount0,
           userToken1: userTokenAccount1,
           poolToken0: tokenVault0Pubkey,
           poolToken1: tokenVault1Pubkey,
           payer: payerPubkey,
+          wsolAccount: null,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
           systemProgram: SystemProgram.programId,
-          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
           rent: SYSVAR_RENT_PUBKEY,
+          eventAuthority: eventAuthorityPda,
+          program: programId,
         })
         .rpc()
       },